@@ -1,4 +1,5 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use std::{future, pin};
 
 use axum::Json;
@@ -9,6 +10,10 @@ use axum::{
 use futures::TryStreamExt;
 use log::{debug, error, info};
 use rayhunter::analysis::analyzer::Harness;
+use rayhunter::analysis::config::AnalyzersConfig;
+use rayhunter::analysis::correlation::ThreatLevel;
+use rayhunter::analysis::known_cells::SharedKnownCellsDb;
+use rayhunter::analysis::metrics::HarnessMetrics;
 use rayhunter::diag::{DataType, MessagesContainer};
 use rayhunter::qmdl::QmdlReader;
 use serde::Serialize;
@@ -22,10 +27,17 @@ use crate::qmdl_store::RecordingStore;
 use crate::server::ServerState;
 use crate::dummy_analyzer::TestAnalyzer;
 
+// How often the known cells database is saved during a recording, so a
+// sudden loss of power doesn't lose everything learned since it started
+const KNOWN_CELLS_SAVE_INTERVAL: Duration = Duration::from_secs(600);
+
 pub struct AnalysisWriter {
     writer: BufWriter<File>,
     harness: Harness,
     bytes_written: usize,
+    threat_level: ThreatLevel,
+    known_cells: Option<SharedKnownCellsDb>,
+    known_cells_saved: Instant,
 }
 
 // We write our analysis results to a file immediately to minimize the amount of
@@ -35,16 +47,20 @@ pub struct AnalysisWriter {
 // lets us simply append new rows to the end without parsing the entire JSON
 // object beforehand.
 impl AnalysisWriter {
-    pub async fn new(file: File, enable_dummy_analyzer: bool) -> Result<Self, std::io::Error> {
-        let mut harness = Harness::new_with_all_analyzers();
+    pub async fn new(file: File, analyzers_config: &AnalyzersConfig, enable_dummy_analyzer: bool) -> Result<Self, std::io::Error> {
+        let mut harness = Harness::new_with_config(analyzers_config);
         if enable_dummy_analyzer {
             harness.add_analyzer(Box::new(TestAnalyzer { count: 0 }));
         }
+        harness.start_recording();
 
         let mut result = Self {
             writer: BufWriter::new(file),
             bytes_written: 0,
             harness,
+            threat_level: ThreatLevel::None,
+            known_cells: analyzers_config.known_cells.clone(),
+            known_cells_saved: Instant::now(),
         };
         let metadata = result.harness.get_metadata();
         result.write(&metadata).await?;
@@ -52,13 +68,38 @@ impl AnalysisWriter {
     }
 
     // Runs the analysis harness on the given container, serializing the results
-    // to the analysis file and returning the file's new length.
-    pub async fn analyze(&mut self, container: MessagesContainer) -> Result<(usize, bool), std::io::Error> {
+    // to the analysis file and returning the file's new length, as well as the
+    // recording's new threat level if this container changed it.
+    pub async fn analyze(&mut self, container: MessagesContainer) -> Result<(usize, Option<ThreatLevel>), std::io::Error> {
         let row = self.harness.analyze_qmdl_messages(container);
-        if !row.is_empty() {
+        let new_threat_level = (row.threat_level != self.threat_level).then_some(row.threat_level);
+        self.threat_level = row.threat_level;
+        // we always write rows which change the threat level, even if they're
+        // otherwise empty, so the report's last row has the current level
+        if !row.is_empty() || new_threat_level.is_some() {
             self.write(&row).await?;
         }
-        Ok((self.bytes_written, row.contains_warnings()))
+        if self.known_cells_saved.elapsed() >= KNOWN_CELLS_SAVE_INTERVAL {
+            self.save_known_cells().await;
+        }
+        Ok((self.bytes_written, new_threat_level))
+    }
+
+    pub fn get_metrics(&self) -> HarnessMetrics {
+        self.harness.get_metrics()
+    }
+
+    // Writing the database can take a while, so it's done on a blocking
+    // thread rather than holding up the runtime
+    async fn save_known_cells(&mut self) {
+        if let Some(known_cells) = self.known_cells.clone() {
+            match tokio::task::spawn_blocking(move || known_cells.lock().unwrap().save()).await {
+                Ok(Ok(())) => {},
+                Ok(Err(err)) => error!("{}", err),
+                Err(err) => error!("failed to save known cells database: {}", err),
+            }
+        }
+        self.known_cells_saved = Instant::now();
     }
 
     async fn write<T: Serialize>(&mut self, value: &T) -> Result<(), std::io::Error> {
@@ -70,10 +111,15 @@ impl AnalysisWriter {
         Ok(())
     }
 
-    // Flushes any pending I/O to disk before dropping the writer
-    pub async fn close(mut self) -> Result<(), std::io::Error> {
+    // Writes each analyzer's summary of the recording as the final row, then
+    // flushes any pending I/O to disk before dropping the writer. Returns the
+    // file's final length.
+    pub async fn close(mut self) -> Result<usize, std::io::Error> {
+        let summary = self.harness.finish_recording();
+        self.write(&summary).await?;
         self.writer.flush().await?;
-        Ok(())
+        self.save_known_cells().await;
+        Ok(self.bytes_written)
     }
 }
 
@@ -83,6 +129,14 @@ pub struct AnalysisStatus {
     running: Option<String>,
 }
 
+// How much work the analyzers are doing, for the live recording and for the
+// most recent re-analysis
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct AnalysisMetrics {
+    pub live: Option<HarnessMetrics>,
+    pub reanalysis: Option<HarnessMetrics>,
+}
+
 pub enum AnalysisCtrlMessage {
     NewFilesQueued,
     Exit,
@@ -108,6 +162,8 @@ async fn clear_running(analysis_status_lock: Arc<RwLock<AnalysisStatus>>) {
 async fn perform_analysis(
     name: &str,
     qmdl_store_lock: Arc<RwLock<RecordingStore>>,
+    analysis_metrics_lock: Arc<RwLock<AnalysisMetrics>>,
+    analyzers_config: &AnalyzersConfig,
     enable_dummy_analyzer: bool,
 ) -> Result<(), String> {
     info!("Opening QMDL and analysis file for {}...", name);
@@ -128,7 +184,12 @@ async fn perform_analysis(
         (analysis_file, qmdl_file, entry_index)
     };
 
-    let mut analysis_writer = AnalysisWriter::new(analysis_file, enable_dummy_analyzer)
+    // the live recording already taught the known cells database about this
+    // recording's cells, so re-analysis only gets a copy to read from
+    let mut analyzers_config = analyzers_config.clone();
+    analyzers_config.known_cells = analyzers_config.known_cells
+        .map(|known_cells| Arc::new(Mutex::new(known_cells.lock().unwrap().snapshot())));
+    let mut analysis_writer = AnalysisWriter::new(analysis_file, &analyzers_config, enable_dummy_analyzer)
         .await
         .map_err(|e| format!("{:?}", e))?;
     let file_size = qmdl_file
@@ -152,6 +213,7 @@ async fn perform_analysis(
             .await
             .map_err(|e| format!("{:?}", e))?;
         debug!("{} analysis: {} bytes written", name, size_bytes);
+        analysis_metrics_lock.write().await.reanalysis = Some(analysis_writer.get_metrics());
         let mut qmdl_store = qmdl_store_lock.write().await;
         qmdl_store
             .update_entry_analysis_size(entry_index, size_bytes)
@@ -159,10 +221,16 @@ async fn perform_analysis(
             .map_err(|e| format!("{:?}", e))?;
     }
 
-    analysis_writer
+    let size_bytes = analysis_writer
         .close()
         .await
         .map_err(|e| format!("{:?}", e))?;
+    qmdl_store_lock
+        .write()
+        .await
+        .update_entry_analysis_size(entry_index, size_bytes)
+        .await
+        .map_err(|e| format!("{:?}", e))?;
     info!("Analysis for {} complete!", name);
 
     Ok(())
@@ -173,6 +241,8 @@ pub fn run_analysis_thread(
     mut analysis_rx: Receiver<AnalysisCtrlMessage>,
     qmdl_store_lock: Arc<RwLock<RecordingStore>>,
     analysis_status_lock: Arc<RwLock<AnalysisStatus>>,
+    analysis_metrics_lock: Arc<RwLock<AnalysisMetrics>>,
+    analyzers_config: AnalyzersConfig,
     enable_dummy_analyzer: bool,
 ) {
     task_tracker.spawn(async move {
@@ -182,7 +252,7 @@ pub fn run_analysis_thread(
                     let count = queued_len(analysis_status_lock.clone()).await;
                     for _ in 0..count {
                         let name = dequeue_to_running(analysis_status_lock.clone()).await;
-                        if let Err(err) = perform_analysis(&name, qmdl_store_lock.clone(), enable_dummy_analyzer).await {
+                        if let Err(err) = perform_analysis(&name, qmdl_store_lock.clone(), analysis_metrics_lock.clone(), &analyzers_config, enable_dummy_analyzer).await {
                             error!("failed to analyze {}: {}", name, err);
                         }
                         clear_running(analysis_status_lock.clone()).await;
@@ -200,6 +270,12 @@ pub async fn get_analysis_status(
     Ok(Json(state.analysis_status_lock.read().await.clone()))
 }
 
+pub async fn get_analysis_metrics(
+    State(state): State<Arc<ServerState>>,
+) -> Result<Json<AnalysisMetrics>, (StatusCode, String)> {
+    Ok(Json(state.analysis_metrics_lock.read().await.clone()))
+}
+
 fn queue_qmdl(name: &str, analysis_status: &mut RwLockWriteGuard<AnalysisStatus>) -> bool {
     if analysis_status.queued.iter().any(|n| n == name)
         || analysis_status.running.iter().any(|n| n == name)
@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use futures::TryStreamExt;
+use log::info;
+use rayhunter::analysis::cell_reference::{CellReferenceStatus, CsvImporter};
+use serde::Serialize;
+
+use crate::server::ServerState;
+
+#[derive(Debug, Serialize)]
+pub struct CellReferenceImport {
+    #[serde(flatten)]
+    status: CellReferenceStatus,
+    skipped_rows: usize,
+}
+
+pub async fn get_cell_reference_status(State(state): State<Arc<ServerState>>) -> Result<Json<CellReferenceStatus>, (StatusCode, String)> {
+    Ok(Json(state.cell_reference.read().unwrap().status()))
+}
+
+// Imports a CSV of cells from the request body, replacing the current
+// reference. The body is parsed as it streams in, since regional dumps can be
+// larger than we'd like to hold in memory at once.
+pub async fn import_cell_reference(State(state): State<Arc<ServerState>>, body: Body) -> Result<Json<CellReferenceImport>, (StatusCode, String)> {
+    let mut importer = CsvImporter::default();
+    let mut stream = body.into_data_stream();
+    let mut buffer: Vec<u8> = Vec::new();
+    let bad_request = |e: String| (StatusCode::BAD_REQUEST, e);
+    while let Some(chunk) = stream.try_next().await
+        .map_err(|e| bad_request(format!("couldn't read request body: {}", e)))? {
+        buffer.extend_from_slice(&chunk);
+        let Some(last_newline) = buffer.iter().rposition(|byte| *byte == b'\n') else {
+            continue;
+        };
+        let rest = buffer.split_off(last_newline + 1);
+        for line in String::from_utf8_lossy(&buffer).lines() {
+            importer.add_line(line).map_err(|e| bad_request(e.to_string()))?;
+        }
+        buffer = rest;
+    }
+    importer.add_line(&String::from_utf8_lossy(&buffer)).map_err(|e| bad_request(e.to_string()))?;
+
+    // sorting and writing out a regional dump takes a while, so it's done on
+    // a blocking thread rather than holding up the runtime
+    let path = state.cell_reference_path.clone();
+    let (reference, skipped_rows) = tokio::task::spawn_blocking(move || {
+        let (reference, skipped_rows) = importer.finish();
+        reference.save(&path).map(|_| (reference, skipped_rows)).map_err(|e| e.to_string())
+    })
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("couldn't save cell reference: {}", e)))?;
+    let status = reference.status();
+    info!("imported cell reference with {} cells, skipping {} rows", status.cells, skipped_rows);
+    *state.cell_reference.write().unwrap() = reference;
+    Ok(Json(CellReferenceImport { status, skipped_rows }))
+}
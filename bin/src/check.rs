@@ -1,6 +1,7 @@
-use std::{collections::HashMap, future, path::PathBuf, pin::pin};
+use std::{collections::HashMap, fmt::Display, future, path::PathBuf, pin::pin, sync::{Arc, RwLock}};
 use log::{info, warn};
-use rayhunter::{analysis::analyzer::{EventType, Harness}, diag::DataType, gsmtap_parser, pcap::GsmtapPcapWriter, qmdl::QmdlReader};
+use rayhunter::{analysis::{analyzer::{EventType, Harness, ReportedEvent}, cell_reference::CellReference, config::AnalyzersConfig, correlation::ThreatLevel, metrics::{AnalyzerMetrics, HarnessMetrics}}, diag::DataType, gsmtap_parser, pcap::GsmtapPcapWriter, qmdl::QmdlReader};
+use serde::Deserialize;
 use tokio::fs::{metadata, read_dir, File};
 use clap::Parser;
 use futures::TryStreamExt;
@@ -22,11 +23,118 @@ struct Args {
     #[arg(long)]
     enable_dummy_analyzer: bool,
 
+    /// Read analyzer settings from the [analyzers] section of a rayhunter config file
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Load rule-based analyzers from this TOML/JSON file or directory
+    #[arg(long, value_name = "PATH")]
+    rules: Vec<PathBuf>,
+
+    /// Check cells against this cell reference, either a CSV dump or an index
+    /// saved by the daemon
+    #[arg(long, value_name = "PATH")]
+    cell_reference: Option<PathBuf>,
+
+    /// Enable the analyzer with this ID, regardless of the config file
+    #[arg(long, value_name = "ID")]
+    enable_analyzer: Vec<String>,
+
+    /// Disable the analyzer with this ID, regardless of the config file
+    #[arg(long, value_name = "ID")]
+    disable_analyzer: Vec<String>,
+
+    /// Print how much time each analyzer took once all files are analyzed
+    #[arg(long)]
+    profile: bool,
+
     #[arg(short, long)]
     verbose: bool,
 }
 
+// We share the daemon's config file format, but only care about the
+// [analyzers] section
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct CheckConfig {
+    analyzers: AnalyzersConfig,
+}
+
+fn load_analyzers_config(args: &Args) -> AnalyzersConfig {
+    let mut analyzers_config = match &args.config {
+        Some(path) => {
+            let config_file = std::fs::read_to_string(path).expect("failed to read config file");
+            let config: CheckConfig = toml::from_str(&config_file).expect("failed to parse config file");
+            config.analyzers
+        },
+        None => AnalyzersConfig::default(),
+    };
+    analyzers_config.rule_paths.extend(args.rules.iter().cloned());
+    analyzers_config.load_rules().expect("failed to load rules");
+    for id in &args.enable_analyzer {
+        analyzers_config.set_enabled(id, true).expect("invalid --enable-analyzer");
+    }
+    for id in &args.disable_analyzer {
+        analyzers_config.set_enabled(id, false).expect("invalid --disable-analyzer");
+    }
+    analyzers_config.load_scripts().expect("failed to load scripts");
+    if let Some(path) = &args.cell_reference {
+        let reference = if path.extension().is_some_and(|ext| ext == "csv") {
+            let (reference, skipped) = CellReference::from_csv_file(path).expect("failed to import cell reference");
+            if skipped > 0 {
+                warn!("skipped {} rows of the cell reference", skipped);
+            }
+            reference
+        } else {
+            CellReference::load(path).expect("failed to load cell reference")
+        };
+        analyzers_config.cell_reference = Some(Arc::new(RwLock::new(reference)));
+    }
+    analyzers_config
+}
+
+// Logs an event at a level matching its type, returning whether it was a
+// warning
+fn log_event(qmdl_path: &str, reported: &ReportedEvent, when: &dyn Display) -> bool {
+    if let Some(repeats) = &reported.repeats {
+        info!(
+            "{}: REPEATED [{}] - {} times from {} to {}: {}",
+            qmdl_path,
+            reported.analyzer_id,
+            repeats.count,
+            repeats.first_seen,
+            repeats.last_seen,
+            reported.event.message,
+        );
+        return false;
+    }
+    match reported.event.event_type {
+        EventType::Informational => {
+            info!(
+                "{}: INFO [{}] - {} {}",
+                qmdl_path,
+                reported.analyzer_id,
+                when,
+                reported.event.message,
+            );
+            false
+        }
+        EventType::QualitativeWarning { severity } => {
+            warn!(
+                "{}: WARNING [{}] (Severity: {:?}) - {} {}",
+                qmdl_path,
+                reported.analyzer_id,
+                severity,
+                when,
+                reported.event.message,
+            );
+            true
+        }
+    }
+}
+
 async fn analyze_file(harness: &mut Harness, qmdl_path: &str, show_skipped: bool) {
+    harness.start_recording();
     let qmdl_file = &mut File::open(&qmdl_path).await.expect("failed to open file");
     let file_size = qmdl_file.metadata().await.expect("failed to get QMDL file metadata").len();
     let mut qmdl_reader = QmdlReader::new(qmdl_file, Some(file_size as usize));
@@ -44,31 +152,19 @@ async fn analyze_file(harness: &mut Harness, qmdl_path: &str, show_skipped: bool
             skipped += 1;
         }
         for analysis in row.analysis {
-            for maybe_event in analysis.events {
-                let Some(event) = maybe_event else { continue };
-                match event.event_type {
-                    EventType::Informational => {
-                        info!(
-                            "{}: INFO - {} {}",
-                            qmdl_path,
-                            analysis.timestamp,
-                            event.message,
-                        );
-                    }
-                    EventType::QualitativeWarning { severity } => {
-                        warn!(
-                            "{}: WARNING (Severity: {:?}) - {} {}",
-                            qmdl_path,
-                            severity,
-                            analysis.timestamp,
-                            event.message,
-                        );
-                        warnings += 1;
-                    }
+            for reported in analysis.events {
+                if log_event(qmdl_path, &reported, &analysis.timestamp) {
+                    warnings += 1;
                 }
             }
         }
     }
+    let summary = harness.finish_recording();
+    for reported in &summary.summary {
+        if log_event(qmdl_path, reported, &"summary") {
+            warnings += 1;
+        }
+    }
     if show_skipped && skipped > 0 {
         info!("{}: messages skipped:", qmdl_path);
         for (reason, count) in skipped_reasons.iter() {
@@ -76,6 +172,31 @@ async fn analyze_file(harness: &mut Harness, qmdl_path: &str, show_skipped: bool
         }
     }
     info!("{}: {} messages analyzed, {} warnings, {} messages skipped", qmdl_path, total_messages, warnings, skipped);
+    if summary.threat_level > ThreatLevel::None {
+        warn!("{}: threat level {:?}", qmdl_path, summary.threat_level);
+    }
+}
+
+fn print_profile(metrics: &HarnessMetrics) {
+    println!(
+        "{} messages ({} skipped), {:.3}ms decoding",
+        metrics.messages,
+        metrics.skipped_messages,
+        metrics.decode_time.as_secs_f64() * 1000.0,
+    );
+    let mut analyzers: Vec<&AnalyzerMetrics> = metrics.analyzers.iter().collect();
+    analyzers.sort_by_key(|analyzer| std::cmp::Reverse(analyzer.analysis_time));
+    println!("{:<40} {:>10} {:>8} {:>12} {:>12}", "analyzer", "calls", "events", "total (ms)", "mean (us)");
+    for analyzer in analyzers {
+        println!(
+            "{:<40} {:>10} {:>8} {:>12.3} {:>12.3}",
+            analyzer.id,
+            analyzer.calls,
+            analyzer.events,
+            analyzer.analysis_time.as_secs_f64() * 1000.0,
+            analyzer.mean_analysis_time().as_secs_f64() * 1_000_000.0,
+        );
+    }
 }
 
 async fn pcapify(qmdl_path: &PathBuf) {
@@ -111,16 +232,18 @@ async fn main() {
         .with_level(level)
         .init().unwrap();
 
-    let mut harness = Harness::new_with_all_analyzers();
+    let mut harness = Harness::new_with_config(&load_analyzers_config(&args));
     if args.enable_dummy_analyzer {
         harness.add_analyzer(Box::new(dummy_analyzer::TestAnalyzer { count: 0 }));
     }
     info!("Analyzers:");
     for analyzer in harness.get_metadata().analyzers {
-        info!("    - {}: {}", analyzer.name, analyzer.description);
+        info!("    - {} (v{}) {}: {}", analyzer.id, analyzer.version, analyzer.name, analyzer.description);
     }
 
     let metadata = metadata(&args.qmdl_path).await.expect("failed to get metadata");
+    // the harness's metrics only cover the latest recording
+    let mut profile = HarnessMetrics::default();
     if metadata.is_dir() {
         let mut dir = read_dir(&args.qmdl_path).await.expect("failed to read dir");
         while let Some(entry) = dir.next_entry().await.expect("failed to get entry") {
@@ -130,6 +253,7 @@ async fn main() {
                 let path = entry.path();
                 let path_str = path.to_str().unwrap();
                 analyze_file(&mut harness, path_str, args.show_skipped).await;
+                profile.add(&harness.get_metrics());
                 if args.pcapify {
                     pcapify(&path).await;
                 }
@@ -138,8 +262,12 @@ async fn main() {
     } else {
         let path = args.qmdl_path.to_str().unwrap();
         analyze_file(&mut harness, path, args.show_skipped).await;
+        profile.add(&harness.get_metrics());
         if args.pcapify {
             pcapify(&args.qmdl_path).await;
         }
     }
+    if args.profile {
+        print_profile(&profile);
+    }
 }
@@ -1,5 +1,12 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+
 use crate::error::RayhunterError;
 
+use log::{error, info};
+use rayhunter::analysis::cell_reference::{CellReference, CellReferenceError};
+use rayhunter::analysis::config::AnalyzersConfig;
+use rayhunter::analysis::known_cells::{KnownCellsDb, KnownCellsError, DEFAULT_MAX_KNOWN_CELLS};
 use serde::Deserialize;
 
 #[derive(Debug)]
@@ -7,31 +14,73 @@ use serde::Deserialize;
 #[serde(default)]
 pub struct Config {
     pub qmdl_store_path: String,
+    pub known_cells_path: String,
+    pub max_known_cells: usize,
+    pub cell_reference_path: String,
     pub port: u16,
     pub debug_mode: bool,
     pub ui_level: u8,
     pub enable_dummy_analyzer: bool,
     pub colorblind_mode: bool,
+    pub analyzers: AnalyzersConfig,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
             qmdl_store_path: "/data/rayhunter/qmdl".to_string(),
+            known_cells_path: "/data/rayhunter/known_cells.json".to_string(),
+            max_known_cells: DEFAULT_MAX_KNOWN_CELLS,
+            cell_reference_path: "/data/rayhunter/cell_reference.bin".to_string(),
             port: 8080,
             debug_mode: false,
             ui_level: 1,
             enable_dummy_analyzer: false,
             colorblind_mode: false,
+            analyzers: AnalyzersConfig::default(),
         }
     }
 }
 
 pub fn parse_config<P>(path: P) -> Result<Config, RayhunterError> where P: AsRef<std::path::Path> {
-    if let Ok(config_file) = std::fs::read_to_string(&path) {
-        Ok(toml::from_str(&config_file).map_err(RayhunterError::ConfigFileParsingError)?)
+    let mut config = if let Ok(config_file) = std::fs::read_to_string(&path) {
+        toml::from_str(&config_file).map_err(RayhunterError::ConfigFileParsingError)?
     } else {
-        Ok(Config::default())
+        Config::default()
+    };
+    config.analyzers.load_rules()?;
+    config.analyzers.load_scripts()?;
+    let known_cells = load_known_cells(Path::new(&config.known_cells_path), config.max_known_cells)?;
+    config.analyzers.known_cells = Some(Arc::new(Mutex::new(known_cells)));
+    let cell_reference = load_cell_reference(Path::new(&config.cell_reference_path))?;
+    config.analyzers.cell_reference = Some(Arc::new(RwLock::new(cell_reference)));
+    Ok(config)
+}
+
+// A corrupt or outdated cell reference index is ignored until a new one is
+// imported
+fn load_cell_reference(path: &Path) -> Result<CellReference, RayhunterError> {
+    match CellReference::load(path) {
+        Ok(reference) => Ok(reference),
+        Err(err @ CellReferenceError::InvalidIndex(_)) => {
+            error!("{}", err);
+            Ok(CellReference::default())
+        },
+        Err(err) => Err(err.into()),
+    }
+}
+
+// If the known cells database is corrupt, start a new one rather than refusing
+// to run
+fn load_known_cells(path: &Path, max_cells: usize) -> Result<KnownCellsDb, RayhunterError> {
+    match KnownCellsDb::load(path, max_cells) {
+        Ok(db) => Ok(db),
+        Err(err @ KnownCellsError::ParseError(..)) => {
+            error!("{}", err);
+            info!("starting a new known cells database...");
+            Ok(KnownCellsDb::new(Some(path.to_path_buf()), max_cells))
+        },
+        Err(err) => Err(err.into()),
     }
 }
 
@@ -49,3 +98,38 @@ pub fn parse_args() -> Args {
         config_path: args[1].clone(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rayhunter::analysis::analyzer::Severity;
+
+    #[test]
+    fn test_parse_analyzers_config() {
+        let config: Config = toml::from_str(r#"
+            port = 8081
+
+            [analyzers.imsi_requested]
+            severity = "Low"
+
+            [analyzers.imsi_requested.params]
+            packet_threshold = 300
+
+            [analyzers.null_cipher]
+            enabled = true
+        "#).unwrap();
+        assert_eq!(config.port, 8081);
+        assert_eq!(config.analyzers.imsi_requested.severity, Some(Severity::Low));
+        assert_eq!(config.analyzers.imsi_requested.params.packet_threshold, 300);
+        assert!(config.analyzers.imsi_requested.is_enabled(true));
+        assert!(config.analyzers.null_cipher.is_enabled(false));
+        assert!(config.analyzers.lte_sib6_and_7_downgrade.is_enabled(true));
+    }
+
+    #[test]
+    fn test_unknown_analyzer_keys_rejected() {
+        assert!(toml::from_str::<Config>("[analyzers.not_an_analyzer]\nenabled = true").is_err());
+        assert!(toml::from_str::<Config>("[analyzers.null_cipher]\nenbled = true").is_err());
+        assert!(toml::from_str::<Config>("[analyzers.null_cipher.params]\npacket_threshold = 1").is_err());
+    }
+}
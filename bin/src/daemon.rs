@@ -8,6 +8,8 @@ mod qmdl_store;
 mod diag;
 mod framebuffer;
 mod dummy_analyzer;
+mod known_cells;
+mod cell_reference;
 
 use crate::config::{parse_config, parse_args};
 use crate::diag::run_diag_read_thread;
@@ -17,8 +19,10 @@ use crate::pcap::get_pcap;
 use crate::stats::get_system_stats;
 use crate::error::RayhunterError;
 use crate::framebuffer::Framebuffer;
+use crate::known_cells::{get_known_cells, reset_known_cells};
+use crate::cell_reference::{get_cell_reference_status, import_cell_reference};
 
-use analysis::{get_analysis_status, run_analysis_thread, start_analysis, AnalysisCtrlMessage, AnalysisStatus};
+use analysis::{get_analysis_metrics, get_analysis_status, run_analysis_thread, start_analysis, AnalysisCtrlMessage, AnalysisMetrics, AnalysisStatus};
 use axum::response::Redirect;
 use diag::{get_analysis_report, start_recording, stop_recording, DiagDeviceCtrlMessage};
 use log::{info, error};
@@ -58,7 +62,11 @@ async fn run_server(
         .route("/api/stop-recording", post(stop_recording))
         .route("/api/analysis-report/*name", get(get_analysis_report))
         .route("/api/analysis", get(get_analysis_status))
+        .route("/api/analysis-metrics", get(get_analysis_metrics))
         .route("/api/analysis/*name", post(start_analysis))
+        .route("/api/known-cells", get(get_known_cells))
+        .route("/api/known-cells/reset", post(reset_known_cells))
+        .route("/api/cell-reference", get(get_cell_reference_status).post(import_cell_reference))
         .route("/", get(|| async { Redirect::permanent("/index.html") }))
         .route("/*path", get(serve_static))
         .with_state(state);
@@ -224,6 +232,7 @@ async fn main() -> Result<(), RayhunterError> {
     let (tx, rx) = mpsc::channel::<DiagDeviceCtrlMessage>(1);
     let (ui_update_tx, ui_update_rx) = mpsc::channel::<framebuffer::DisplayState>(1);
     let (analysis_tx, analysis_rx) = mpsc::channel::<AnalysisCtrlMessage>(5);
+    let analysis_metrics_lock = Arc::new(RwLock::new(AnalysisMetrics::default()));
     let mut maybe_ui_shutdown_tx = None;
     if !config.debug_mode {
         let (ui_shutdown_tx, ui_shutdown_rx) = oneshot::channel();
@@ -234,14 +243,14 @@ async fn main() -> Result<(), RayhunterError> {
             .map_err(RayhunterError::DiagInitError)?;
 
         info!("Starting Diag Thread");
-        run_diag_read_thread(&task_tracker, dev, rx, ui_update_tx.clone(), qmdl_store_lock.clone(), config.enable_dummy_analyzer);
+        run_diag_read_thread(&task_tracker, dev, rx, ui_update_tx.clone(), qmdl_store_lock.clone(), analysis_metrics_lock.clone(), config.analyzers.clone(), config.enable_dummy_analyzer);
         info!("Starting UI");
         update_ui(&task_tracker, &config, ui_shutdown_rx, ui_update_rx);
     }
     let (server_shutdown_tx, server_shutdown_rx) = oneshot::channel::<()>();
     info!("create shutdown thread");
     let analysis_status_lock = Arc::new(RwLock::new(AnalysisStatus::default()));
-    run_analysis_thread(&task_tracker, analysis_rx, qmdl_store_lock.clone(), analysis_status_lock.clone(), config.enable_dummy_analyzer);
+    run_analysis_thread(&task_tracker, analysis_rx, qmdl_store_lock.clone(), analysis_status_lock.clone(), analysis_metrics_lock.clone(), config.analyzers.clone(), config.enable_dummy_analyzer);
     run_ctrl_c_thread(&task_tracker, tx.clone(), server_shutdown_tx, maybe_ui_shutdown_tx, qmdl_store_lock.clone(), analysis_tx.clone());
     let state = Arc::new(ServerState {
         qmdl_store_lock: qmdl_store_lock.clone(),
@@ -249,7 +258,11 @@ async fn main() -> Result<(), RayhunterError> {
         ui_update_sender: ui_update_tx,
         debug_mode: config.debug_mode,
         analysis_status_lock,
+        analysis_metrics_lock,
         analysis_sender: analysis_tx,
+        known_cells: config.analyzers.known_cells.clone().expect("parse_config didn't load the known cells database"),
+        cell_reference: config.analyzers.cell_reference.clone().expect("parse_config didn't load the cell reference"),
+        cell_reference_path: config.cell_reference_path.clone().into(),
         colorblind_mode: config.colorblind_mode,
     });
     run_server(&task_tracker, &config, state, server_shutdown_rx).await;
@@ -6,6 +6,8 @@ use axum::extract::{Path, State};
 use axum::http::header::CONTENT_TYPE;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
+use rayhunter::analysis::config::AnalyzersConfig;
+use rayhunter::analysis::correlation::ThreatLevel;
 use rayhunter::diag::DataType;
 use rayhunter::diag_device::DiagDevice;
 use tokio::sync::RwLock;
@@ -20,44 +22,58 @@ use futures::{StreamExt, TryStreamExt};
 use crate::framebuffer;
 use crate::qmdl_store::RecordingStore;
 use crate::server::ServerState;
-use crate::analysis::AnalysisWriter;
+use crate::analysis::{AnalysisMetrics, AnalysisWriter};
 
+// only ever sent a handful of times per recording, so not worth boxing
+#[allow(clippy::large_enum_variant)]
 pub enum DiagDeviceCtrlMessage {
     StopRecording,
     StartRecording((QmdlWriter<File>, File)),
     Exit,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn run_diag_read_thread(
     task_tracker: &TaskTracker,
     mut dev: DiagDevice,
     mut qmdl_file_rx: Receiver<DiagDeviceCtrlMessage>,
     ui_update_sender: Sender<framebuffer::DisplayState>,
     qmdl_store_lock: Arc<RwLock<RecordingStore>>,
+    analysis_metrics_lock: Arc<RwLock<AnalysisMetrics>>,
+    analyzers_config: AnalyzersConfig,
     enable_dummy_analyzer: bool,
 ) {
     task_tracker.spawn(async move {
         let (initial_qmdl_file, initial_analysis_file) = qmdl_store_lock.write().await.new_entry().await.expect("failed creating QMDL file entry");
         let mut maybe_qmdl_writer: Option<QmdlWriter<File>> = Some(QmdlWriter::new(initial_qmdl_file));
         let mut diag_stream = pin!(dev.as_stream().into_stream());
-        let mut maybe_analysis_writer = Some(AnalysisWriter::new(initial_analysis_file, enable_dummy_analyzer).await
-            .expect("failed to create analysis writer"));
+        // we keep track of which entry each analysis writer belongs to, since
+        // the store's current entry has already moved on by the time we're
+        // told to close it
+        let mut maybe_analysis_writer = Some((
+            AnalysisWriter::new(initial_analysis_file, &analyzers_config, enable_dummy_analyzer).await
+                .expect("failed to create analysis writer"),
+            current_entry_index(&qmdl_store_lock).await,
+        ));
         loop {
             tokio::select! {
                 msg = qmdl_file_rx.recv() => {
                     match msg {
                         Some(DiagDeviceCtrlMessage::StartRecording((new_writer, new_analysis_file))) => {
                             maybe_qmdl_writer = Some(new_writer);
-                            if let Some(analysis_writer) = maybe_analysis_writer {
-                                analysis_writer.close().await.expect("failed to close analysis writer");
+                            if let Some((analysis_writer, index)) = maybe_analysis_writer {
+                                close_analysis_writer(analysis_writer, index, &qmdl_store_lock).await;
                             }
-                            maybe_analysis_writer = Some(AnalysisWriter::new(new_analysis_file, enable_dummy_analyzer).await
-                                .expect("failed to write to analysis file"));
+                            maybe_analysis_writer = Some((
+                                AnalysisWriter::new(new_analysis_file, &analyzers_config, enable_dummy_analyzer).await
+                                    .expect("failed to write to analysis file"),
+                                current_entry_index(&qmdl_store_lock).await,
+                            ));
                         },
                         Some(DiagDeviceCtrlMessage::StopRecording) => {
                             maybe_qmdl_writer = None;
-                            if let Some(analysis_writer) = maybe_analysis_writer {
-                                analysis_writer.close().await.expect("failed to close analysis writer");
+                            if let Some((analysis_writer, index)) = maybe_analysis_writer {
+                                close_analysis_writer(analysis_writer, index, &qmdl_store_lock).await;
                             }
                             maybe_analysis_writer = None;
                         },
@@ -65,8 +81,8 @@ pub fn run_diag_read_thread(
                         // time to go
                         Some(DiagDeviceCtrlMessage::Exit) | None => {
                             info!("Diag reader thread exiting...");
-                            if let Some(analysis_writer) = maybe_analysis_writer {
-                                analysis_writer.close().await.expect("failed to close analysis writer");
+                            if let Some((analysis_writer, index)) = maybe_analysis_writer {
+                                close_analysis_writer(analysis_writer, index, &qmdl_store_lock).await;
                             }
                             return Ok(())
                         },
@@ -93,19 +109,22 @@ pub fn run_diag_read_thread(
                                 debug!("no qmdl_writer set, continuing...");
                             }
 
-                            if let Some(analysis_writer) = maybe_analysis_writer.as_mut() {
+                            if let Some((analysis_writer, index)) = maybe_analysis_writer.as_mut() {
                                 let analysis_output = analysis_writer.analyze(container).await
                                     .expect("failed to analyze container");
-                                let (analysis_file_len, heuristic_warning) = analysis_output;
-                                if heuristic_warning {
-                                    info!("a heuristic triggered on this run!");
-                                    ui_update_sender.send(framebuffer::DisplayState::WarningDetected).await
-                                        .expect("couldn't send ui update message: {}");
+                                let (analysis_file_len, new_threat_level) = analysis_output;
+                                if let Some(threat_level) = new_threat_level {
+                                    info!("threat level for this run is now {:?}", threat_level);
+                                    // a Low threat level isn't worth alarming the user over
+                                    if threat_level >= ThreatLevel::Medium {
+                                        ui_update_sender.send(framebuffer::DisplayState::WarningDetected).await
+                                            .expect("couldn't send ui update message: {}");
+                                    }
                                 }
                                 let mut qmdl_store = qmdl_store_lock.write().await;
-                                let index = qmdl_store.current_entry.expect("DiagDevice had qmdl_writer, but QmdlStore didn't have current entry???");
-                                qmdl_store.update_entry_analysis_size(index, analysis_file_len).await
+                                qmdl_store.update_entry_analysis_size(*index, analysis_file_len).await
                                     .expect("failed to update analysis file size");
+                                analysis_metrics_lock.write().await.live = Some(analysis_writer.get_metrics());
                             }
                         },
                         Err(err) => {
@@ -119,6 +138,19 @@ pub fn run_diag_read_thread(
     });
 }
 
+async fn current_entry_index(qmdl_store_lock: &RwLock<RecordingStore>) -> usize {
+    qmdl_store_lock.read().await.current_entry
+        .expect("created analysis writer, but QmdlStore didn't have current entry???")
+}
+
+// Closes an analysis writer, which writes its recording's summary row, and
+// records the analysis file's final size for that recording's entry
+async fn close_analysis_writer(analysis_writer: AnalysisWriter, entry_index: usize, qmdl_store_lock: &RwLock<RecordingStore>) {
+    let analysis_file_len = analysis_writer.close().await.expect("failed to close analysis writer");
+    qmdl_store_lock.write().await.update_entry_analysis_size(entry_index, analysis_file_len).await
+        .expect("failed to update analysis file size");
+}
+
 pub async fn start_recording(State(state): State<Arc<ServerState>>) -> Result<(StatusCode, String), (StatusCode, String)> {
     if state.debug_mode {
         return Err((StatusCode::FORBIDDEN, "server is in debug mode".to_string()));
@@ -3,6 +3,7 @@ use std::borrow::Cow;
 use rayhunter::telcom_parser::lte_rrc::{PCCH_MessageType, PCCH_MessageType_c1, PagingUE_Identity};
 
 use rayhunter::analysis::analyzer::{Analyzer, Event, EventType, Severity};
+use rayhunter::analysis::context::PacketContext;
 use rayhunter::analysis::information_element::{InformationElement, LteInformationElement};
 
 pub struct TestAnalyzer{
@@ -10,6 +11,14 @@ pub struct TestAnalyzer{
 }
 
 impl Analyzer for TestAnalyzer{
+    fn get_id(&self) -> Cow<str> {
+        Cow::from("example")
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
     fn get_name(&self) -> Cow<str> {
         Cow::from("Example Analyzer")
     }
@@ -18,7 +27,7 @@ impl Analyzer for TestAnalyzer{
         Cow::from("Always returns true, if you are seeing this you are either a developer or you are about to have problems.")
     }
 
-    fn analyze_information_element(&mut self, ie: &InformationElement) -> Option<Event> {
+    fn analyze_information_element(&mut self, ie: &InformationElement, _context: &PacketContext) -> Option<Event> {
         self.count += 1;
         if self.count % 100 == 0 {
             return Some(Event {
@@ -1,4 +1,8 @@
 use thiserror::Error;
+use rayhunter::analysis::cell_reference::CellReferenceError;
+use rayhunter::analysis::known_cells::KnownCellsError;
+use rayhunter::analysis::rules::RuleError;
+use rayhunter::analysis::script::ScriptError;
 use rayhunter::diag_device::DiagDeviceError;
 
 use crate::qmdl_store::RecordingStoreError;
@@ -15,4 +19,12 @@ pub enum RayhunterError{
     QmdlStoreError(#[from] RecordingStoreError),
     #[error("No QMDL store found at path {0}, but can't create a new one due to debug mode")]
     NoStoreDebugMode(String),
+    #[error("Rule loading error: {0}")]
+    RuleLoadingError(#[from] RuleError),
+    #[error("Script loading error: {0}")]
+    ScriptLoadingError(#[from] ScriptError),
+    #[error("Known cells database error: {0}")]
+    KnownCellsError(#[from] KnownCellsError),
+    #[error("Cell reference error: {0}")]
+    CellReferenceError(#[from] CellReferenceError),
 }
@@ -0,0 +1,34 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use rayhunter::analysis::known_cells::KnownCell;
+use serde::Serialize;
+
+use crate::server::ServerState;
+
+#[derive(Debug, Serialize)]
+pub struct KnownCells {
+    max_cells: usize,
+    cells: Vec<KnownCell>,
+}
+
+pub async fn get_known_cells(State(state): State<Arc<ServerState>>) -> Result<Json<KnownCells>, (StatusCode, String)> {
+    let known_cells = state.known_cells.lock().unwrap();
+    Ok(Json(KnownCells {
+        max_cells: known_cells.max_cells(),
+        cells: known_cells.cells().into_iter().cloned().collect(),
+    }))
+}
+
+pub async fn reset_known_cells(State(state): State<Arc<ServerState>>) -> Result<(StatusCode, String), (StatusCode, String)> {
+    state.known_cells.lock().unwrap().reset();
+    let known_cells = state.known_cells.clone();
+    tokio::task::spawn_blocking(move || known_cells.lock().unwrap().save().map_err(|e| e.to_string()))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("couldn't save known cells database: {}", e)))?;
+    Ok((StatusCode::OK, "ok".to_string()))
+}
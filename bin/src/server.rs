@@ -8,12 +8,14 @@ use tokio::fs::File;
 use tokio::io::AsyncReadExt;
 use tokio::sync::mpsc::Sender;
 use std::sync::Arc;
+use rayhunter::analysis::cell_reference::SharedCellReference;
+use rayhunter::analysis::known_cells::SharedKnownCellsDb;
 use tokio::sync::RwLock;
 use tokio_util::io::ReaderStream;
 use include_dir::{include_dir, Dir};
 
 use crate::{framebuffer, DiagDeviceCtrlMessage};
-use crate::analysis::{AnalysisCtrlMessage, AnalysisStatus};
+use crate::analysis::{AnalysisCtrlMessage, AnalysisMetrics, AnalysisStatus};
 use crate::qmdl_store::RecordingStore;
 
 pub struct ServerState {
@@ -21,7 +23,11 @@ pub struct ServerState {
     pub diag_device_ctrl_sender: Sender<DiagDeviceCtrlMessage>,
     pub ui_update_sender: Sender<framebuffer::DisplayState>,
     pub analysis_status_lock: Arc<RwLock<AnalysisStatus>>,
+    pub analysis_metrics_lock: Arc<RwLock<AnalysisMetrics>>,
     pub analysis_sender: Sender<AnalysisCtrlMessage>,
+    pub known_cells: SharedKnownCellsDb,
+    pub cell_reference: SharedCellReference,
+    pub cell_reference_path: std::path::PathBuf,
     pub debug_mode: bool,
     pub colorblind_mode: bool,
 }
@@ -62,9 +62,15 @@ function parseNewlineDelimitedJSON(inputStr) {
 async function updateEntryAnalysisResult(entry) {
     entry.analysis = {
         warnings: [],
+        threat_level: 'None',
     };
     const report = parseNewlineDelimitedJSON(await req('GET', `/api/analysis-report/${entry.name}`));
     for (const row of report) {
+      // each row has the recording's threat level as of when it was written,
+      // so the last one is the current level
+      if (row["threat_level"]) {
+        entry.analysis.threat_level = row["threat_level"];
+      }
       if (row["analysis"]) {
         const timestamp = new Date(row["timestamp"]);
         const analysis = row["analysis"];
@@ -75,15 +81,30 @@ async function updateEntryAnalysisResult(entry) {
           })
         }
       }
+      // the last row holds each analyzer's summary of the whole recording
+      if (row["summary"] && row["summary"].length > 0) {
+        entry.analysis.warnings.push({
+          timestamp: new Date(row["timestamp"]),
+          warning: { events: row["summary"] },
+        })
+      }
     }
     if (entry.analysis.warnings.length === 0) {
         entry.analysis_result = `0 warnings!`;
     } else {
-        entry.analysis_result = `!!! ${entry.analysis.warnings.length} warnings !!!`;
+        entry.analysis_result = `Threat level: ${entry.analysis.threat_level}, ${entry.analysis.warnings.length} warnings`;
         for (const warning of entry.analysis.warnings) {
             for (const event of warning.warning.events) {
                 if (event === null) continue;
-                msg = `${warning.timestamp}: ${event.message}`
+                // reports written before events were tagged have no analyzer ID
+                if (event.analyzer_id) {
+                    msg = `${warning.timestamp}: [${event.analyzer_id}] ${event.message}`
+                } else {
+                    msg = `${warning.timestamp}: ${event.message}`
+                }
+                if (event.repeats) {
+                    msg += ` (repeated ${event.repeats.count} times since ${new Date(event.repeats.first_seen)})`
+                }
                 entry.analysis_result += `<br>${msg}`
             }
         }
@@ -148,7 +169,7 @@ function createEntryRow(entry, isCurrent) {
 
     const analysisResult = document.createElement('td');
     analysisResult.innerHTML = entry.analysis_result;
-    if (entry.analysis.warnings.length > 0) {
+    if (['Medium', 'High'].includes(entry.analysis.threat_level)) {
         row.classList.add("warning");
     }
     row.appendChild(analysisResult);
@@ -0,0 +1,149 @@
+//! Compares how long the analysis harness takes when it decodes every message
+//! versus only the messages some analyzer is interested in, both with the
+//! default analyzers and with only the IMSI request analyzer enabled.
+//!
+//! Run with `cargo bench --bench harness [-- path/to/sample.qmdl]`. Without a
+//! QMDL file, a synthetic recording is used: broadcast and paging messages,
+//! plus MBMS control and measurement report traffic that no default analyzer
+//! looks at.
+
+use std::time::{Duration, Instant};
+
+use rayhunter::analysis::analyzer::Harness;
+use rayhunter::analysis::config::{AnalyzersConfig, ANALYZER_IDS};
+use rayhunter::diag::{DataType, HdlcEncapsulatedMessage, MessagesContainer, CRC_CCITT};
+use rayhunter::hdlc::hdlc_encapsulate;
+use rayhunter::qmdl::QmdlReader;
+use tokio::fs::File;
+
+const ITERATIONS: u32 = 20;
+const SYNTHETIC_CONTAINERS: usize = 500;
+
+// pdu_num values for LTE RRC OTA packets with ext_header_version 26
+const PDU_BCCH_BCH: u8 = 1;
+const PDU_BCCH_DL_SCH: u8 = 3;
+const PDU_MCCH: u8 = 6;
+const PDU_PCCH: u8 = 7;
+const PDU_UL_DCCH: u8 = 11;
+
+// An MBSFNAreaConfiguration with one subframe allocation and no PMCHs
+const MCCH_MBSFN_AREA_CONFIGURATION: [u8; 4] = [0x00, 0x04, 0x00, 0x00];
+// A MeasurementReport with only the serving cell's RSRP and RSRQ
+const UL_DCCH_MEASUREMENT_REPORT: [u8; 4] = [0x08, 0x00, 0x32, 0x50];
+
+const SIB1_HEX: &str = "484c469010600018fd1a9207e22103108ac21bdc09802292cdd20000";
+
+fn decode_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+// Builds a diag log message containing an LTE RRC OTA packet, in the same
+// layout as the ones in tests/test_lte_parsing.rs
+fn lte_rrc_ota_message(pdu_num: u8, payload: &[u8]) -> Vec<u8> {
+    let length = (33 + payload.len()) as u16;
+    let mut data = vec![0x10, 0x00];
+    data.extend(length.to_le_bytes());
+    data.extend(length.to_le_bytes());
+    data.extend(0xb0c0u16.to_le_bytes());
+    data.extend([0; 8]); // timestamp
+    data.extend([26, 15, 64, 15, 64, 1]); // header version, RRC releases, bearer ID
+    data.extend(270u16.to_le_bytes()); // physical cell ID
+    data.extend(1811u32.to_le_bytes()); // EARFCN
+    data.extend([0, 0, pdu_num, 0, 0, 0, 0]); // SFN, PDU number, SIB mask
+    data.extend((payload.len() as u16).to_le_bytes());
+    data.extend(payload);
+    data
+}
+
+fn synthetic_containers() -> Vec<MessagesContainer> {
+    let sib1 = decode_hex(SIB1_HEX);
+    let mut messages = vec![
+        lte_rrc_ota_message(PDU_BCCH_BCH, &[0x64, 0x9c, 0x00]),
+        lte_rrc_ota_message(PDU_BCCH_DL_SCH, &sib1),
+        lte_rrc_ota_message(PDU_PCCH, &[0x00]),
+    ];
+    // eNBs repeat the MCCH every modification period, and a connected phone
+    // reports measurements far more often than it sees a new SIB1
+    for _ in 0..4 {
+        messages.push(lte_rrc_ota_message(PDU_MCCH, &MCCH_MBSFN_AREA_CONFIGURATION));
+        messages.push(lte_rrc_ota_message(PDU_UL_DCCH, &UL_DCCH_MEASUREMENT_REPORT));
+    }
+    let container = MessagesContainer {
+        data_type: DataType::UserSpace,
+        num_messages: messages.len() as u32,
+        messages: messages.iter()
+            .map(|message| {
+                let data = hdlc_encapsulate(message, &CRC_CCITT);
+                HdlcEncapsulatedMessage { len: data.len() as u32, data }
+            })
+            .collect(),
+    };
+    vec![container; SYNTHETIC_CONTAINERS]
+}
+
+async fn read_qmdl(path: &str) -> Vec<MessagesContainer> {
+    let file = File::open(path).await.expect("failed to open QMDL file");
+    let file_size = file.metadata().await.expect("failed to get QMDL file size").len();
+    let mut reader = QmdlReader::new(file, Some(file_size as usize));
+    let mut containers = Vec::new();
+    while let Some(container) = reader.get_next_messages_container().await.expect("failed to read QMDL file") {
+        if container.data_type == DataType::UserSpace {
+            containers.push(container);
+        }
+    }
+    containers
+}
+
+// Returns the mean time per run, and how many messages failed to decode
+fn run(containers: &[MessagesContainer], config: &AnalyzersConfig, decode_all: bool) -> (Duration, u64) {
+    let mut total = Duration::ZERO;
+    let mut skipped = 0;
+    // the first run only warms up caches and the allocator
+    for iteration in 0..=ITERATIONS {
+        let mut harness = Harness::new_with_config(config);
+        harness.set_decode_all(decode_all);
+        harness.start_recording();
+        let start = Instant::now();
+        for container in containers {
+            harness.analyze_qmdl_messages(container.clone());
+        }
+        harness.finish_recording();
+        if iteration > 0 {
+            total += start.elapsed();
+        }
+        skipped = harness.get_metrics().skipped_messages;
+    }
+    (total / ITERATIONS, skipped)
+}
+
+fn main() {
+    // cargo passes flags like --bench, so the first other argument is the path
+    let containers = match std::env::args().skip(1).find(|arg| !arg.starts_with('-')) {
+        Some(path) => {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            runtime.block_on(read_qmdl(&path))
+        },
+        None => synthetic_containers(),
+    };
+    let messages: usize = containers.iter().map(|container| container.messages.len()).sum();
+    println!("analyzing {} containers ({} messages), {} iterations each", containers.len(), messages, ITERATIONS);
+
+    let mut imsi_requested_only = AnalyzersConfig::default();
+    for id in ANALYZER_IDS {
+        imsi_requested_only.set_enabled(id, *id == "imsi_requested").unwrap();
+    }
+    compare("default analyzers", &containers, &AnalyzersConfig::default());
+    compare("imsi_requested only", &containers, &imsi_requested_only);
+}
+
+fn compare(name: &str, containers: &[MessagesContainer], config: &AnalyzersConfig) {
+    println!("{}:", name);
+    let (decode_all, skipped) = run(containers, config, true);
+    println!("  decode all messages:         {:>10.2?} per run ({} skipped)", decode_all, skipped);
+    let (decode_interesting, skipped) = run(containers, config, false);
+    println!("  decode interesting messages: {:>10.2?} per run ({} skipped)", decode_interesting, skipped);
+    println!("  speedup: {:.2}x", decode_all.as_secs_f64() / decode_interesting.as_secs_f64());
+}
@@ -1,23 +1,52 @@
 use std::borrow::Cow;
-use chrono::{DateTime, FixedOffset};
-use serde::Serialize;
-
-use crate::{diag::MessagesContainer, gsmtap_parser};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{diag::{DiagParsingError, Message, MessagesContainer}, gsmtap_parser};
 use crate::util::RuntimeMetadata;
 
 use super::{
+    cell_identity::Sib1CellIdentityAnalyzer,
+    cell_reference_mismatch::CellReferenceMismatchAnalyzer,
+    cell_reselection::CellReselectionAnalyzer,
+    config::AnalyzersConfig,
+    dedup::{Deduplicator, Repeats},
+    emergency_alert::EmergencyAlertAnalyzer,
+    correlation::{Correlator, CorrelationConfig, ThreatLevel},
+    context::{PacketContext, PhysicalCell},
+    idle_mode_mobility::IdleModeMobilityDowngradeAnalyzer,
+    imsi_paging::ImsiPagingAnalyzer,
     imsi_requested::ImsiRequestedAnalyzer,
-    information_element::InformationElement,
+    information_element::{InformationElement, LteChannel},
+    known_cells::{KnownCellsDb, KnownCellsRecorder, SharedKnownCellsDb, DEFAULT_MAX_KNOWN_CELLS},
+    location_request::LocationRequestAnalyzer,
+    metrics::{AnalyzerMetrics, HarnessMetrics},
+    mobility_from_eutra::MobilityFromEutraAnalyzer,
     connection_redirect_downgrade::ConnectionRedirect2GDowngradeAnalyzer,
+    connection_reject::ConnectionRejectAnalyzer,
+    novel_cell::NovelCellAnalyzer,
+    null_cipher::NullCipherAnalyzer,
     priority_2g_downgrade::LteSib6And7DowngradeAnalyzer,
+    rrc_state::RrcStateTracker,
+    rules::RuleAnalyzer,
+    transient_cell::TransientCellAnalyzer,
+    ue_capability_enquiry::UeCapabilityEnquiryAnalyzer,
 };
+#[cfg(feature = "scripting")]
+use super::script::ScriptAnalyzer;
+#[cfg(feature = "scripting")]
+use log::error;
 
 /// Qualitative measure of how severe a Warning event type is.
 /// The levels should break down like this:
 ///   * Low: if combined with a large number of other Warnings, user should investigate
 ///   * Medium: if combined with a few other Warnings, user should investigate
 ///   * High: user should investigate
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Severity {
     Low,
     Medium,
@@ -26,7 +55,7 @@ pub enum Severity {
 
 /// `QualitativeWarning` events will always be shown to the user in some manner,
 /// while `Informational` ones may be hidden based on user settings.
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Hash)]
 #[serde(tag = "type")]
 pub enum EventType {
     Informational,
@@ -36,17 +65,62 @@ pub enum EventType {
 /// Events are user-facing signals that can be emitted by an [Analyzer] upon a
 /// message being received. They can be used to signifiy an IC detection
 /// warning, or just to display some relevant information to the user.
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Event {
     pub event_type: EventType,
     pub message: String,
 }
 
+/// Which messages an [Analyzer] wants to see. Messages which no analyzer is
+/// interested in aren't decoded at all, which saves a lot of CPU time.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Interests {
+    /// Every message.
+    #[default]
+    All,
+    /// Only LTE messages on these channels.
+    LteChannels(HashSet<LteChannel>),
+}
+
+impl Interests {
+    pub fn lte_channels(channels: impl IntoIterator<Item = LteChannel>) -> Self {
+        Interests::LteChannels(channels.into_iter().collect())
+    }
+
+    /// Whether messages on the given channel are wanted. `None` means the
+    /// channel isn't known, which only [Interests::All] covers.
+    pub fn includes(&self, channel: Option<LteChannel>) -> bool {
+        match (self, channel) {
+            (Interests::All, _) => true,
+            (Interests::LteChannels(channels), Some(channel)) => channels.contains(&channel),
+            (Interests::LteChannels(_), None) => false,
+        }
+    }
+
+    fn extend(&mut self, other: &Interests) {
+        match (self, other) {
+            (Interests::All, _) => {},
+            (this, Interests::All) => *this = Interests::All,
+            (Interests::LteChannels(channels), Interests::LteChannels(others)) => channels.extend(others),
+        }
+    }
+}
+
 /// An [Analyzer] represents one type of heuristic for detecting an IMSI Catcher
 /// (IC). While maintaining some amount of state is useful, be mindful of how
 /// much memory your [Analyzer] uses at runtime, since rayhunter may run for
 /// many hours at a time with dozens of [Analyzers](Analyzer) working in parallel.
 pub trait Analyzer {
+    /// Returns a stable, machine-readable ID for your heuristic, e.g.
+    /// "imsi_requested". This is how config files and analysis reports refer
+    /// to it, so it must never change once released.
+    fn get_id(&self) -> Cow<str>;
+
+    /// Returns the version of your heuristic's logic. Bump this whenever its
+    /// behavior changes enough that reports generated by different versions
+    /// shouldn't be compared directly.
+    fn get_version(&self) -> u32;
+
     /// Returns a user-friendly, concise name for your heuristic.
     fn get_name(&self) -> Cow<str>;
 
@@ -58,14 +132,57 @@ pub trait Analyzer {
     fn get_description(&self) -> Cow<str>;
 
     /// Analyze a single [InformationElement], possibly returning an [Event] if your
-    /// heuristic deems it relevant. Again, be mindful of any state your
-    /// [Analyzer] updates per message, since it may be run over hundreds or
-    /// thousands of them alongside many other [Analyzers](Analyzer).
-    fn analyze_information_element(&mut self, ie: &InformationElement) -> Option<Event>;
+    /// heuristic deems it relevant. The [PacketContext] describes when and on
+    /// which cell the message was seen, as well as its direction. Again, be
+    /// mindful of any state your [Analyzer] updates per message, since it may
+    /// be run over hundreds or thousands of them alongside many other
+    /// [Analyzers](Analyzer).
+    fn analyze_information_element(&mut self, ie: &InformationElement, context: &PacketContext) -> Option<Event>;
+
+    /// Returns which messages your heuristic wants to see. Other messages
+    /// won't be passed to [Analyzer::analyze_information_element], and won't
+    /// be decoded at all if no other [Analyzer] wants them either, so narrow
+    /// this down wherever possible.
+    fn get_interests(&self) -> Interests {
+        Interests::All
+    }
+
+    /// Returns whether your heuristic inspects LTE messages through their
+    /// serde representation, as [rules](super::rules) do. If so, the [Harness]
+    /// serializes each message once and passes it to
+    /// [Analyzer::analyze_serialized_message] instead of
+    /// [Analyzer::analyze_information_element], so it's shared between every
+    /// such [Analyzer].
+    fn wants_serialized_messages(&self) -> bool {
+        false
+    }
+
+    /// Analyze the serde representation of an LTE message, which is an object
+    /// with the message under its channel's name. Only called if
+    /// [Analyzer::wants_serialized_messages] returns true.
+    fn analyze_serialized_message(&mut self, _message: &Value, _context: &PacketContext) -> Option<Event> {
+        None
+    }
+
+    /// Called before the first message of a recording, whether it's being
+    /// recorded live or re-analyzed. Analyzers which track state across
+    /// messages should reset it here, since the same [Analyzer] may be run
+    /// over several recordings.
+    fn on_recording_start(&mut self) {}
+
+    /// Called after the last message of a recording, once it's been stopped or
+    /// its re-analysis has completed. Any [Events](Event) returned are written
+    /// to the end of the analysis report as a summary of the whole recording,
+    /// e.g. how many times something was seen.
+    fn on_recording_end(&mut self) -> Vec<Event> {
+        Vec::new()
+    }
 }
 
 #[derive(Serialize, Debug)]
 pub struct AnalyzerMetadata {
+    pub id: String,
+    pub version: u32,
     pub name: String,
     pub description: String,
 }
@@ -76,10 +193,24 @@ pub struct ReportMetadata {
     pub rayhunter: RuntimeMetadata,
 }
 
+/// An [Event] as it appears in an analysis report, tagged with the ID of the
+/// [Analyzer] which emitted it so it can be interpreted without knowing which
+/// analyzers were running.
+#[derive(Serialize, Debug, Clone)]
+pub struct ReportedEvent {
+    pub analyzer_id: String,
+    #[serde(flatten)]
+    pub event: Event,
+    /// If set, this summarizes repeats of an event which was already reported,
+    /// rather than being a new event.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeats: Option<Repeats>,
+}
+
 #[derive(Serialize, Debug, Clone)]
 pub struct PacketAnalysis {
     pub timestamp: DateTime<FixedOffset>,
-    pub events: Vec<Option<Event>>,
+    pub events: Vec<ReportedEvent>,
 }
 
 #[derive(Serialize, Debug)]
@@ -87,6 +218,19 @@ pub struct AnalysisRow {
     pub timestamp: DateTime<FixedOffset>,
     pub skipped_message_reasons: Vec<String>,
     pub analysis: Vec<PacketAnalysis>,
+    /// The recording's threat level as of this row, taking into account every
+    /// warning so far.
+    pub threat_level: ThreatLevel,
+}
+
+/// The last row of an analysis report, holding the [Events](Event) returned
+/// by each [Analyzer] once the recording was over.
+#[derive(Serialize, Debug)]
+pub struct RecordingSummary {
+    pub timestamp: DateTime<FixedOffset>,
+    pub summary: Vec<ReportedEvent>,
+    /// The recording's final threat level.
+    pub threat_level: ThreatLevel,
 }
 
 impl AnalysisRow {
@@ -96,8 +240,8 @@ impl AnalysisRow {
 
     pub fn contains_warnings(&self) -> bool {
         for analysis in &self.analysis {
-            for event in analysis.events.iter().flatten() {
-                if matches!(event.event_type, EventType::QualitativeWarning { .. }) {
+            for reported in &analysis.events {
+                if matches!(reported.event.event_type, EventType::QualitativeWarning { .. }) {
                     return true;
                 }
             }
@@ -106,8 +250,82 @@ impl AnalysisRow {
     }
 }
 
+/// Settings for how a [Harness] reports an [Analyzer]'s [Events](Event).
+#[derive(Debug, Clone, Default)]
+pub struct AnalyzerOptions {
+    /// If set, all `QualitativeWarning` events will be reported with this
+    /// [Severity] instead of their own.
+    pub severity_override: Option<Severity>,
+    /// If set, repeats of the same event on the same cell within this long of
+    /// each other are merged (see [dedup](super::dedup)).
+    pub dedup_window: Option<TimeDelta>,
+}
+
+impl AnalyzerOptions {
+    /// Builds options from a config's severity and `dedup_seconds`, where 0
+    /// disables deduplication.
+    pub fn new(severity_override: Option<Severity>, dedup_seconds: Option<u32>) -> Self {
+        AnalyzerOptions {
+            severity_override,
+            dedup_window: dedup_seconds
+                .filter(|seconds| *seconds > 0)
+                .map(|seconds| TimeDelta::seconds(seconds.into())),
+        }
+    }
+}
+
+struct HarnessAnalyzer {
+    analyzer: Box<dyn Analyzer + Send>,
+    interests: Interests,
+    wants_serialized: bool,
+    severity_override: Option<Severity>,
+    dedup: Option<Deduplicator>,
+    metrics: AnalyzerMetrics,
+}
+
+impl HarnessAnalyzer {
+    // Applies any severity override to the event, and tags it with the
+    // analyzer's ID
+    fn report(&self, mut event: Event, repeats: Option<Repeats>) -> ReportedEvent {
+        if let (EventType::QualitativeWarning { severity }, Some(severity_override)) = (&mut event.event_type, self.severity_override) {
+            *severity = severity_override;
+        }
+        ReportedEvent {
+            analyzer_id: self.analyzer.get_id().to_string(),
+            event,
+            repeats,
+        }
+    }
+
+    fn flush_repeats(&mut self, now: Option<DateTime<FixedOffset>>) -> Vec<ReportedEvent> {
+        let Some(dedup) = self.dedup.as_mut() else {
+            return Vec::new();
+        };
+        let flushed = match now {
+            Some(now) => dedup.flush_expired(now),
+            None => dedup.flush_all(),
+        };
+        flushed.into_iter()
+            .map(|(event, repeats)| self.report(event, Some(repeats)))
+            .collect()
+    }
+}
+
 pub struct Harness {
-    analyzers: Vec<Box<dyn Analyzer + Send>>,
+    analyzers: Vec<HarnessAnalyzer>,
+    // the union of all the analyzers' interests
+    interests: Interests,
+    decode_all: bool,
+    correlator: Correlator,
+    rrc_state: RrcStateTracker,
+    known_cells: Option<KnownCellsRecorder>,
+    // how many GSMTAP packets we've seen in the current recording
+    recording_packets: u64,
+    // when the latest packet we analyzed in the current recording was logged
+    last_timestamp: Option<DateTime<FixedOffset>>,
+    messages: u64,
+    skipped_messages: u64,
+    decode_time: Duration,
 }
 
 impl Default for Harness {
@@ -118,25 +336,167 @@ impl Default for Harness {
 
 impl Harness {
     pub fn new() -> Self {
-        Self { analyzers: Vec::new() }
+        Self {
+            analyzers: Vec::new(),
+            // the RRC state has to be tracked even if no analyzer is
+            // interested in the messages it follows
+            interests: Interests::lte_channels(RrcStateTracker::CHANNELS),
+            decode_all: false,
+            correlator: Correlator::new(CorrelationConfig::default()),
+            rrc_state: RrcStateTracker::default(),
+            known_cells: None,
+            recording_packets: 0,
+            last_timestamp: None,
+            messages: 0,
+            skipped_messages: 0,
+            decode_time: Duration::ZERO,
+        }
     }
 
     pub fn new_with_all_analyzers() -> Self {
+        Self::new_with_config(&AnalyzersConfig::default())
+    }
+
+    pub fn new_with_config(config: &AnalyzersConfig) -> Self {
         let mut harness = Harness::new();
-        harness.add_analyzer(Box::new(ImsiRequestedAnalyzer::new()));
-        harness.add_analyzer(Box::new(ConnectionRedirect2GDowngradeAnalyzer{}));
-        harness.add_analyzer(Box::new(LteSib6And7DowngradeAnalyzer{}));
+        harness.correlator = Correlator::new(config.correlation.clone());
+        // without a database to load, cells are only known to this harness
+        let known_cells = config.known_cells.clone()
+            .unwrap_or_else(|| Arc::new(Mutex::new(KnownCellsDb::new(None, DEFAULT_MAX_KNOWN_CELLS))));
+        if config.imsi_requested.is_enabled(true) {
+            let analyzer = ImsiRequestedAnalyzer::new(config.imsi_requested.params.packet_threshold);
+            harness.add_analyzer_with_options(Box::new(analyzer), config.imsi_requested.options(None));
+        }
+        if config.imsi_paging.is_enabled(true) {
+            let analyzer = ImsiPagingAnalyzer::new(config.imsi_paging.params.imsi.clone());
+            harness.add_analyzer_with_options(Box::new(analyzer), config.imsi_paging.options(None));
+        }
+        if config.connection_redirect_2g_downgrade.is_enabled(true) {
+            let analyzer = ConnectionRedirect2GDowngradeAnalyzer{};
+            harness.add_analyzer_with_options(Box::new(analyzer), config.connection_redirect_2g_downgrade.options(None));
+        }
+        if config.mobility_from_eutra_downgrade.is_enabled(true) {
+            let analyzer = MobilityFromEutraAnalyzer::default();
+            harness.add_analyzer_with_options(Box::new(analyzer), config.mobility_from_eutra_downgrade.options(None));
+        }
+        if config.idle_mode_mobility_downgrade.is_enabled(true) {
+            let analyzer = IdleModeMobilityDowngradeAnalyzer::default();
+            harness.add_analyzer_with_options(Box::new(analyzer), config.idle_mode_mobility_downgrade.options(None));
+        }
+        if config.lte_sib6_and_7_downgrade.is_enabled(true) {
+            let analyzer = LteSib6And7DowngradeAnalyzer::default();
+            harness.add_analyzer_with_options(Box::new(analyzer), config.lte_sib6_and_7_downgrade.options(Some(60)));
+        }
 
         // FIXME: our RRC parser is reporting false positives for this due to an
         // upstream hampi bug (https://github.com/ystero-dev/hampi/issues/133).
-        // once that's fixed, we should regenerate our parser and re-enable this
-        // harness.add_analyzer(Box::new(NullCipherAnalyzer{}));
+        // once that's fixed, we should regenerate our parser and enable this by
+        // default
+        if config.null_cipher.is_enabled(false) {
+            let analyzer = NullCipherAnalyzer{};
+            harness.add_analyzer_with_options(Box::new(analyzer), config.null_cipher.options(None));
+        }
+
+        if config.sib1_cell_identity.is_enabled(true) {
+            let analyzer = Sib1CellIdentityAnalyzer::default();
+            harness.add_analyzer_with_options(Box::new(analyzer), config.sib1_cell_identity.options(Some(60)));
+        }
+
+        if config.ue_capability_enquiry_before_security.is_enabled(true) {
+            let analyzer = UeCapabilityEnquiryAnalyzer::default();
+            harness.add_analyzer_with_options(Box::new(analyzer), config.ue_capability_enquiry_before_security.options(None));
+        }
+
+        if config.cell_reselection_anomaly.is_enabled(true) {
+            let analyzer = CellReselectionAnalyzer::new(config.cell_reselection_anomaly.params.clone());
+            harness.add_analyzer_with_options(Box::new(analyzer), config.cell_reselection_anomaly.options(Some(60)));
+        }
+
+        if config.location_request.is_enabled(true) {
+            let analyzer = LocationRequestAnalyzer::new(known_cells.clone());
+            harness.add_analyzer_with_options(Box::new(analyzer), config.location_request.options(None));
+        }
+
+        if config.emergency_alert.is_enabled(true) {
+            let analyzer = EmergencyAlertAnalyzer::new(known_cells.clone());
+            harness.add_analyzer_with_options(Box::new(analyzer), config.emergency_alert.options(None));
+        }
+
+        if config.rrc_connection_reject.is_enabled(true) {
+            let analyzer = ConnectionRejectAnalyzer::new(&config.rrc_connection_reject.params);
+            harness.add_analyzer_with_options(Box::new(analyzer), config.rrc_connection_reject.options(None));
+        }
+
+        if config.novel_cell.is_enabled(true) {
+            let analyzer = NovelCellAnalyzer::new(known_cells.clone(), &config.novel_cell.params);
+            harness.add_analyzer_with_options(Box::new(analyzer), config.novel_cell.options(None));
+        }
+
+        // the daemon's database learns every cell we see, but one private to
+        // this harness only needs to if an analyzer reads it
+        let reads_known_cells = config.location_request.is_enabled(true)
+            || config.emergency_alert.is_enabled(true)
+            || config.novel_cell.is_enabled(true);
+        if config.known_cells.is_some() || reads_known_cells {
+            harness.set_known_cells(known_cells);
+        }
+
+        if let Some(reference) = config.cell_reference.as_ref().filter(|_| config.cell_reference_mismatch.is_enabled(true)) {
+            let analyzer = CellReferenceMismatchAnalyzer::new(reference.clone());
+            harness.add_analyzer_with_options(Box::new(analyzer), config.cell_reference_mismatch.options(None));
+        }
+
+        if config.transient_cell.is_enabled(true) {
+            let analyzer = TransientCellAnalyzer::new(&config.transient_cell.params);
+            harness.add_analyzer_with_options(Box::new(analyzer), config.transient_cell.options(None));
+        }
+
+        for rule in config.rules.iter().filter(|rule| rule.enabled) {
+            harness.add_analyzer_with_options(Box::new(RuleAnalyzer::new(rule.clone())), rule.options());
+        }
+
+        #[cfg(feature = "scripting")]
+        for script in config.scripts.iter().filter(|script| script.enabled) {
+            match ScriptAnalyzer::new(script) {
+                Ok(analyzer) => harness.add_analyzer_with_options(Box::new(analyzer), script.options()),
+                Err(err) => error!("failed to load script {}: {}", script.id, err),
+            }
+        }
 
         harness
     }
 
     pub fn add_analyzer(&mut self, analyzer: Box<dyn Analyzer + Send>) {
-        self.analyzers.push(analyzer);
+        self.add_analyzer_with_options(analyzer, AnalyzerOptions::default());
+    }
+
+    pub fn add_analyzer_with_options(&mut self, analyzer: Box<dyn Analyzer + Send>, options: AnalyzerOptions) {
+        let metrics = AnalyzerMetrics::new(analyzer.get_id().to_string());
+        let interests = analyzer.get_interests();
+        self.interests.extend(&interests);
+        let wants_serialized = analyzer.wants_serialized_messages();
+        self.analyzers.push(HarnessAnalyzer {
+            analyzer,
+            interests,
+            wants_serialized,
+            severity_override: options.severity_override,
+            dedup: options.dedup_window.map(Deduplicator::new),
+            metrics,
+        });
+    }
+
+    /// Records every cell we see in the given database, regardless of which
+    /// analyzers are enabled.
+    pub fn set_known_cells(&mut self, known_cells: SharedKnownCellsDb) {
+        self.interests.extend(&Interests::lte_channels([LteChannel::BcchDlSch]));
+        self.known_cells = Some(KnownCellsRecorder::new(known_cells));
+    }
+
+    /// If set, every message is decoded, even if no [Analyzer] is interested
+    /// in it. This is only useful for benchmarking, or for finding messages we
+    /// fail to decode.
+    pub fn set_decode_all(&mut self, decode_all: bool) {
+        self.decode_all = decode_all;
     }
 
     pub fn analyze_qmdl_messages(&mut self, container: MessagesContainer) -> AnalysisRow {
@@ -144,40 +504,43 @@ impl Harness {
             timestamp: chrono::Local::now().fixed_offset(),
             skipped_message_reasons: Vec::new(),
             analysis: Vec::new(),
+            threat_level: self.correlator.level(),
         };
-        for maybe_qmdl_message in container.into_messages() {
-            let qmdl_message = match maybe_qmdl_message {
-                Ok(msg) => msg,
-                Err(err) => {
-                    row.skipped_message_reasons.push(format!("{:?}", err));
+        let decode_start = Instant::now();
+        let messages = container.into_messages();
+        self.decode_time += decode_start.elapsed();
+        for maybe_qmdl_message in messages {
+            self.messages += 1;
+            let decode_start = Instant::now();
+            let decoded = self.decode_message(maybe_qmdl_message);
+            self.decode_time += decode_start.elapsed();
+            let (element, context) = match decoded {
+                Ok(Some(decoded)) => decoded,
+                Ok(None) => continue,
+                Err(reason) => {
+                    self.skipped_messages += 1;
+                    row.skipped_message_reasons.push(reason);
                     continue;
                 }
             };
-
-            let gsmtap_message = match gsmtap_parser::parse(qmdl_message) {
-                Ok(msg) => msg,
-                Err(err) => {
-                    row.skipped_message_reasons.push(format!("{:?}", err));
-                    continue;
+            self.last_timestamp = Some(context.timestamp);
+
+            for entry in self.analyzers.iter_mut() {
+                for reported in entry.flush_repeats(Some(context.timestamp)) {
+                    let repeats = reported.repeats.as_ref().expect("flushed event without repeats");
+                    row.analysis.push(PacketAnalysis {
+                        timestamp: repeats.last_seen,
+                        events: vec![reported],
+                    });
                 }
-            };
-
-            let Some((timestamp, gsmtap_msg)) = gsmtap_message else {
-                continue;
-            };
-
-            let element = match InformationElement::try_from(&gsmtap_msg) {
-                Ok(element) => element,
-                Err(err) => {
-                    row.skipped_message_reasons.push(format!("{:?}", err));
-                    continue;
-                }
-            };
+            }
 
-            let analysis_result = self.analyze_information_element(&element);
-            if analysis_result.iter().any(Option::is_some) {
+            let analysis_result = self.analyze_information_element(&element, &context);
+            self.rrc_state.observe(&element);
+            row.threat_level = self.correlator.observe(context.timestamp, &analysis_result);
+            if !analysis_result.is_empty() {
                 row.analysis.push(PacketAnalysis {
-                    timestamp: timestamp.to_datetime(),
+                    timestamp: context.timestamp,
                     events: analysis_result,
                 });
             }
@@ -185,34 +548,175 @@ impl Harness {
         row
     }
 
-    fn analyze_information_element(&mut self, ie: &InformationElement) -> Vec<Option<Event>> {
-        self.analyzers.iter_mut()
-            .map(|analyzer| analyzer.analyze_information_element(ie))
-            .collect()
+    // Converts a diag message into an InformationElement, returning None for
+    // messages which parse fine but that no analyzer wants, or else the reason
+    // the message was skipped
+    fn decode_message(&mut self, maybe_qmdl_message: Result<Message, DiagParsingError>) -> Result<Option<(InformationElement, PacketContext)>, String> {
+        let qmdl_message = maybe_qmdl_message.map_err(|err| format!("{:?}", err))?;
+
+        // GSMTAP headers don't have room for the PCI, so grab the cell
+        // before the message gets converted
+        let cell = PhysicalCell::from_message(&qmdl_message);
+
+        let gsmtap_message = gsmtap_parser::parse(qmdl_message)
+            .map_err(|err| format!("{:?}", err))?;
+
+        let Some((timestamp, gsmtap_msg)) = gsmtap_message else {
+            return Ok(None);
+        };
+        let packet_index = self.recording_packets;
+        self.recording_packets += 1;
+
+        // UPER decoding is by far the most expensive part of analysis, so
+        // avoid it if we can. Messages with an unknown channel are passed on
+        // so they can be reported as skipped
+        let channel = LteChannel::from_gsmtap_type(gsmtap_msg.header.gsmtap_type);
+        if channel.is_some() && !self.decode_all && !self.interests.includes(channel) {
+            return Ok(None);
+        }
+
+        let element = InformationElement::try_from(&gsmtap_msg)
+            .map_err(|err| format!("{:?}", err))?;
+
+        let mut context = PacketContext::new(timestamp.to_datetime(), &gsmtap_msg.header, cell, packet_index);
+        context.rrc_state = self.rrc_state.state();
+        Ok(Some((element, context)))
+    }
+
+    fn analyze_information_element(&mut self, ie: &InformationElement, context: &PacketContext) -> Vec<ReportedEvent> {
+        let channel = match ie {
+            InformationElement::LTE(lte_ie) => Some(lte_ie.channel()),
+            _ => None,
+        };
+        // serialized at most once, the first time an analyzer wants it
+        let mut serialized: Option<Option<Value>> = None;
+        let events = self.analyzers.iter_mut()
+            .filter_map(|entry| {
+                if !entry.interests.includes(channel) {
+                    return None;
+                }
+                let start = Instant::now();
+                let maybe_event = if entry.wants_serialized {
+                    let message = serialized.get_or_insert_with(|| match ie {
+                        InformationElement::LTE(lte_ie) => serde_json::to_value(lte_ie).ok(),
+                        _ => None,
+                    });
+                    message.as_ref().and_then(|message| entry.analyzer.analyze_serialized_message(message, context))
+                } else {
+                    entry.analyzer.analyze_information_element(ie, context)
+                };
+                entry.metrics.record_call(start.elapsed(), usize::from(maybe_event.is_some()));
+                let reported = entry.report(maybe_event?, None);
+                if let Some(dedup) = entry.dedup.as_mut() {
+                    if !dedup.observe(&reported.event, context) {
+                        return None;
+                    }
+                }
+                Some(reported)
+            })
+            .collect();
+        // after the analyzers, so they see whether the cell was already known
+        if let Some(known_cells) = self.known_cells.as_mut() {
+            known_cells.observe(ie, context);
+        }
+        events
+    }
+
+    /// Lets each [Analyzer] know a new recording is starting, and resets the
+    /// [metrics](Harness::get_metrics). This should be called before
+    /// analyzing the recording's first messages.
+    pub fn start_recording(&mut self) {
+        self.recording_packets = 0;
+        self.last_timestamp = None;
+        self.messages = 0;
+        self.skipped_messages = 0;
+        self.decode_time = Duration::ZERO;
+        for entry in self.analyzers.iter_mut() {
+            entry.metrics = AnalyzerMetrics::new(entry.metrics.id.clone());
+        }
+        self.rrc_state.reset();
+        if let Some(known_cells) = self.known_cells.as_mut() {
+            known_cells.reset();
+        }
+        self.correlator.reset();
+        for entry in self.analyzers.iter_mut() {
+            if let Some(dedup) = entry.dedup.as_mut() {
+                dedup.flush_all();
+            }
+        }
+        for entry in self.analyzers.iter_mut() {
+            entry.analyzer.on_recording_start();
+        }
+    }
+
+    /// Lets each [Analyzer] know the current recording is over, collecting
+    /// their summary [Events](Event). The summary is timestamped with the
+    /// recording's last analyzed packet.
+    pub fn finish_recording(&mut self) -> RecordingSummary {
+        let summary = self.analyzers.iter_mut()
+            .flat_map(|entry| {
+                let start = Instant::now();
+                let events = entry.analyzer.on_recording_end();
+                entry.metrics.analysis_time += start.elapsed();
+                entry.metrics.events += events.len() as u64;
+                // any repeats we were still tracking come first, since they
+                // happened before the recording ended
+                let mut reported = entry.flush_repeats(None);
+                reported.extend(events.into_iter().map(|event| entry.report(event, None)));
+                reported
+            })
+            .collect::<Vec<_>>();
+        // re-analyzed recordings should be summarized as of when they ended,
+        // not when the re-analysis ran
+        let timestamp = self.last_timestamp
+            .unwrap_or_else(|| chrono::Local::now().fixed_offset());
+        let threat_level = self.correlator.observe(timestamp, &summary);
+        RecordingSummary {
+            timestamp,
+            summary,
+            threat_level,
+        }
+    }
+
+    /// The current recording's threat level, based on the warnings seen so far.
+    pub fn threat_level(&self) -> ThreatLevel {
+        self.correlator.level()
     }
 
-    pub fn get_names(&self) -> Vec<Cow<'_, str>> {
+    /// Returns how much work the harness and each of its analyzers have done
+    /// in the current recording.
+    pub fn get_metrics(&self) -> HarnessMetrics {
+        HarnessMetrics {
+            messages: self.messages,
+            skipped_messages: self.skipped_messages,
+            decode_time: self.decode_time,
+            analyzers: self.analyzers.iter()
+                .map(|entry| entry.metrics.clone())
+                .collect(),
+        }
+    }
+
+    pub fn get_names(&self) -> Vec<Cow<str>> {
         self.analyzers.iter()
-            .map(|analyzer| analyzer.get_name())
+            .map(|entry| entry.analyzer.get_name())
             .collect()
     }
 
-    pub fn get_descriptions(&self) -> Vec<Cow<'_, str>> {
+    pub fn get_descriptions(&self) -> Vec<Cow<str>> {
         self.analyzers.iter()
-            .map(|analyzer| analyzer.get_description())
+            .map(|entry| entry.analyzer.get_description())
             .collect()
     }
 
     pub fn get_metadata(&self) -> ReportMetadata {
-        let names = self.get_names();
-        let descriptions = self.get_descriptions();
-        let mut analyzers = Vec::new();
-        for (name, description) in names.iter().zip(descriptions.iter()) {
-            analyzers.push(AnalyzerMetadata {
-                name: name.to_string(),
-                description: description.to_string(),
-            });
-        }
+        let analyzers = self.analyzers.iter()
+            .map(|entry| AnalyzerMetadata {
+                id: entry.analyzer.get_id().to_string(),
+                version: entry.analyzer.get_version(),
+                name: entry.analyzer.get_name().to_string(),
+                description: entry.analyzer.get_description().to_string(),
+            })
+            .collect();
 
         let rayhunter = RuntimeMetadata::new();
 
@@ -222,3 +726,120 @@ impl Harness {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::information_element::LteInformationElement;
+    use crate::analysis::config::ANALYZER_IDS;
+    use crate::analysis::known_cells::KnownCellKey;
+    use crate::analysis::test_util::{context, context_on, sib1};
+
+    // Reports every other message, taking at least a millisecond each time
+    struct SlowAnalyzer {
+        calls: u64,
+    }
+
+    impl Analyzer for SlowAnalyzer {
+        fn get_id(&self) -> Cow<str> {
+            Cow::from("slow")
+        }
+
+        fn get_version(&self) -> u32 {
+            1
+        }
+
+        fn get_name(&self) -> Cow<str> {
+            Cow::from("Slow")
+        }
+
+        fn get_description(&self) -> Cow<str> {
+            Cow::from("")
+        }
+
+        fn get_interests(&self) -> Interests {
+            Interests::lte_channels([LteChannel::NAS])
+        }
+
+        fn analyze_information_element(&mut self, _ie: &InformationElement, _context: &PacketContext) -> Option<Event> {
+            std::thread::sleep(Duration::from_millis(1));
+            self.calls += 1;
+            self.calls.is_multiple_of(2).then(|| Event {
+                event_type: EventType::Informational,
+                message: String::new(),
+            })
+        }
+
+        fn on_recording_end(&mut self) -> Vec<Event> {
+            vec![Event { event_type: EventType::Informational, message: String::new() }]
+        }
+    }
+
+    #[test]
+    fn test_analyzer_metrics() {
+        let mut harness = Harness::new();
+        harness.add_analyzer(Box::new(SlowAnalyzer { calls: 0 }));
+        harness.start_recording();
+        let nas = InformationElement::LTE(Box::new(LteInformationElement::NAS(vec![])));
+        for _ in 0..4 {
+            harness.analyze_information_element(&nas, &context());
+        }
+        // messages the analyzer isn't interested in don't count as calls
+        harness.analyze_information_element(&InformationElement::GSM, &context());
+        harness.finish_recording();
+
+        let metrics = &harness.get_metrics().analyzers[0];
+        assert_eq!(metrics.id, "slow");
+        assert_eq!((metrics.calls, metrics.events), (4, 3));
+        assert!(metrics.analysis_time >= Duration::from_millis(4));
+
+        harness.start_recording();
+        let metrics = &harness.get_metrics().analyzers[0];
+        assert_eq!((metrics.calls, metrics.events, metrics.analysis_time), (0, 0, Duration::ZERO));
+    }
+
+    #[test]
+    fn test_config_ids() {
+        let mut config = AnalyzersConfig {
+            cell_reference: Some(Default::default()),
+            ..Default::default()
+        };
+        for id in ANALYZER_IDS {
+            config.set_enabled(id, true).unwrap();
+        }
+        let mut ids: Vec<String> = Harness::new_with_config(&config).get_metadata().analyzers.into_iter()
+            .map(|analyzer| analyzer.id)
+            .collect();
+        ids.sort();
+        let mut expected = ANALYZER_IDS.to_vec();
+        expected.sort();
+        assert_eq!(ids, expected);
+        assert!(config.set_enabled("not_an_analyzer", true).is_err());
+    }
+
+    #[test]
+    fn test_known_cells_recorded_by_harness() {
+        let db = Arc::new(Mutex::new(KnownCellsDb::new(None, 100)));
+        let mut config = AnalyzersConfig {
+            known_cells: Some(db.clone()),
+            ..Default::default()
+        };
+        for id in ANALYZER_IDS {
+            config.set_enabled(id, false).unwrap();
+        }
+        let mut harness = Harness::new_with_config(&config);
+        assert!(harness.interests.includes(Some(LteChannel::BcchDlSch)));
+        for _ in 0..2 {
+            harness.start_recording();
+            for _ in 0..3 {
+                harness.analyze_information_element(&sib1(|_| {}), &context_on(5230, 7));
+            }
+        }
+
+        let db = db.lock().unwrap();
+        assert_eq!(db.len(), 1);
+        let key = KnownCellKey::from_sib1(&sib1(|_| {}), Some(PhysicalCell { earfcn: 5230, phy_cell_id: 7 })).unwrap();
+        assert_eq!(key.plmn, "311-480");
+        assert_eq!(db.get(&key).unwrap().observations, 2);
+    }
+}
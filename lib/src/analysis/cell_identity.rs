@@ -0,0 +1,182 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use telcom_parser::lte_rrc::{BCCH_DL_SCH_MessageType, BCCH_DL_SCH_MessageType_c1, PLMN_IdentityList, SystemInformationBlockType1};
+
+use super::analyzer::{Analyzer, Event, EventType, Interests, Severity};
+use super::context::{PacketContext, PhysicalCell};
+use super::information_element::{InformationElement, LteChannel, LteInformationElement};
+use super::util::{bits_to_u32, unpack};
+
+/// The identity a cell advertises in its SIB1.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct CellIdentity {
+    /// The PLMNs the cell belongs to, formatted as "MCC-MNC".
+    pub plmns: Vec<String>,
+    pub tac: u32,
+    pub cell_identity: u32,
+}
+
+impl CellIdentity {
+    pub(crate) fn from_sib1(sib1: &SystemInformationBlockType1) -> Self {
+        let info = &sib1.cell_access_related_info;
+        CellIdentity {
+            plmns: format_plmns(&info.plmn_identity_list),
+            tac: bits_to_u32(info.tracking_area_code.0.iter().by_vals()),
+            cell_identity: bits_to_u32(info.cell_identity.0.iter().by_vals()),
+        }
+    }
+
+    // The E-UTRAN cell global ID, which should be unique to a cell
+    fn global_id(&self) -> (Option<&String>, u32) {
+        (self.plmns.first(), self.cell_identity)
+    }
+}
+
+impl fmt::Display for CellIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PLMN {}, TAC {}, cell ID {}", self.plmns.join("/"), self.tac, self.cell_identity)
+    }
+}
+
+// Entries without an MCC share the previous entry's, per TS 36.331
+fn format_plmns(list: &PLMN_IdentityList) -> Vec<String> {
+    let mut mcc = String::new();
+    list.0.iter()
+        .map(|info| {
+            let plmn = &info.plmn_identity;
+            if let Some(digits) = &plmn.mcc {
+                mcc = digits.0.iter().map(|digit| digit.0.to_string()).collect();
+            }
+            let mnc: String = plmn.mnc.0.iter().map(|digit| digit.0.to_string()).collect();
+            format!("{}-{}", mcc, mnc)
+        })
+        .collect()
+}
+
+/// Tracks the identity each physical cell advertises in its SIB1. A physical
+/// cell changing its identity, or the same cell identity being broadcast by
+/// several physical cells, suggests a cloned or spoofed cell.
+#[derive(Default)]
+pub struct Sib1CellIdentityAnalyzer {
+    identities: HashMap<PhysicalCell, CellIdentity>,
+    // every PCI each cell global ID was seen on
+    pcis: HashMap<(Option<String>, u32), HashSet<u16>>,
+}
+
+impl Analyzer for Sib1CellIdentityAnalyzer {
+    fn get_id(&self) -> Cow<str> {
+        Cow::from("sib1_cell_identity")
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("SIB1 Cell Identity Consistency")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from("Tests whether a cell changes the PLMN, TAC or cell ID it advertises in SIB1, or whether one cell ID is used by several physical cells.")
+    }
+
+    fn get_interests(&self) -> Interests {
+        Interests::lte_channels([LteChannel::BcchDlSch])
+    }
+
+    fn analyze_information_element(&mut self, ie: &InformationElement, context: &PacketContext) -> Option<Event> {
+        unpack!(InformationElement::LTE(lte_ie) = ie);
+        unpack!(LteInformationElement::BcchDlSch(bcch_dl_sch_message) = &**lte_ie);
+        unpack!(BCCH_DL_SCH_MessageType::C1(c1) = &bcch_dl_sch_message.message);
+        unpack!(BCCH_DL_SCH_MessageType_c1::SystemInformationBlockType1(sib1) = c1);
+        let cell = context.cell?;
+        let identity = CellIdentity::from_sib1(sib1);
+
+        let (plmn, cell_identity) = identity.global_id();
+        let pcis = self.pcis.entry((plmn.cloned(), cell_identity)).or_default();
+        let new_pci = pcis.insert(cell.phy_cell_id) && pcis.len() > 1;
+        let previous_identity = self.identities.insert(cell, identity.clone());
+
+        if let Some(previous_identity) = previous_identity {
+            if previous_identity != identity {
+                return Some(Event {
+                    event_type: EventType::QualitativeWarning { severity: Severity::Medium },
+                    message: format!(
+                        "Cell with PCI {} on EARFCN {} changed its identity from {} to {}",
+                        cell.phy_cell_id, cell.earfcn, previous_identity, identity,
+                    ),
+                });
+            }
+        }
+        if !new_pci {
+            return None;
+        }
+        let mut previous_pcis: Vec<u16> = self.pcis[&(plmn.cloned(), cell_identity)].iter()
+            .copied()
+            .filter(|pci| *pci != cell.phy_cell_id)
+            .collect();
+        previous_pcis.sort_unstable();
+        let previous_pcis: Vec<String> = previous_pcis.iter().map(|pci| pci.to_string()).collect();
+        Some(Event {
+            event_type: EventType::QualitativeWarning { severity: Severity::Medium },
+            message: format!(
+                "Cell ID {} was advertised by PCI {} on EARFCN {}, but was previously seen on PCI {}",
+                cell_identity, cell.phy_cell_id, cell.earfcn, previous_pcis.join(", "),
+            ),
+        })
+    }
+
+    fn on_recording_start(&mut self) {
+        self.identities.clear();
+        self.pcis.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::test_util::{context_on, sib1};
+
+    fn context(phy_cell_id: u16) -> PacketContext {
+        context_on(1, phy_cell_id)
+    }
+
+    #[test]
+    fn test_identity_change() {
+        let mut analyzer = Sib1CellIdentityAnalyzer::default();
+        let original = sib1(|_| {});
+        assert!(analyzer.analyze_information_element(&original, &context(1)).is_none());
+        assert!(analyzer.analyze_information_element(&original, &context(1)).is_none());
+
+        let new_tac = sib1(|sib1| {
+            let tac = &mut sib1.cell_access_related_info.tracking_area_code.0;
+            let bit = tac[0];
+            tac.set(0, !bit);
+        });
+        let event = analyzer.analyze_information_element(&new_tac, &context(1)).unwrap();
+        assert!(event.message.contains("changed its identity"));
+        assert!(analyzer.analyze_information_element(&new_tac, &context(1)).is_none());
+    }
+
+    #[test]
+    fn test_cell_identity_on_several_pcis() {
+        let mut analyzer = Sib1CellIdentityAnalyzer::default();
+        let original = sib1(|_| {});
+        assert!(analyzer.analyze_information_element(&original, &context(1)).is_none());
+        let event = analyzer.analyze_information_element(&original, &context(2)).unwrap();
+        assert!(event.message.contains("previously seen on PCI 1"));
+
+        // going back and forth between known PCIs is only reported once
+        assert!(analyzer.analyze_information_element(&original, &context(1)).is_none());
+        assert!(analyzer.analyze_information_element(&original, &context(2)).is_none());
+
+        // every PCI the cell ID was seen on is listed
+        let event = analyzer.analyze_information_element(&original, &context(10)).unwrap();
+        assert!(event.message.ends_with("previously seen on PCI 1, 2"));
+
+        analyzer.on_recording_start();
+        assert!(analyzer.analyze_information_element(&original, &context(2)).is_none());
+    }
+}
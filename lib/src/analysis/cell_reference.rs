@@ -0,0 +1,425 @@
+//! An offline reference of known cell towers, imported from a CSV dump such as
+//! those published by OpenCelliD, which analyzers can check the cells we see
+//! against.
+//!
+//! The CSV needs a header row naming its MCC, MNC (or "net"), TAC (or "area")
+//! and cell ID (or "cell") columns, and may have an EARFCN column. If there's
+//! a "radio" column, only LTE rows are imported. Headerless OpenCelliD dumps,
+//! which start with the radio type, are also accepted. Rows we can't parse are
+//! skipped and counted.
+//!
+//! Imported references are saved as a compact binary index of fixed-size
+//! records sorted by cell identity, so even large regional dumps can be held
+//! in memory and searched quickly.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use thiserror::Error;
+
+const INDEX_MAGIC: &[u8; 4] = b"RHCR";
+const INDEX_VERSION: u32 = 3;
+const INDEX_HEADER_LEN: usize = 20;
+const RECORD_LEN: usize = 17;
+// stored in place of an EARFCN for records which didn't list one
+const NO_EARFCN: u32 = u32::MAX;
+
+// The column order of headerless OpenCelliD dumps
+const OPENCELLID_COLUMNS: Columns = Columns { radio: Some(0), mcc: 1, mnc: 2, tac: 3, cell_identity: 4, earfcn: None };
+
+#[derive(Error, Debug)]
+pub enum CellReferenceError {
+    #[error("Failed to read cell reference {0}: {1}")]
+    ReadError(PathBuf, std::io::Error),
+    #[error("Failed to write cell reference {0}: {1}")]
+    WriteError(PathBuf, std::io::Error),
+    #[error("{0} isn't a valid cell reference index")]
+    InvalidIndex(PathBuf),
+    #[error("Cell reference CSV has no {0} column")]
+    MissingColumn(&'static str),
+}
+
+/// A network's MCC and MNC. MNCs are two or three digits long, and e.g. "01"
+/// and "001" are different networks, so we keep track of how many digits the
+/// MNC was written with, if we know.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Plmn {
+    pub mcc: u16,
+    pub mnc: u16,
+    /// `None` if the MNC was written as a plain number, as OpenCelliD does,
+    /// in which case it matches an MNC of either length.
+    pub mnc_digits: Option<u8>,
+}
+
+impl Plmn {
+    /// Parses an MCC and MNC as they were written. Only an MNC with three
+    /// digits or a leading zero is known to be padded, so e.g. "30" could be
+    /// either "30" or "030".
+    pub fn parse(mcc: &str, mnc: &str) -> Option<Self> {
+        let is_number = |digits: &str| !digits.is_empty() && digits.bytes().all(|digit| digit.is_ascii_digit());
+        if !is_number(mcc) || !is_number(mnc) || mnc.len() > 3 {
+            return None;
+        }
+        let padded = mnc.len() == 3 || (mnc.len() == 2 && mnc.starts_with('0'));
+        Some(Plmn {
+            mcc: mcc.parse().ok()?,
+            mnc: mnc.parse().ok()?,
+            mnc_digits: padded.then_some(mnc.len() as u8),
+        })
+    }
+
+    /// Whether the two could be the same network, treating an unknown MNC
+    /// length as matching either.
+    pub fn matches(&self, other: &Plmn) -> bool {
+        self.mcc == other.mcc && self.mnc == other.mnc
+            && (self.mnc_digits.is_none() || other.mnc_digits.is_none() || self.mnc_digits == other.mnc_digits)
+    }
+}
+
+/// One cell listed in the reference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ReferenceCell {
+    pub plmn: Plmn,
+    pub cell_identity: u32,
+    pub tac: u32,
+    pub earfcn: Option<u32>,
+}
+
+impl ReferenceCell {
+    fn to_bytes(self) -> [u8; RECORD_LEN] {
+        let mut bytes = [0; RECORD_LEN];
+        bytes[0..2].copy_from_slice(&self.plmn.mcc.to_le_bytes());
+        bytes[2..4].copy_from_slice(&self.plmn.mnc.to_le_bytes());
+        bytes[4] = self.plmn.mnc_digits.unwrap_or(0);
+        bytes[5..9].copy_from_slice(&self.cell_identity.to_le_bytes());
+        bytes[9..13].copy_from_slice(&self.tac.to_le_bytes());
+        bytes[13..17].copy_from_slice(&self.earfcn.unwrap_or(NO_EARFCN).to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        let u32_at = |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        ReferenceCell {
+            plmn: Plmn { mcc: u16_at(0), mnc: u16_at(2), mnc_digits: Some(bytes[4]).filter(|digits| *digits != 0) },
+            cell_identity: u32_at(5),
+            tac: u32_at(9),
+            earfcn: Some(u32_at(13)).filter(|earfcn| *earfcn != NO_EARFCN),
+        }
+    }
+}
+
+/// Summarizes a reference for the daemon's API.
+#[derive(Debug, Clone, Serialize)]
+pub struct CellReferenceStatus {
+    pub cells: usize,
+    pub tracking_areas: usize,
+    pub imported_at: Option<DateTime<Utc>>,
+}
+
+/// The reference is swapped out whenever a new one is imported, so is shared
+/// between every [Harness](super::analyzer::Harness) and the daemon's API.
+pub type SharedCellReference = Arc<RwLock<CellReference>>;
+
+#[derive(Debug, Default)]
+pub struct CellReference {
+    // sorted by MCC, MNC and cell identity, so the records for a cell
+    // identity can be binary searched whatever their MNC length
+    cells: Vec<ReferenceCell>,
+    // every (PLMN, TAC) the reference lists a cell in
+    tracking_areas: HashSet<(Plmn, u32)>,
+    imported_at: Option<DateTime<Utc>>,
+}
+
+impl CellReference {
+    fn new(mut cells: Vec<ReferenceCell>, imported_at: Option<DateTime<Utc>>) -> Self {
+        cells.sort_by_key(|cell| (cell.plmn.mcc, cell.plmn.mnc, cell.cell_identity, *cell));
+        cells.dedup();
+        let tracking_areas = cells.iter().map(|cell| (cell.plmn, cell.tac)).collect();
+        CellReference { cells, tracking_areas, imported_at }
+    }
+
+    /// Reads a CSV file, as described in the [module docs](self). Returns the
+    /// reference, and how many rows were skipped.
+    pub fn from_csv_file(path: &Path) -> Result<(Self, usize), CellReferenceError> {
+        let read_error = |err| CellReferenceError::ReadError(path.to_path_buf(), err);
+        let file = File::open(path).map_err(read_error)?;
+        let mut importer = CsvImporter::default();
+        for line in BufReader::new(file).lines() {
+            importer.add_line(&line.map_err(read_error)?)?;
+        }
+        Ok(importer.finish())
+    }
+
+    /// Loads an index written by [CellReference::save], or an empty reference
+    /// if there isn't one.
+    pub fn load(path: &Path) -> Result<Self, CellReferenceError> {
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(CellReference::default()),
+            Err(err) => return Err(CellReferenceError::ReadError(path.to_path_buf(), err)),
+        };
+        let invalid = || CellReferenceError::InvalidIndex(path.to_path_buf());
+        if bytes.len() < INDEX_HEADER_LEN || &bytes[0..4] != INDEX_MAGIC {
+            return Err(invalid());
+        }
+        let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        let imported_at = i64::from_le_bytes(bytes[8..16].try_into().unwrap());
+        let count = u32::from_le_bytes(bytes[16..20].try_into().unwrap()) as usize;
+        let records = &bytes[INDEX_HEADER_LEN..];
+        if version != INDEX_VERSION || count.checked_mul(RECORD_LEN) != Some(records.len()) {
+            return Err(invalid());
+        }
+        let cells = records.chunks_exact(RECORD_LEN).map(ReferenceCell::from_bytes).collect();
+        Ok(CellReference::new(cells, DateTime::from_timestamp(imported_at, 0)))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), CellReferenceError> {
+        let mut bytes = Vec::with_capacity(INDEX_HEADER_LEN + self.cells.len() * RECORD_LEN);
+        bytes.extend_from_slice(INDEX_MAGIC);
+        bytes.extend_from_slice(&INDEX_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.imported_at.map_or(0, |time| time.timestamp()).to_le_bytes());
+        bytes.extend_from_slice(&(self.cells.len() as u32).to_le_bytes());
+        for cell in &self.cells {
+            bytes.extend_from_slice(&cell.to_bytes());
+        }
+        let write_error = |err| CellReferenceError::WriteError(path.to_path_buf(), err);
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, bytes).map_err(write_error)?;
+        std::fs::rename(&tmp_path, path).map_err(write_error)
+    }
+
+    /// Returns every record for a cell identity, which may list it in several
+    /// tracking areas or on several EARFCNs.
+    pub fn lookup(&self, plmn: Plmn, cell_identity: u32) -> Vec<ReferenceCell> {
+        let key = (plmn.mcc, plmn.mnc, cell_identity);
+        let start = self.cells.partition_point(|cell| (cell.plmn.mcc, cell.plmn.mnc, cell.cell_identity) < key);
+        self.cells[start..].iter()
+            .take_while(|cell| (cell.plmn.mcc, cell.plmn.mnc, cell.cell_identity) == key)
+            .filter(|cell| cell.plmn.matches(&plmn))
+            .copied()
+            .collect()
+    }
+
+    /// Whether the reference lists any cells in a tracking area. If it
+    /// doesn't, we're probably outside the region it covers.
+    pub fn covers(&self, plmn: Plmn, tac: u32) -> bool {
+        [None, Some(2), Some(3)].into_iter()
+            .map(|mnc_digits| Plmn { mnc_digits, ..plmn })
+            .filter(|candidate| candidate.matches(&plmn))
+            .any(|candidate| self.tracking_areas.contains(&(candidate, tac)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn status(&self) -> CellReferenceStatus {
+        CellReferenceStatus {
+            cells: self.cells.len(),
+            tracking_areas: self.tracking_areas.len(),
+            imported_at: self.imported_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Columns {
+    radio: Option<usize>,
+    mcc: usize,
+    mnc: usize,
+    tac: usize,
+    cell_identity: usize,
+    earfcn: Option<usize>,
+}
+
+impl Columns {
+    // Returns None if the line doesn't look like a header
+    fn from_header(fields: &[&str]) -> Result<Option<Self>, CellReferenceError> {
+        let names: Vec<String> = fields.iter().map(|field| field.to_lowercase().replace([' ', '_'], "")).collect();
+        let find = |aliases: &[&str]| names.iter().position(|name| aliases.contains(&name.as_str()));
+        let Some(mcc) = find(&["mcc"]) else {
+            return Ok(None);
+        };
+        Ok(Some(Columns {
+            radio: find(&["radio"]),
+            mcc,
+            mnc: find(&["mnc", "net"]).ok_or(CellReferenceError::MissingColumn("MNC"))?,
+            tac: find(&["tac", "area"]).ok_or(CellReferenceError::MissingColumn("TAC"))?,
+            cell_identity: find(&["cellid", "cell", "ci", "eci"]).ok_or(CellReferenceError::MissingColumn("cell ID"))?,
+            earfcn: find(&["earfcn", "arfcn"]),
+        }))
+    }
+
+    fn parse(&self, fields: &[&str]) -> Option<ReferenceCell> {
+        if let Some(radio) = self.radio {
+            if !fields.get(radio)?.eq_ignore_ascii_case("LTE") {
+                return None;
+            }
+        }
+        let earfcn = match self.earfcn.and_then(|i| fields.get(i)).filter(|field| !field.is_empty()) {
+            Some(field) => Some(field.parse().ok()?),
+            None => None,
+        };
+        Some(ReferenceCell {
+            plmn: Plmn::parse(fields.get(self.mcc)?, fields.get(self.mnc)?)?,
+            cell_identity: fields.get(self.cell_identity)?.parse().ok()?,
+            tac: fields.get(self.tac)?.parse().ok()?,
+            earfcn,
+        })
+    }
+}
+
+/// Builds a [CellReference] from a CSV one line at a time, so large dumps can
+/// be streamed in without holding the whole file in memory.
+#[derive(Debug, Default)]
+pub struct CsvImporter {
+    columns: Option<Columns>,
+    cells: Vec<ReferenceCell>,
+    skipped: usize,
+}
+
+impl CsvImporter {
+    pub fn add_line(&mut self, line: &str) -> Result<(), CellReferenceError> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(());
+        }
+        let fields: Vec<&str> = line.split(',').map(|field| field.trim().trim_matches('"')).collect();
+        let columns = match self.columns {
+            Some(columns) => columns,
+            None => match Columns::from_header(&fields)? {
+                Some(columns) => {
+                    self.columns = Some(columns);
+                    return Ok(());
+                },
+                // headerless dumps start straight away with a row of data
+                None => *self.columns.insert(OPENCELLID_COLUMNS),
+            },
+        };
+        match columns.parse(&fields) {
+            Some(cell) => self.cells.push(cell),
+            None => self.skipped += 1,
+        }
+        Ok(())
+    }
+
+    /// Returns the imported reference, and how many rows were skipped.
+    pub fn finish(self) -> (CellReference, usize) {
+        (CellReference::new(self.cells, Some(Utc::now())), self.skipped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plmn(mcc: &str, mnc: &str) -> Plmn {
+        Plmn::parse(mcc, mnc).unwrap()
+    }
+
+    #[test]
+    fn test_import_csv() {
+        let mut importer = CsvImporter::default();
+        for line in [
+            "MCC,MNC,TAC,Cell ID,EARFCN",
+            "310,260,100,12345,5230",
+            "310,260,100,12345,66786",
+            "310,260,101,12346,",
+            "310,260,not a TAC,12347,5230",
+        ] {
+            importer.add_line(line).unwrap();
+        }
+        let (reference, skipped) = importer.finish();
+        assert_eq!(skipped, 1);
+        let earfcns: Vec<Option<u32>> = reference.lookup(plmn("310", "260"), 12345).iter().map(|cell| cell.earfcn).collect();
+        assert_eq!(earfcns, vec![Some(5230), Some(66786)]);
+        assert_eq!(reference.lookup(plmn("310", "260"), 12346)[0].earfcn, None);
+        assert!(reference.lookup(plmn("310", "26"), 12345).is_empty());
+        assert!(reference.covers(plmn("310", "260"), 101));
+        assert!(!reference.covers(plmn("310", "260"), 102));
+    }
+
+    #[test]
+    fn test_mnc_digits() {
+        let mut importer = CsvImporter::default();
+        for line in ["mcc,mnc,tac,cellid", "001,01,1,100", "001,001,2,100", "001,1,3,200"] {
+            importer.add_line(line).unwrap();
+        }
+        let (reference, skipped) = importer.finish();
+        assert_eq!(skipped, 0);
+        assert_eq!(reference.lookup(plmn("001", "01"), 100)[0].tac, 1);
+        assert_eq!(reference.lookup(plmn("001", "001"), 100)[0].tac, 2);
+        // an MNC written as a plain number could have either length
+        assert_eq!(plmn("001", "1").mnc_digits, None);
+        assert_eq!(reference.lookup(plmn("001", "01"), 200)[0].tac, 3);
+        assert_eq!(reference.lookup(plmn("001", "001"), 200)[0].tac, 3);
+        assert!(reference.covers(plmn("001", "001"), 3));
+        assert!(!reference.covers(plmn("001", "001"), 1));
+        assert!(Plmn::parse("001", "+1").is_none());
+        assert!(Plmn::parse("001", "0001").is_none());
+    }
+
+    #[test]
+    fn test_import_headerless_opencellid() {
+        let mut importer = CsvImporter::default();
+        for line in [
+            "LTE,310,260,100,12345,0,-122.4,37.7,1000,10,1,1500000000,1600000000,0",
+            "GSM,310,260,100,5,0,-122.4,37.7,1000,10,1,1500000000,1600000000,0",
+        ] {
+            importer.add_line(line).unwrap();
+        }
+        let (reference, skipped) = importer.finish();
+        assert_eq!(skipped, 1);
+        assert_eq!(reference.status().cells, 1);
+        assert_eq!(reference.lookup(plmn("310", "260"), 12345)[0].tac, 100);
+    }
+
+    #[test]
+    fn test_import_headerless_opencellid_unpadded_mnc() {
+        // OpenCelliD stores the MNC as a number, so 310-030 is written as 30
+        let mut importer = CsvImporter::default();
+        importer.add_line("LTE,310,30,100,12345,0,-122.4,37.7,1000,10,1,1500000000,1600000000,0").unwrap();
+        let (reference, skipped) = importer.finish();
+        assert_eq!(skipped, 0);
+        assert_eq!(reference.lookup(plmn("310", "030"), 12345)[0].tac, 100);
+        assert_eq!(reference.lookup(plmn("310", "30"), 12345)[0].tac, 100);
+        assert!(reference.lookup(plmn("310", "300"), 12345).is_empty());
+        assert!(reference.covers(plmn("310", "030"), 100));
+    }
+
+    #[test]
+    fn test_missing_column() {
+        let mut importer = CsvImporter::default();
+        assert!(matches!(importer.add_line("mcc,mnc,cell"), Err(CellReferenceError::MissingColumn("TAC"))));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("rayhunter_cell_reference_{}.bin", std::process::id()));
+        assert!(CellReference::load(&path).unwrap().is_empty());
+        let mut importer = CsvImporter::default();
+        importer.add_line("mcc,net,area,cell,earfcn").unwrap();
+        importer.add_line("310,260,100,12345,5230").unwrap();
+        importer.add_line("310,260,100,12346,").unwrap();
+        let (reference, _) = importer.finish();
+        reference.save(&path).unwrap();
+
+        let loaded = CellReference::load(&path).unwrap();
+        assert_eq!(loaded.cells, reference.cells);
+        assert_eq!(loaded.imported_at.map(|time| time.timestamp()), reference.imported_at.map(|time| time.timestamp()));
+        // a record count which would overflow
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&path, bytes).unwrap();
+        assert!(matches!(CellReference::load(&path), Err(CellReferenceError::InvalidIndex(_))));
+        std::fs::write(&path, b"not an index").unwrap();
+        assert!(matches!(CellReference::load(&path), Err(CellReferenceError::InvalidIndex(_))));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
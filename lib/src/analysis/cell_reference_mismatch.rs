@@ -0,0 +1,195 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use telcom_parser::lte_rrc::{BCCH_DL_SCH_MessageType, BCCH_DL_SCH_MessageType_c1};
+
+use super::analyzer::{Analyzer, Event, EventType, Interests, Severity};
+use super::cell_identity::CellIdentity;
+use super::cell_reference::{Plmn, SharedCellReference};
+use super::context::{PacketContext, PhysicalCell};
+use super::information_element::{InformationElement, LteChannel, LteInformationElement};
+use super::util::unpack;
+
+// Parses a PLMN formatted as "MCC-MNC" into the form the reference uses
+fn parse_plmn(plmn: &str) -> Option<Plmn> {
+    let (mcc, mnc) = plmn.split_once('-')?;
+    Plmn::parse(mcc, mnc)
+}
+
+fn join<T: ToString>(values: impl Iterator<Item = T>) -> String {
+    values.map(|value| value.to_string()).collect::<Vec<_>>().join("/")
+}
+
+/// Checks the identity each cell advertises in its SIB1 against an imported
+/// [cell reference](super::cell_reference). A cell missing from a tracking
+/// area the reference covers, or broadcasting on a different EARFCN than the
+/// real cell with its identity, may be a fake base station. Cells in tracking
+/// areas the reference doesn't cover aren't reported, since we're probably
+/// outside the region it was made for.
+pub struct CellReferenceMismatchAnalyzer {
+    reference: SharedCellReference,
+    // the cells we've already checked in this recording
+    checked: HashSet<(PhysicalCell, CellIdentity)>,
+}
+
+impl CellReferenceMismatchAnalyzer {
+    pub fn new(reference: SharedCellReference) -> Self {
+        CellReferenceMismatchAnalyzer {
+            reference,
+            checked: HashSet::new(),
+        }
+    }
+
+    fn check(&self, cell: PhysicalCell, identity: &CellIdentity) -> Option<Event> {
+        let plmn = parse_plmn(identity.plmns.first()?)?;
+        let reference = self.reference.read().unwrap();
+        let listed = reference.lookup(plmn, identity.cell_identity);
+        if listed.is_empty() {
+            if !reference.covers(plmn, identity.tac) {
+                return None;
+            }
+            return Some(Event {
+                event_type: EventType::QualitativeWarning { severity: Severity::Medium },
+                message: format!(
+                    "Cell ({}, PCI {} on EARFCN {}) isn't in the cell reference, though it lists other cells in that tracking area",
+                    identity, cell.phy_cell_id, cell.earfcn,
+                ),
+            });
+        }
+
+        // records without an EARFCN can't contradict the one we saw
+        let earfcns: Vec<u32> = listed.iter().filter_map(|listed| listed.earfcn).collect();
+        if !earfcns.is_empty() && !earfcns.contains(&cell.earfcn) {
+            return Some(Event {
+                event_type: EventType::QualitativeWarning { severity: Severity::Medium },
+                message: format!(
+                    "Cell ({}, PCI {}) is on EARFCN {}, but the cell reference lists it on EARFCN {}",
+                    identity, cell.phy_cell_id, cell.earfcn, join(earfcns.iter()),
+                ),
+            });
+        }
+        if !listed.iter().any(|listed| listed.tac == identity.tac) {
+            // operators do occasionally re-plan their tracking areas
+            return Some(Event {
+                event_type: EventType::QualitativeWarning { severity: Severity::Low },
+                message: format!(
+                    "Cell ({}, PCI {} on EARFCN {}) is listed in the cell reference under TAC {}",
+                    identity, cell.phy_cell_id, cell.earfcn, join(listed.iter().map(|listed| listed.tac)),
+                ),
+            });
+        }
+        None
+    }
+}
+
+impl Analyzer for CellReferenceMismatchAnalyzer {
+    fn get_id(&self) -> Cow<str> {
+        Cow::from("cell_reference_mismatch")
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Cell Reference Mismatch")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from("Tests whether a cell's SIB1 identity is missing from the imported cell reference, or is on a different EARFCN or TAC than the reference lists.")
+    }
+
+    fn get_interests(&self) -> Interests {
+        Interests::lte_channels([LteChannel::BcchDlSch])
+    }
+
+    fn analyze_information_element(&mut self, ie: &InformationElement, context: &PacketContext) -> Option<Event> {
+        unpack!(InformationElement::LTE(lte_ie) = ie);
+        unpack!(LteInformationElement::BcchDlSch(bcch_dl_sch_message) = &**lte_ie);
+        unpack!(BCCH_DL_SCH_MessageType::C1(BCCH_DL_SCH_MessageType_c1::SystemInformationBlockType1(sib1)) = &bcch_dl_sch_message.message);
+        let cell = context.cell?;
+        let identity = CellIdentity::from_sib1(sib1);
+        if !self.checked.insert((cell, identity.clone())) {
+            return None;
+        }
+        self.check(cell, &identity)
+    }
+
+    fn on_recording_start(&mut self) {
+        self.checked.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, RwLock};
+    use crate::analysis::cell_reference::CsvImporter;
+    use crate::analysis::test_util::{self, context_on};
+    use telcom_parser::lte_rrc::{MCC_MNC_Digit, MNC};
+
+    fn sib1() -> (InformationElement, CellIdentity) {
+        let mut identity = None;
+        let ie = test_util::sib1(|sib1| identity = Some(CellIdentity::from_sib1(sib1)));
+        (ie, identity.unwrap())
+    }
+
+    fn analyzer(rows: &[String]) -> CellReferenceMismatchAnalyzer {
+        let mut importer = CsvImporter::default();
+        importer.add_line("mcc,mnc,tac,cellid,earfcn").unwrap();
+        for row in rows {
+            importer.add_line(row).unwrap();
+        }
+        CellReferenceMismatchAnalyzer::new(Arc::new(RwLock::new(importer.finish().0)))
+    }
+
+    fn context() -> PacketContext {
+        context_on(5230, 1)
+    }
+
+    #[test]
+    fn test_cell_reference_mismatches() {
+        let (ie, identity) = sib1();
+        let (mcc, mnc) = identity.plmns[0].split_once('-').unwrap();
+        let (tac, cell_identity) = (identity.tac, identity.cell_identity);
+
+        let mut listed = analyzer(&[format!("{},{},{},{},5230", mcc, mnc, tac, cell_identity)]);
+        assert!(listed.analyze_information_element(&ie, &context()).is_none());
+
+        let mut uncovered = analyzer(&[format!("{},{},{},{},5230", mcc, mnc, tac + 1, cell_identity + 1)]);
+        assert!(uncovered.analyze_information_element(&ie, &context()).is_none());
+
+        let mut missing = analyzer(&[format!("{},{},{},{},5230", mcc, mnc, tac, cell_identity + 1)]);
+        let event = missing.analyze_information_element(&ie, &context()).unwrap();
+        assert!(event.message.ends_with("isn't in the cell reference, though it lists other cells in that tracking area"));
+        // each cell is only reported once per recording
+        assert!(missing.analyze_information_element(&ie, &context()).is_none());
+
+        let mut wrong_earfcn = analyzer(&[format!("{},{},{},{},66786", mcc, mnc, tac, cell_identity)]);
+        let event = wrong_earfcn.analyze_information_element(&ie, &context()).unwrap();
+        assert_eq!(event.event_type, EventType::QualitativeWarning { severity: Severity::Medium });
+        assert!(event.message.ends_with("is on EARFCN 5230, but the cell reference lists it on EARFCN 66786"));
+
+        let mut wrong_tac = analyzer(&[format!("{},{},{},{},", mcc, mnc, tac + 1, cell_identity)]);
+        let event = wrong_tac.analyze_information_element(&ie, &context()).unwrap();
+        assert_eq!(event.event_type, EventType::QualitativeWarning { severity: Severity::Low });
+    }
+
+    #[test]
+    fn test_mnc_digits() {
+        // the captured SIB1 with its MNC changed to "01"
+        let ie = test_util::sib1(|sib1| {
+            sib1.cell_access_related_info.plmn_identity_list.0[0].plmn_identity.mnc = MNC(vec![MCC_MNC_Digit(0), MCC_MNC_Digit(1)]);
+        });
+        let (_, identity) = sib1();
+        let (mcc, _) = identity.plmns[0].split_once('-').unwrap();
+        let (tac, cell_identity) = (identity.tac, identity.cell_identity);
+
+        let mut two_digits = analyzer(&[format!("{},01,{},{},5230", mcc, tac, cell_identity + 1)]);
+        assert!(two_digits.analyze_information_element(&ie, &context()).is_some());
+        // "001" is a different network, whose tracking areas the reference
+        // doesn't tell us anything about
+        let mut three_digits = analyzer(&[format!("{},001,{},{},5230", mcc, tac, cell_identity + 1)]);
+        assert!(three_digits.analyze_information_element(&ie, &context()).is_none());
+    }
+}
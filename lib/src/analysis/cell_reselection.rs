@@ -0,0 +1,338 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use telcom_parser::lte_rrc::{BCCH_DL_SCH_MessageType, BCCH_DL_SCH_MessageType_c1, SIB_Type, SystemInformationBlockType1, SystemInformationBlockType3, SystemInformationBlockType5, SystemInformation_r8_IEsSib_TypeAndInfo_Entry};
+
+use super::analyzer::{Analyzer, Event, EventType, Interests, Severity};
+use super::config::CellReselectionParams;
+use super::context::{PacketContext, PhysicalCell};
+use super::information_element::{InformationElement, LteChannel, LteInformationElement};
+use super::util::{system_information_blocks, unpack};
+
+// q-Hyst is an enumeration of these values, in dB
+const Q_HYST_DB: [u8; 16] = [0, 1, 2, 3, 4, 5, 6, 8, 10, 12, 14, 16, 18, 20, 22, 24];
+const MAX_CELL_RESELECTION_PRIORITY: u8 = 7;
+
+// q-RxLevMin is sent in units of 2 dBm
+fn q_rx_lev_min_dbm(value: i8) -> i32 {
+    value as i32 * 2
+}
+
+/// The serving cell's reselection parameters, from SIB3.
+#[derive(Debug, Clone, Copy)]
+struct ServingParams {
+    q_hyst_db: u8,
+    q_rx_lev_min_dbm: i32,
+    priority: u8,
+}
+
+impl From<&SystemInformationBlockType3> for ServingParams {
+    fn from(sib3: &SystemInformationBlockType3) -> Self {
+        ServingParams {
+            q_hyst_db: Q_HYST_DB.get(sib3.cell_reselection_info_common.q_hyst.0 as usize).copied().unwrap_or(0),
+            q_rx_lev_min_dbm: q_rx_lev_min_dbm(sib3.intra_freq_cell_reselection_info.q_rx_lev_min.0),
+            priority: sib3.cell_reselection_serving_freq_info.cell_reselection_priority.0,
+        }
+    }
+}
+
+/// The reselection parameters of an inter-frequency neighbour, from SIB5.
+#[derive(Debug, Clone, Copy)]
+struct NeighbourParams {
+    earfcn: u16,
+    q_rx_lev_min_dbm: i32,
+    priority: Option<u8>,
+}
+
+fn neighbour_params(sib5: &SystemInformationBlockType5) -> Vec<NeighbourParams> {
+    sib5.inter_freq_carrier_freq_list.0.iter()
+        .map(|carrier| NeighbourParams {
+            earfcn: carrier.dl_carrier_freq.0,
+            q_rx_lev_min_dbm: q_rx_lev_min_dbm(carrier.q_rx_lev_min.0),
+            priority: carrier.cell_reselection_priority.as_ref().map(|priority| priority.0),
+        })
+        .collect()
+}
+
+fn schedules_sib5(sib1: &SystemInformationBlockType1) -> bool {
+    sib1.scheduling_info_list.0.iter()
+        .flat_map(|info| info.sib_mapping_info.0.iter())
+        .any(|sib_type| sib_type.0 == SIB_Type::SIB_TYPE5)
+}
+
+fn sib1(ie: &InformationElement) -> Option<&SystemInformationBlockType1> {
+    unpack!(InformationElement::LTE(lte_ie) = ie);
+    unpack!(LteInformationElement::BcchDlSch(bcch_dl_sch_message) = &**lte_ie);
+    unpack!(BCCH_DL_SCH_MessageType::C1(BCCH_DL_SCH_MessageType_c1::SystemInformationBlockType1(sib1)) = &bcch_dl_sch_message.message);
+    Some(sib1)
+}
+
+#[derive(Default)]
+struct CellState {
+    serving: Option<ServingParams>,
+    // None until we've seen the cell's SIB5, or its SIB1 says it doesn't
+    // send one
+    neighbours: Option<Vec<NeighbourParams>>,
+}
+
+/// Fake base stations attract phones by broadcasting reselection parameters
+/// which make them look better than any real cell: a high serving cell
+/// priority, a very low minimum receive level, or a large hysteresis to keep
+/// phones from leaving. This checks SIB3 and SIB5 for parameters outside of
+/// sane ranges, or which favour the cell over all the neighbours it
+/// advertises itself.
+pub struct CellReselectionAnalyzer {
+    params: CellReselectionParams,
+    cells: HashMap<PhysicalCell, CellState>,
+}
+
+impl CellReselectionAnalyzer {
+    pub fn new(params: CellReselectionParams) -> Self {
+        Self { params, cells: HashMap::new() }
+    }
+
+    fn anomalies(&self, state: &CellState) -> Vec<String> {
+        let mut anomalies = Vec::new();
+        let Some(serving) = state.serving else {
+            return anomalies;
+        };
+        if serving.q_rx_lev_min_dbm < self.params.min_q_rx_lev_min_dbm {
+            anomalies.push(format!(
+                "q-RxLevMin of {} dBm (below {} dBm)",
+                serving.q_rx_lev_min_dbm, self.params.min_q_rx_lev_min_dbm,
+            ));
+        }
+        if serving.q_hyst_db > self.params.max_q_hyst_db {
+            anomalies.push(format!("q-Hyst of {} dB (above {} dB)", serving.q_hyst_db, self.params.max_q_hyst_db));
+        }
+
+        // until we know the neighbours, we can't tell whether a high
+        // priority is out of the ordinary
+        let Some(neighbours) = &state.neighbours else {
+            return anomalies;
+        };
+        let neighbour_priorities: Vec<u8> = neighbours.iter().filter_map(|neighbour| neighbour.priority).collect();
+        if serving.priority == MAX_CELL_RESELECTION_PRIORITY && neighbour_priorities.iter().all(|priority| *priority < serving.priority) {
+            if neighbour_priorities.is_empty() {
+                anomalies.push(format!("serving cell priority of {}", serving.priority));
+            } else {
+                anomalies.push(format!(
+                    "serving cell priority of {}, above all of its neighbouring frequencies ({})",
+                    serving.priority,
+                    neighbour_priorities.iter().map(|priority| priority.to_string()).collect::<Vec<_>>().join(", "),
+                ));
+            }
+        }
+        for neighbour in neighbours {
+            let difference = neighbour.q_rx_lev_min_dbm - serving.q_rx_lev_min_dbm;
+            if difference >= self.params.max_q_rx_lev_min_difference_db {
+                anomalies.push(format!(
+                    "q-RxLevMin of {} dBm, {} dB below its neighbour on EARFCN {}",
+                    serving.q_rx_lev_min_dbm, difference, neighbour.earfcn,
+                ));
+            }
+        }
+        anomalies
+    }
+
+    fn event(&self, cell: PhysicalCell) -> Option<Event> {
+        let anomalies = self.anomalies(&self.cells[&cell]);
+        if anomalies.is_empty() {
+            return None;
+        }
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: if anomalies.len() > 1 { Severity::Medium } else { Severity::Low },
+            },
+            message: format!(
+                "Cell with PCI {} on EARFCN {} broadcast suspicious reselection parameters: {}",
+                cell.phy_cell_id, cell.earfcn, anomalies.join("; "),
+            ),
+        })
+    }
+}
+
+impl Analyzer for CellReselectionAnalyzer {
+    fn get_id(&self) -> Cow<str> {
+        Cow::from("cell_reselection_anomaly")
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Cell Reselection Parameter Anomaly")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from("Tests whether a cell's SIB3/SIB5 reselection parameters are outside of sane ranges, or favour it over all of its neighbours.")
+    }
+
+    fn get_interests(&self) -> Interests {
+        Interests::lte_channels([LteChannel::BcchDlSch])
+    }
+
+    fn analyze_information_element(&mut self, ie: &InformationElement, context: &PacketContext) -> Option<Event> {
+        let cell = context.cell?;
+        if let Some(sib1) = sib1(ie) {
+            // a cell without SIB5 has no inter-frequency neighbours at all
+            let state = self.cells.entry(cell).or_default();
+            if schedules_sib5(sib1) || state.neighbours.is_some() {
+                return None;
+            }
+            state.neighbours = Some(Vec::new());
+            return self.event(cell);
+        }
+
+        let sibs = system_information_blocks(ie)?;
+        let state = self.cells.entry(cell).or_default();
+        let mut updated = false;
+        for sib in sibs {
+            match sib {
+                SystemInformation_r8_IEsSib_TypeAndInfo_Entry::Sib3(sib3) => {
+                    state.serving = Some(ServingParams::from(sib3));
+                    updated = true;
+                },
+                SystemInformation_r8_IEsSib_TypeAndInfo_Entry::Sib5(sib5) => {
+                    state.neighbours = Some(neighbour_params(sib5));
+                    updated = true;
+                },
+                _ => {},
+            }
+        }
+        if !updated {
+            return None;
+        }
+        self.event(cell)
+    }
+
+    fn on_recording_start(&mut self) {
+        self.cells.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::test_util::{self, context_on};
+    use crate::analysis::information_element::LteInformationElement;
+    use telcom_parser::lte_rrc::*;
+
+    fn sib3(q_hyst: u8, q_rx_lev_min: i8, priority: u8) -> SystemInformation_r8_IEsSib_TypeAndInfo_Entry {
+        SystemInformation_r8_IEsSib_TypeAndInfo_Entry::Sib3(SystemInformationBlockType3 {
+            cell_reselection_info_common: SystemInformationBlockType3CellReselectionInfoCommon {
+                q_hyst: SystemInformationBlockType3CellReselectionInfoCommonQ_Hyst(q_hyst),
+                speed_state_reselection_pars: None,
+            },
+            cell_reselection_serving_freq_info: SystemInformationBlockType3CellReselectionServingFreqInfo {
+                s_non_intra_search: None,
+                thresh_serving_low: ReselectionThreshold(0),
+                cell_reselection_priority: CellReselectionPriority(priority),
+            },
+            intra_freq_cell_reselection_info: SystemInformationBlockType3IntraFreqCellReselectionInfo {
+                q_rx_lev_min: Q_RxLevMin(q_rx_lev_min),
+                p_max: None,
+                s_intra_search: None,
+                allowed_meas_bandwidth: None,
+                presence_antenna_port1: PresenceAntennaPort1(false),
+                neigh_cell_config: NeighCellConfig(Default::default()),
+                t_reselection_eutra: T_Reselection(1),
+                t_reselection_eutra_sf: None,
+            },
+        })
+    }
+
+    fn sib5(neighbours: &[(u16, i8, u8)]) -> SystemInformation_r8_IEsSib_TypeAndInfo_Entry {
+        let carriers = neighbours.iter()
+            .map(|(earfcn, q_rx_lev_min, priority)| InterFreqCarrierFreqInfo {
+                dl_carrier_freq: ARFCN_ValueEUTRA(*earfcn),
+                q_rx_lev_min: Q_RxLevMin(*q_rx_lev_min),
+                p_max: None,
+                t_reselection_eutra: T_Reselection(1),
+                t_reselection_eutra_sf: None,
+                thresh_x_high: ReselectionThreshold(0),
+                thresh_x_low: ReselectionThreshold(0),
+                allowed_meas_bandwidth: AllowedMeasBandwidth(0),
+                presence_antenna_port1: PresenceAntennaPort1(false),
+                cell_reselection_priority: Some(CellReselectionPriority(*priority)),
+                neigh_cell_config: NeighCellConfig(Default::default()),
+                q_offset_freq: None,
+                inter_freq_neigh_cell_list: None,
+                inter_freq_excluded_cell_list: None,
+            })
+            .collect();
+        SystemInformation_r8_IEsSib_TypeAndInfo_Entry::Sib5(SystemInformationBlockType5 {
+            inter_freq_carrier_freq_list: InterFreqCarrierFreqList(carriers),
+        })
+    }
+
+    fn system_information(sibs: Vec<SystemInformation_r8_IEsSib_TypeAndInfo_Entry>) -> InformationElement {
+        let message = BCCH_DL_SCH_Message {
+            message: BCCH_DL_SCH_MessageType::C1(BCCH_DL_SCH_MessageType_c1::SystemInformation(SystemInformation {
+                critical_extensions: SystemInformationCriticalExtensions::SystemInformation_r8(SystemInformation_r8_IEs {
+                    sib_type_and_info: SystemInformation_r8_IEsSib_TypeAndInfo(sibs),
+                    non_critical_extension: None,
+                }),
+            })),
+        };
+        InformationElement::LTE(Box::new(LteInformationElement::BcchDlSch(message)))
+    }
+
+    fn context() -> PacketContext {
+        context_on(1, 1)
+    }
+
+    #[test]
+    fn test_sane_parameters() {
+        let mut analyzer = CellReselectionAnalyzer::new(CellReselectionParams::default());
+        let ie = system_information(vec![sib3(4, -62, 5), sib5(&[(100, -62, 6), (200, -60, 4)])]);
+        assert!(analyzer.analyze_information_element(&ie, &context()).is_none());
+    }
+
+    #[test]
+    fn test_aggressive_parameters() {
+        let mut analyzer = CellReselectionAnalyzer::new(CellReselectionParams::default());
+        let event = analyzer.analyze_information_element(&system_information(vec![sib3(4, -70, 5)]), &context()).unwrap();
+        assert_eq!(event.event_type, EventType::QualitativeWarning { severity: Severity::Low });
+        assert!(event.message.contains("q-RxLevMin of -140 dBm (below -130 dBm)"));
+
+        // SIB5 arrives separately, and is compared against the SIB3 we saw
+        // before
+        let event = analyzer.analyze_information_element(&system_information(vec![sib5(&[(100, -60, 3)])]), &context()).unwrap();
+        assert_eq!(event.event_type, EventType::QualitativeWarning { severity: Severity::Medium });
+        assert!(event.message.contains("20 dB below its neighbour on EARFCN 100"));
+
+        let ie = system_information(vec![sib3(15, -62, 7)]);
+        let event = analyzer.analyze_information_element(&ie, &context()).unwrap();
+        assert!(event.message.contains("q-Hyst of 24 dB (above 8 dB)"));
+        assert!(event.message.contains("serving cell priority of 7, above all of its neighbouring frequencies (3)"));
+    }
+
+    #[test]
+    fn test_priority_waits_for_neighbours() {
+        let mut analyzer = CellReselectionAnalyzer::new(CellReselectionParams::default());
+        let ie = system_information(vec![sib3(4, -62, 7)]);
+        assert!(analyzer.analyze_information_element(&ie, &context()).is_none());
+        let ie = system_information(vec![sib5(&[(100, -62, 7)])]);
+        assert!(analyzer.analyze_information_element(&ie, &context()).is_none());
+
+        // a SIB1 which schedules SIB5 doesn't tell us anything new
+        let mut analyzer = CellReselectionAnalyzer::new(CellReselectionParams::default());
+        let ie = system_information(vec![sib3(4, -62, 7)]);
+        assert!(analyzer.analyze_information_element(&ie, &context()).is_none());
+        let with_sib5 = test_util::sib1(|sib1| sib1.scheduling_info_list.0[0].sib_mapping_info.0.push(SIB_Type(SIB_Type::SIB_TYPE5)));
+        assert!(analyzer.analyze_information_element(&with_sib5, &context()).is_none());
+
+        // but one which doesn't means the cell has no neighbours to compare
+        // against
+        let without_sib5 = test_util::sib1(|sib1| {
+            for info in &mut sib1.scheduling_info_list.0 {
+                info.sib_mapping_info.0.retain(|sib_type| sib_type.0 != SIB_Type::SIB_TYPE5);
+            }
+        });
+        let event = analyzer.analyze_information_element(&without_sib5, &context()).unwrap();
+        assert!(event.message.ends_with("broadcast suspicious reselection parameters: serving cell priority of 7"));
+        assert!(analyzer.analyze_information_element(&without_sib5, &context()).is_none());
+    }
+}
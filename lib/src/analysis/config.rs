@@ -0,0 +1,320 @@
+//! User-facing configuration for which [Analyzers](super::analyzer::Analyzer)
+//! a [Harness](super::analyzer::Harness) runs and how they behave. This is
+//! deserialized from the `[analyzers]` section of rayhunter's config file, with
+//! each analyzer getting its own table keyed by its stable ID, e.g.:
+//!
+//! ```toml
+//! [analyzers.imsi_requested]
+//! enabled = true
+//! severity = "Medium"
+//!
+//! [analyzers.imsi_requested.params]
+//! packet_threshold = 300
+//! ```
+//!
+//! Rule-based analyzers (see [rules](super::rules)) are loaded from the files
+//! or directories listed in `rule_paths`, and use their rule's ID. Scripted
+//! analyzers (see [script](super::script)) are listed under `scripts`.
+//!
+//! The `[analyzers.correlation]` table configures how the analyzers' warnings
+//! are combined into a threat level (see [correlation](super::correlation)).
+
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::analyzer::{AnalyzerOptions, Severity};
+use super::cell_reference::SharedCellReference;
+use super::correlation::CorrelationConfig;
+use super::known_cells::SharedKnownCellsDb;
+use super::rules::{load_rules, Rule, RuleError};
+use super::script::{ScriptConfig, ScriptError};
+
+#[derive(Error, Debug)]
+pub enum AnalyzerConfigError {
+    #[error("Unknown analyzer ID \"{0}\", expected one of: {ids}", ids = ANALYZER_IDS.join(", "))]
+    UnknownAnalyzer(String),
+}
+
+// Declares the built-in analyzers' config tables, keyed by their stable IDs,
+// alongside the config's other fields. This is the only place the IDs are
+// listed: it also generates ANALYZER_IDS, and the lookup used to enable or
+// disable an analyzer by ID.
+macro_rules! analyzers_config {
+    (
+        analyzers { $($id:ident: $config:ty,)* }
+        $($(#[$meta:meta])* pub $field:ident: $field_type:ty,)*
+    ) => {
+        /// Stable IDs of every built-in analyzer, as used in the config file.
+        pub const ANALYZER_IDS: &[&str] = &[$(stringify!($id)),*];
+
+        #[derive(Deserialize, Debug, Clone, Default)]
+        #[serde(default, deny_unknown_fields)]
+        pub struct AnalyzersConfig {
+            $(pub $id: $config,)*
+            $($(#[$meta])* pub $field: $field_type,)*
+        }
+
+        impl AnalyzersConfig {
+            // The `enabled` setting of the built-in analyzer with this ID
+            fn enabled_setting(&mut self, id: &str) -> Option<&mut Option<bool>> {
+                match id {
+                    $(stringify!($id) => Some(&mut self.$id.enabled),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+/// Settings shared by all analyzers, plus analyzer-specific parameters `P`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnalyzerConfig<P = NoParams> {
+    /// Whether to run this analyzer. If unset, the analyzer's own default is used.
+    pub enabled: Option<bool>,
+    /// Overrides the severity of any `QualitativeWarning` this analyzer emits.
+    pub severity: Option<Severity>,
+    /// Repeats of the same event on the same cell within this many seconds of
+    /// each other are merged into one. 0 disables this, and if unset, the
+    /// analyzer's own default is used.
+    pub dedup_seconds: Option<u32>,
+    pub params: P,
+}
+
+impl<P: Default> Default for AnalyzerConfig<P> {
+    fn default() -> Self {
+        AnalyzerConfig {
+            enabled: None,
+            severity: None,
+            dedup_seconds: None,
+            params: P::default(),
+        }
+    }
+}
+
+impl<P> AnalyzerConfig<P> {
+    pub fn is_enabled(&self, enabled_by_default: bool) -> bool {
+        self.enabled.unwrap_or(enabled_by_default)
+    }
+
+    /// Returns the options the [Harness](super::analyzer::Harness) should run
+    /// the analyzer with, given its default dedup window.
+    pub fn options(&self, default_dedup_seconds: Option<u32>) -> AnalyzerOptions {
+        AnalyzerOptions::new(self.severity, self.dedup_seconds.or(default_dedup_seconds))
+    }
+}
+
+/// Parameters for analyzers which don't take any.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct NoParams {}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImsiRequestedParams {
+    /// Identity requests within this many packets of the start of an
+    /// analysis are reported with a lower severity, since they're expected
+    /// when the device first attaches to the network. Every LTE RRC and NAS
+    /// packet counts, whether or not any analyzer looks at it.
+    pub packet_threshold: u64,
+}
+
+impl Default for ImsiRequestedParams {
+    fn default() -> Self {
+        ImsiRequestedParams {
+            packet_threshold: 150,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImsiPagingParams {
+    /// This device's IMSI. If unset, it's learned from the first NAS message
+    /// the device sends it in unencrypted.
+    pub imsi: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CellReselectionParams {
+    /// Cells advertising a q-RxLevMin below this many dBm are reported, since
+    /// it lets phones camp on them at implausibly weak signal levels.
+    pub min_q_rx_lev_min_dbm: i32,
+    /// Cells advertising a q-Hyst above this many dB are reported, since it
+    /// keeps phones from reselecting away from them.
+    pub max_q_hyst_db: u8,
+    /// Cells whose q-RxLevMin is at least this many dB below that of a
+    /// neighbouring frequency they advertise are reported.
+    pub max_q_rx_lev_min_difference_db: i32,
+}
+
+impl Default for CellReselectionParams {
+    fn default() -> Self {
+        CellReselectionParams {
+            min_q_rx_lev_min_dbm: -130,
+            max_q_hyst_db: 8,
+            max_q_rx_lev_min_difference_db: 10,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConnectionRejectParams {
+    /// Rejects from the same cell are counted over this many seconds.
+    pub window_seconds: u32,
+    /// A cell which rejects us this many times within the window is reported
+    /// as repeatedly rejecting us.
+    pub repeat_threshold: usize,
+    /// A cell which keeps repeatedly rejecting us for this many seconds is
+    /// reported with the highest severity.
+    pub persistent_seconds: u32,
+}
+
+impl Default for ConnectionRejectParams {
+    fn default() -> Self {
+        ConnectionRejectParams {
+            window_seconds: 300,
+            repeat_threshold: 3,
+            persistent_seconds: 1800,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct NovelCellParams {
+    /// New cells are only reported in tracking areas where we've seen a cell
+    /// in at least this many recordings.
+    pub min_area_visits: u64,
+}
+
+impl Default for NovelCellParams {
+    fn default() -> Self {
+        NovelCellParams {
+            min_area_visits: 3,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransientCellParams {
+    /// Cells visible for longer than this many seconds are never reported.
+    pub max_lifetime_seconds: u32,
+    /// Cells are only reported if they were visible for at most this fraction
+    /// of the median time cells in the recording were visible for.
+    pub max_fraction_of_median: f64,
+    /// How many other cells we need to have seen to judge what's normal.
+    pub min_cells: usize,
+    /// How often vanished cells are checked during a recording, as well as
+    /// when it ends.
+    pub window_seconds: u32,
+    /// How long a cell must have been gone to count as having vanished.
+    pub vanish_seconds: u32,
+}
+
+impl Default for TransientCellParams {
+    fn default() -> Self {
+        TransientCellParams {
+            max_lifetime_seconds: 600,
+            max_fraction_of_median: 0.1,
+            min_cells: 3,
+            window_seconds: 3600,
+            vanish_seconds: 300,
+        }
+    }
+}
+
+analyzers_config! {
+    analyzers {
+        imsi_requested: AnalyzerConfig<ImsiRequestedParams>,
+        imsi_paging: AnalyzerConfig<ImsiPagingParams>,
+        connection_redirect_2g_downgrade: AnalyzerConfig,
+        lte_sib6_and_7_downgrade: AnalyzerConfig,
+        null_cipher: AnalyzerConfig,
+        sib1_cell_identity: AnalyzerConfig,
+        ue_capability_enquiry_before_security: AnalyzerConfig,
+        cell_reselection_anomaly: AnalyzerConfig<CellReselectionParams>,
+        mobility_from_eutra_downgrade: AnalyzerConfig,
+        idle_mode_mobility_downgrade: AnalyzerConfig,
+        location_request: AnalyzerConfig,
+        emergency_alert: AnalyzerConfig,
+        rrc_connection_reject: AnalyzerConfig<ConnectionRejectParams>,
+        novel_cell: AnalyzerConfig<NovelCellParams>,
+        cell_reference_mismatch: AnalyzerConfig,
+        transient_cell: AnalyzerConfig<TransientCellParams>,
+    }
+    /// The database of cells seen in earlier recordings. If this isn't set,
+    /// each [Harness](super::analyzer::Harness) only knows about the cells it
+    /// has seen itself.
+    #[serde(skip)]
+    pub known_cells: Option<SharedKnownCellsDb>,
+    /// The imported cell reference. The `cell_reference_mismatch` analyzer
+    /// only runs if this is set.
+    #[serde(skip)]
+    pub cell_reference: Option<SharedCellReference>,
+    /// Rule files, or directories of them, to load rule-based analyzers from.
+    pub rule_paths: Vec<PathBuf>,
+    /// The rules loaded from `rule_paths` by [AnalyzersConfig::load_rules].
+    #[serde(skip)]
+    pub rules: Vec<Rule>,
+    pub scripts: Vec<ScriptConfig>,
+    /// How warnings from all analyzers are combined into a threat level.
+    pub correlation: CorrelationConfig,
+}
+
+impl AnalyzersConfig {
+    /// Enables or disables the analyzer with the given ID, overriding whatever
+    /// was set in the config file.
+    pub fn set_enabled(&mut self, id: &str, enabled: bool) -> Result<(), AnalyzerConfigError> {
+        if let Some(rule) = self.rules.iter_mut().find(|rule| rule.id == id) {
+            rule.enabled = enabled;
+            return Ok(());
+        }
+        if let Some(script) = self.scripts.iter_mut().find(|script| script.id == id) {
+            script.enabled = enabled;
+            return Ok(());
+        }
+        let setting = self.enabled_setting(id)
+            .ok_or_else(|| AnalyzerConfigError::UnknownAnalyzer(id.to_string()))?;
+        *setting = Some(enabled);
+        Ok(())
+    }
+
+    /// Loads the rules in `rule_paths`, making sure none of their IDs clash
+    /// with each other or with a built-in analyzer.
+    pub fn load_rules(&mut self) -> Result<(), RuleError> {
+        let mut rules: Vec<Rule> = Vec::new();
+        for path in &self.rule_paths {
+            for rule in load_rules(path)? {
+                if ANALYZER_IDS.contains(&rule.id.as_str()) || rules.iter().any(|other| other.id == rule.id) {
+                    return Err(RuleError::DuplicateId(rule.id));
+                }
+                rules.push(rule);
+            }
+        }
+        self.rules = rules;
+        Ok(())
+    }
+
+    /// Reads and compiles each enabled script in `scripts`. This should be
+    /// called after [AnalyzersConfig::load_rules], so script IDs can be
+    /// checked against rule IDs.
+    pub fn load_scripts(&mut self) -> Result<(), ScriptError> {
+        for (i, script) in self.scripts.iter().enumerate() {
+            if ANALYZER_IDS.contains(&script.id.as_str())
+                || self.rules.iter().any(|rule| rule.id == script.id)
+                || self.scripts[..i].iter().any(|other| other.id == script.id) {
+                return Err(ScriptError::DuplicateId(script.id.clone()));
+            }
+        }
+        for script in self.scripts.iter_mut().filter(|script| script.enabled) {
+            script.load()?;
+        }
+        Ok(())
+    }
+}
@@ -1,7 +1,8 @@
 use std::borrow::Cow;
 
-use super::analyzer::{Analyzer, Event, EventType, Severity};
-use super::information_element::{InformationElement, LteInformationElement};
+use super::analyzer::{Analyzer, Event, EventType, Interests, Severity};
+use super::context::PacketContext;
+use super::information_element::{InformationElement, LteChannel, LteInformationElement};
 use telcom_parser::lte_rrc::{DL_DCCH_MessageType, DL_DCCH_MessageType_c1, RRCConnectionReleaseCriticalExtensions, RRCConnectionReleaseCriticalExtensions_c1, RedirectedCarrierInfo};
 use super::util::unpack;
 
@@ -12,6 +13,14 @@ pub struct ConnectionRedirect2GDowngradeAnalyzer {
 
 // TODO: keep track of SIB state to compare LTE reselection blocks w/ 2g/3g ones
 impl Analyzer for ConnectionRedirect2GDowngradeAnalyzer {
+    fn get_id(&self) -> Cow<str> {
+        Cow::from("connection_redirect_2g_downgrade")
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
     fn get_name(&self) -> Cow<str> {
         Cow::from("Connection Release/Redirected Carrier 2G Downgrade")
     }
@@ -20,7 +29,11 @@ impl Analyzer for ConnectionRedirect2GDowngradeAnalyzer {
         Cow::from("Tests if a cell releases our connection and redirects us to a 2G cell.")
     }
 
-    fn analyze_information_element(&mut self, ie: &InformationElement) -> Option<Event> {
+    fn get_interests(&self) -> Interests {
+        Interests::lte_channels([LteChannel::DlDcch])
+    }
+
+    fn analyze_information_element(&mut self, ie: &InformationElement, _context: &PacketContext) -> Option<Event> {
         unpack!(InformationElement::LTE(lte_ie) = ie);
         let message = match &**lte_ie {
             LteInformationElement::DlDcch(msg_cont) => &msg_cont.message,
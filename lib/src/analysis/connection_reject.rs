@@ -0,0 +1,218 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use telcom_parser::lte_rrc::{DL_CCCH_MessageType, DL_CCCH_MessageType_c1, RRCConnectionRejectCriticalExtensions, RRCConnectionRejectCriticalExtensions_c1, RRCConnectionReject_r8_IEs, RRCConnectionReject_v1130_IEsDeprioritisationReq_r11DeprioritisationType_r11};
+
+use super::analyzer::{Analyzer, Event, EventType, Interests, Severity};
+use super::config::ConnectionRejectParams;
+use super::context::{PacketContext, PhysicalCell};
+use super::information_element::{InformationElement, LteChannel, LteInformationElement};
+use super::util::unpack;
+
+// deprioritisationTimer is an enumeration of these values, in minutes
+const DEPRIORITISATION_MINUTES: [u32; 4] = [5, 10, 15, 30];
+
+/// The parts of an RRCConnectionReject we report.
+struct Reject {
+    wait_time: u8,
+    extended_wait_time: Option<u16>,
+    // whether all of LTE (rather than just this frequency) was deprioritised,
+    // and for how many minutes
+    deprioritisation: Option<(bool, u32)>,
+}
+
+impl From<&RRCConnectionReject_r8_IEs> for Reject {
+    fn from(r8_ies: &RRCConnectionReject_r8_IEs) -> Self {
+        let v1020 = r8_ies.non_critical_extension.as_ref().and_then(|v8a0| v8a0.non_critical_extension.as_ref());
+        let v1130 = v1020.and_then(|v1020| v1020.non_critical_extension.as_ref());
+        Reject {
+            wait_time: r8_ies.wait_time.0,
+            extended_wait_time: v1020.and_then(|v1020| v1020.extended_wait_time_r10.as_ref()).map(|time| time.0),
+            deprioritisation: v1130.and_then(|v1130| v1130.deprioritisation_req_r11.as_ref()).map(|request| (
+                request.deprioritisation_type_r11.0 == RRCConnectionReject_v1130_IEsDeprioritisationReq_r11DeprioritisationType_r11::E_UTRA,
+                DEPRIORITISATION_MINUTES.get(request.deprioritisation_timer_r11.0 as usize).copied().unwrap_or(0),
+            )),
+        }
+    }
+}
+
+impl Reject {
+    fn describe(&self) -> String {
+        let mut description = format!("wait time {} s", self.wait_time);
+        if let Some(extended_wait_time) = self.extended_wait_time {
+            description.push_str(&format!(", extended wait time {} s", extended_wait_time));
+        }
+        match self.deprioritisation {
+            Some((true, minutes)) => description.push_str(&format!(", all of LTE deprioritised for {} minutes", minutes)),
+            Some((false, minutes)) => description.push_str(&format!(", this frequency deprioritised for {} minutes", minutes)),
+            None => {},
+        }
+        description
+    }
+}
+
+#[derive(Default)]
+struct CellRejects {
+    // when the rejects within the window were received
+    times: VecDeque<DateTime<FixedOffset>>,
+    // when the cell started rejecting us repeatedly
+    repeated_since: Option<DateTime<FixedOffset>>,
+}
+
+/// A cell which rejects every connection attempt denies the UE service without
+/// it ever being told why, and a long extendedWaitTime or deprioritisation of
+/// LTE can keep it off the network for up to half an hour, or push it onto
+/// another RAT. This reports rejects per cell, escalating when a cell keeps
+/// rejecting us.
+pub struct ConnectionRejectAnalyzer {
+    window: TimeDelta,
+    repeat_threshold: usize,
+    persistent_duration: TimeDelta,
+    cells: HashMap<Option<PhysicalCell>, CellRejects>,
+}
+
+impl ConnectionRejectAnalyzer {
+    pub fn new(params: &ConnectionRejectParams) -> Self {
+        Self {
+            window: TimeDelta::seconds(params.window_seconds.into()),
+            repeat_threshold: params.repeat_threshold,
+            persistent_duration: TimeDelta::seconds(params.persistent_seconds.into()),
+            cells: HashMap::new(),
+        }
+    }
+}
+
+impl Analyzer for ConnectionRejectAnalyzer {
+    fn get_id(&self) -> Cow<str> {
+        Cow::from("rrc_connection_reject")
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("RRC Connection Reject Denial of Service")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from("Tests whether a cell repeatedly rejects our connections, or rejects them with long wait times or LTE deprioritisation.")
+    }
+
+    fn get_interests(&self) -> Interests {
+        Interests::lte_channels([LteChannel::DlCcch])
+    }
+
+    fn analyze_information_element(&mut self, ie: &InformationElement, context: &PacketContext) -> Option<Event> {
+        unpack!(InformationElement::LTE(lte_ie) = ie);
+        unpack!(LteInformationElement::DlCcch(msg) = &**lte_ie);
+        unpack!(DL_CCCH_MessageType::C1(DL_CCCH_MessageType_c1::RrcConnectionReject(reject)) = &msg.message);
+        unpack!(RRCConnectionRejectCriticalExtensions::C1(RRCConnectionRejectCriticalExtensions_c1::RrcConnectionReject_r8(r8_ies)) = &reject.critical_extensions);
+        let reject = Reject::from(r8_ies);
+
+        let rejects = self.cells.entry(context.cell).or_default();
+        rejects.times.push_back(context.timestamp);
+        while rejects.times.front().is_some_and(|time| context.timestamp - *time > self.window) {
+            rejects.times.pop_front();
+        }
+        let repeated = rejects.times.len() >= self.repeat_threshold;
+        if repeated {
+            rejects.repeated_since.get_or_insert(rejects.times[0]);
+        } else {
+            rejects.repeated_since = None;
+        }
+
+        let mut message = match context.cell {
+            Some(cell) => format!("RRCConnectionReject from PCI {} on EARFCN {} ({})", cell.phy_cell_id, cell.earfcn, reject.describe()),
+            None => format!("RRCConnectionReject ({})", reject.describe()),
+        };
+        let severity = match rejects.repeated_since {
+            Some(since) => {
+                let duration = context.timestamp - since;
+                message.push_str(&format!(
+                    ", {} rejects in the last {} seconds, rejecting us for {} seconds so far",
+                    rejects.times.len(), self.window.num_seconds(), duration.num_seconds(),
+                ));
+                if duration >= self.persistent_duration {
+                    Some(Severity::High)
+                } else {
+                    Some(Severity::Medium)
+                }
+            },
+            None if matches!(reject.deprioritisation, Some((true, _))) => Some(Severity::Medium),
+            None if reject.extended_wait_time.is_some() || reject.deprioritisation.is_some() => Some(Severity::Low),
+            None => None,
+        };
+        Some(Event {
+            event_type: match severity {
+                Some(severity) => EventType::QualitativeWarning { severity },
+                None => EventType::Informational,
+            },
+            message,
+        })
+    }
+
+    fn on_recording_start(&mut self) {
+        self.cells.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::test_util::{context_on, time};
+    use telcom_parser::lte_rrc::{DL_CCCH_Message, RRCConnectionReject, RRCConnectionReject_r8_IEsWaitTime, RRCConnectionReject_v1020_IEs, RRCConnectionReject_v1020_IEsExtendedWaitTime_r10, RRCConnectionReject_v8a0_IEs};
+
+    fn reject(extended_wait_time: Option<u16>) -> InformationElement {
+        let message = DL_CCCH_Message {
+            message: DL_CCCH_MessageType::C1(DL_CCCH_MessageType_c1::RrcConnectionReject(RRCConnectionReject {
+                critical_extensions: RRCConnectionRejectCriticalExtensions::C1(
+                    RRCConnectionRejectCriticalExtensions_c1::RrcConnectionReject_r8(RRCConnectionReject_r8_IEs {
+                        wait_time: RRCConnectionReject_r8_IEsWaitTime(16),
+                        non_critical_extension: extended_wait_time.map(|time| RRCConnectionReject_v8a0_IEs {
+                            late_non_critical_extension: None,
+                            non_critical_extension: Some(RRCConnectionReject_v1020_IEs {
+                                extended_wait_time_r10: Some(RRCConnectionReject_v1020_IEsExtendedWaitTime_r10(time)),
+                                non_critical_extension: None,
+                            }),
+                        }),
+                    })
+                ),
+            })),
+        };
+        InformationElement::LTE(Box::new(LteInformationElement::DlCcch(message)))
+    }
+
+    fn context(seconds: i64) -> PacketContext {
+        PacketContext { timestamp: time(seconds), ..context_on(5230, 3) }
+    }
+
+    fn severity(event: Event) -> Option<Severity> {
+        match event.event_type {
+            EventType::QualitativeWarning { severity } => Some(severity),
+            EventType::Informational => None,
+        }
+    }
+
+    #[test]
+    fn test_repeated_rejects() {
+        let params = ConnectionRejectParams { window_seconds: 300, repeat_threshold: 3, persistent_seconds: 1200 };
+        let mut analyzer = ConnectionRejectAnalyzer::new(&params);
+        assert_eq!(severity(analyzer.analyze_information_element(&reject(None), &context(0)).unwrap()), None);
+        let event = analyzer.analyze_information_element(&reject(Some(1800)), &context(100)).unwrap();
+        assert_eq!(event.message, "RRCConnectionReject from PCI 3 on EARFCN 5230 (wait time 16 s, extended wait time 1800 s)");
+        assert_eq!(severity(event), Some(Severity::Low));
+        let event = analyzer.analyze_information_element(&reject(None), &context(200)).unwrap();
+        assert!(event.message.ends_with("3 rejects in the last 300 seconds, rejecting us for 200 seconds so far"));
+        assert_eq!(severity(event), Some(Severity::Medium));
+
+        for seconds in (400..1200).step_by(100) {
+            analyzer.analyze_information_element(&reject(None), &context(seconds));
+        }
+        assert_eq!(severity(analyzer.analyze_information_element(&reject(None), &context(1200)).unwrap()), Some(Severity::High));
+
+        // after a break, the pattern starts over
+        assert_eq!(severity(analyzer.analyze_information_element(&reject(None), &context(2000)).unwrap()), None);
+    }
+}
@@ -0,0 +1,94 @@
+//! Radio-level context about the packet an [InformationElement] was decoded
+//! from. GSMTAP headers only carry a subset of this (and e.g. truncate EARFCNs
+//! to 14 bits), so we pull what we can from the diag log itself before it's
+//! converted.
+//!
+//! [InformationElement]: super::information_element::InformationElement
+
+use chrono::{DateTime, FixedOffset};
+use serde::Serialize;
+
+use super::rrc_state::RrcConnectionState;
+use crate::diag::{LogBody, Message};
+use crate::gsmtap::{GsmtapHeader, GsmtapType, LteRrcSubtype};
+
+/// Whether a packet was sent by the UE (uplink) or received by it (downlink).
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Uplink,
+    Downlink,
+}
+
+impl From<&GsmtapHeader> for Direction {
+    fn from(header: &GsmtapHeader) -> Self {
+        let uplink = match header.gsmtap_type {
+            GsmtapType::LteRrc(subtype) => matches!(subtype,
+                LteRrcSubtype::UlCcch |
+                LteRrcSubtype::UlDcch |
+                LteRrcSubtype::UlCcchNb |
+                LteRrcSubtype::UlDcchNb
+            ),
+            _ => header.uplink,
+        };
+        if uplink {
+            Direction::Uplink
+        } else {
+            Direction::Downlink
+        }
+    }
+}
+
+/// A physical LTE cell, as identified by the frequency it's transmitting on
+/// and its physical cell ID (PCI). Note that PCIs are only locally unique, so
+/// two distinct cells may share the same [PhysicalCell] if they're far enough
+/// apart.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PhysicalCell {
+    pub earfcn: u32,
+    pub phy_cell_id: u16,
+}
+
+impl PhysicalCell {
+    /// Returns the cell an LTE RRC OTA message was sent or received on, or
+    /// `None` for any other type of message.
+    pub fn from_message(msg: &Message) -> Option<Self> {
+        let Message::Log { body: LogBody::LteRrcOtaMessage { packet, .. }, .. } = msg else {
+            return None;
+        };
+        Some(PhysicalCell {
+            earfcn: packet.get_earfcn(),
+            phy_cell_id: packet.get_phy_cell_id(),
+        })
+    }
+}
+
+/// Per-packet metadata handed to every [Analyzer](super::analyzer::Analyzer)
+/// alongside the decoded message.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PacketContext {
+    /// When the diag device logged this packet.
+    pub timestamp: DateTime<FixedOffset>,
+    pub direction: Direction,
+    /// The cell this packet was exchanged with. This is only known for LTE
+    /// RRC messages, since the diag device doesn't report it for NAS.
+    pub cell: Option<PhysicalCell>,
+    /// How many GSMTAP packets (i.e. radio messages, rather than other diag
+    /// logs) came before this one in the recording, including ones which
+    /// weren't decoded or handed to this analyzer.
+    pub message_index: u64,
+    /// The state of the RRC connection when this packet was sent or received,
+    /// i.e. not yet taking this packet into account.
+    pub rrc_state: RrcConnectionState,
+}
+
+impl PacketContext {
+    pub fn new(timestamp: DateTime<FixedOffset>, header: &GsmtapHeader, cell: Option<PhysicalCell>, message_index: u64) -> Self {
+        PacketContext {
+            timestamp,
+            direction: Direction::from(header),
+            cell,
+            message_index,
+            rrc_state: RrcConnectionState::default(),
+        }
+    }
+}
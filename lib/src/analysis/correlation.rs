@@ -0,0 +1,179 @@
+//! Combines the warnings emitted by all [Analyzers](super::analyzer::Analyzer)
+//! into a single threat level for a recording. Each warning is weighted by its
+//! [Severity], and the weights of all warnings within a sliding time window are
+//! summed into a score. That way a handful of Low warnings in quick succession
+//! can add up to something worth investigating, while the same warnings spread
+//! across a day of recording don't. A recording's threat level is the highest
+//! one reached by any window.
+//!
+//! The weights and thresholds are configured in the `[analyzers.correlation]`
+//! section of the config file.
+
+use std::collections::VecDeque;
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use serde::{Deserialize, Serialize};
+
+use super::analyzer::{EventType, ReportedEvent, Severity};
+
+/// How worried the user should be about a recording.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum ThreatLevel {
+    /// No warnings at all.
+    #[default]
+    None,
+    /// Some warnings, but not enough to be worth investigating on their own.
+    Low,
+    /// The user should investigate.
+    Medium,
+    /// An IMSI catcher is likely.
+    High,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorrelationConfig {
+    /// How far apart, in seconds, two warnings may be and still be combined.
+    pub window_seconds: u32,
+    pub low_weight: u32,
+    pub medium_weight: u32,
+    pub high_weight: u32,
+    /// The score a window needs to reach a Medium threat level. Any warning at
+    /// all is at least Low.
+    pub medium_score: u32,
+    /// The score a window needs to reach a High threat level.
+    pub high_score: u32,
+}
+
+impl Default for CorrelationConfig {
+    fn default() -> Self {
+        CorrelationConfig {
+            window_seconds: 600,
+            low_weight: 1,
+            medium_weight: 3,
+            high_weight: 10,
+            medium_score: 5,
+            high_score: 10,
+        }
+    }
+}
+
+impl CorrelationConfig {
+    fn weight(&self, severity: Severity) -> u32 {
+        match severity {
+            Severity::Low => self.low_weight,
+            Severity::Medium => self.medium_weight,
+            Severity::High => self.high_weight,
+        }
+    }
+
+    fn level(&self, score: u32) -> ThreatLevel {
+        if score >= self.high_score {
+            ThreatLevel::High
+        } else if score >= self.medium_score {
+            ThreatLevel::Medium
+        } else if score > 0 {
+            ThreatLevel::Low
+        } else {
+            ThreatLevel::None
+        }
+    }
+}
+
+/// Tracks the warnings in the current window, and the highest threat level
+/// seen so far in the recording.
+pub struct Correlator {
+    config: CorrelationConfig,
+    window: VecDeque<(DateTime<FixedOffset>, u32)>,
+    score: u32,
+    level: ThreatLevel,
+}
+
+impl Correlator {
+    pub fn new(config: CorrelationConfig) -> Self {
+        Correlator {
+            config,
+            window: VecDeque::new(),
+            score: 0,
+            level: ThreatLevel::None,
+        }
+    }
+
+    /// Forgets everything seen so far, e.g. because a new recording started.
+    pub fn reset(&mut self) {
+        self.window.clear();
+        self.score = 0;
+        self.level = ThreatLevel::None;
+    }
+
+    /// Adds the events seen at `timestamp` to the current window, returning
+    /// the recording's threat level.
+    pub fn observe(&mut self, timestamp: DateTime<FixedOffset>, events: &[ReportedEvent]) -> ThreatLevel {
+        let window_start = timestamp - TimeDelta::seconds(self.config.window_seconds.into());
+        while let Some((oldest, weight)) = self.window.front() {
+            if *oldest >= window_start {
+                break;
+            }
+            self.score -= weight;
+            self.window.pop_front();
+        }
+        // summaries of repeated events were already counted when they were
+        // first reported
+        for reported in events.iter().filter(|reported| reported.repeats.is_none()) {
+            if let EventType::QualitativeWarning { severity } = reported.event.event_type {
+                // warnings with no weight shouldn't raise the level to Low
+                let weight = self.config.weight(severity);
+                if weight > 0 {
+                    self.window.push_back((timestamp, weight));
+                    self.score += weight;
+                }
+            }
+        }
+        self.level = self.level.max(self.config.level(self.score));
+        self.level
+    }
+
+    pub fn level(&self) -> ThreatLevel {
+        self.level
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::analyzer::Event;
+    use crate::analysis::test_util::time;
+
+    fn warning(severity: Severity) -> ReportedEvent {
+        ReportedEvent {
+            analyzer_id: "test".to_string(),
+            event: Event {
+                event_type: EventType::QualitativeWarning { severity },
+                message: String::new(),
+            },
+            repeats: None,
+        }
+    }
+
+    fn at(minutes: i64) -> DateTime<FixedOffset> {
+        time(minutes * 60)
+    }
+
+    #[test]
+    fn test_correlation() {
+        let mut correlator = Correlator::new(CorrelationConfig::default());
+        assert_eq!(correlator.observe(at(0), &[]), ThreatLevel::None);
+        assert_eq!(correlator.observe(at(0), &[warning(Severity::Low)]), ThreatLevel::Low);
+        assert_eq!(correlator.observe(at(1), &[warning(Severity::Medium)]), ThreatLevel::Low);
+        assert_eq!(correlator.observe(at(2), &[warning(Severity::Low)]), ThreatLevel::Medium);
+
+        // warnings far enough apart aren't combined, but the level never drops
+        correlator.reset();
+        for minutes in [0, 20, 40, 60] {
+            assert_eq!(correlator.observe(at(minutes), &[warning(Severity::Medium)]), ThreatLevel::Low);
+        }
+        assert_eq!(correlator.observe(at(65), &[warning(Severity::Medium), warning(Severity::Medium)]), ThreatLevel::Medium);
+        assert_eq!(correlator.observe(at(66), &[warning(Severity::Low)]), ThreatLevel::High);
+        assert_eq!(correlator.observe(at(120), &[]), ThreatLevel::High);
+    }
+}
@@ -0,0 +1,124 @@
+//! Merges repeats of the same [Event] from an [Analyzer](super::analyzer::Analyzer).
+//! Heuristics which look at broadcast messages (e.g. SIBs) will otherwise fire
+//! every time the cell retransmits them, which can be several times a second.
+//!
+//! The first occurrence of an event is always reported as soon as it's seen,
+//! so warnings aren't delayed. Identical events from the same cell which follow
+//! within the dedup window of the previous one are suppressed, and once the
+//! window passes without another repeat, a single event summarizing them with
+//! [Repeats] is reported instead.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use serde::Serialize;
+
+use super::analyzer::Event;
+use super::context::{PacketContext, PhysicalCell};
+
+/// How often a suppressed event was seen.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Repeats {
+    pub first_seen: DateTime<FixedOffset>,
+    pub last_seen: DateTime<FixedOffset>,
+    /// How many times the event was seen, including the first time it was
+    /// reported.
+    pub count: u64,
+}
+
+pub struct Deduplicator {
+    window: TimeDelta,
+    groups: HashMap<(Option<PhysicalCell>, Event), Repeats>,
+}
+
+impl Deduplicator {
+    pub fn new(window: TimeDelta) -> Self {
+        Deduplicator {
+            window,
+            groups: HashMap::new(),
+        }
+    }
+
+    /// Records an event, returning whether it should be reported, i.e. it's not
+    /// a repeat of a recent event. [Deduplicator::flush_expired] should be
+    /// called first, or else repeats of expired events will be lost.
+    pub fn observe(&mut self, event: &Event, context: &PacketContext) -> bool {
+        let key = (context.cell, event.clone());
+        if let Some(repeats) = self.groups.get_mut(&key) {
+            if context.timestamp - repeats.last_seen <= self.window {
+                repeats.last_seen = context.timestamp;
+                repeats.count += 1;
+                return false;
+            }
+        }
+        self.groups.insert(key, Repeats {
+            first_seen: context.timestamp,
+            last_seen: context.timestamp,
+            count: 1,
+        });
+        true
+    }
+
+    /// Stops tracking events which haven't been repeated within the window as
+    /// of `now`, returning those which were seen more than once.
+    pub fn flush_expired(&mut self, now: DateTime<FixedOffset>) -> Vec<(Event, Repeats)> {
+        let window = self.window;
+        self.flush(|repeats| now - repeats.last_seen > window)
+    }
+
+    /// Stops tracking all events, returning those which were seen more than
+    /// once.
+    pub fn flush_all(&mut self) -> Vec<(Event, Repeats)> {
+        self.flush(|_| true)
+    }
+
+    fn flush(&mut self, mut should_flush: impl FnMut(&Repeats) -> bool) -> Vec<(Event, Repeats)> {
+        let mut flushed = Vec::new();
+        self.groups.retain(|(_, event), repeats| {
+            if !should_flush(repeats) {
+                return true;
+            }
+            if repeats.count > 1 {
+                flushed.push((event.clone(), repeats.clone()));
+            }
+            false
+        });
+        flushed.sort_by_key(|(_, repeats)| repeats.last_seen);
+        flushed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::test_util::{context_on, time};
+    use crate::analysis::analyzer::EventType;
+
+    fn context(seconds: i64, phy_cell_id: u16) -> PacketContext {
+        PacketContext { timestamp: time(seconds), ..context_on(1, phy_cell_id) }
+    }
+
+    #[test]
+    fn test_dedup() {
+        let event = Event {
+            event_type: EventType::Informational,
+            message: "hello".to_string(),
+        };
+        let mut dedup = Deduplicator::new(TimeDelta::seconds(10));
+        assert!(dedup.observe(&event, &context(0, 1)));
+        assert!(dedup.observe(&event, &context(1, 2)));
+        assert!(!dedup.observe(&event, &context(5, 1)));
+        assert!(!dedup.observe(&event, &context(12, 1)));
+        assert!(dedup.flush_expired(context(20, 1).timestamp).is_empty());
+
+        let flushed = dedup.flush_expired(context(30, 1).timestamp);
+        assert_eq!(flushed.len(), 1);
+        assert_eq!(flushed[0].1, Repeats {
+            first_seen: context(0, 1).timestamp,
+            last_seen: context(12, 1).timestamp,
+            count: 3,
+        });
+        assert!(dedup.observe(&event, &context(31, 1)));
+        assert!(dedup.flush_all().is_empty());
+    }
+}
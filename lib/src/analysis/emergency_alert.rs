@@ -0,0 +1,527 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use telcom_parser::lte_rrc::{SystemInformationBlockType11WarningMessageSegmentType, SystemInformationBlockType12_r9WarningMessageSegmentType_r9, SystemInformation_r8_IEsSib_TypeAndInfo_Entry};
+
+use super::analyzer::{Analyzer, Event, EventType, Interests, Severity};
+use super::context::{PacketContext, PhysicalCell};
+use super::information_element::{InformationElement, LteChannel};
+use super::known_cells::{KnownCellKey, SharedKnownCellsDb};
+use super::util::{bits_to_u32, system_information_blocks};
+
+/// Alerts from cells we first saw less than this long ago are suspicious, since
+/// a fake base station would start broadcasting them as soon as it's up.
+const NEW_CELL_WINDOW: TimeDelta = TimeDelta::minutes(5);
+
+/// Cells rebroadcast every segment of an alert within a few SI periods, so a
+/// partial alert which hasn't progressed for this long never will.
+const PARTIAL_ALERT_TIMEOUT: TimeDelta = TimeDelta::minutes(10);
+
+// A fake base station can broadcast as many alerts as it likes, so only keep
+// track of the most recent ones
+const MAX_PARTIAL_ALERTS: usize = 32;
+const MAX_ALERTS: usize = 64;
+
+// The GSM 7 bit default alphabet, from TS 23.038 6.2.1. The escape to the
+// extension table (0x1b) is handled separately.
+const GSM7_ALPHABET: [char; 128] = [
+    '@', '£', '$', '¥', 'è', 'é', 'ù', 'ì', 'ò', 'Ç', '\n', 'Ø', 'ø', '\r', 'Å', 'å',
+    'Δ', '_', 'Φ', 'Γ', 'Λ', 'Ω', 'Π', 'Ψ', 'Σ', 'Θ', 'Ξ', ' ', 'Æ', 'æ', 'ß', 'É',
+    ' ', '!', '"', '#', '¤', '%', '&', '\'', '(', ')', '*', '+', ',', '-', '.', '/',
+    '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', ':', ';', '<', '=', '>', '?',
+    '¡', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O',
+    'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z', 'Ä', 'Ö', 'Ñ', 'Ü', '§',
+    '¿', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o',
+    'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z', 'ä', 'ö', 'ñ', 'ü', 'à',
+];
+const GSM7_ESCAPE: u8 = 0x1b;
+
+fn decode_gsm7(octets: &[u8]) -> String {
+    let septets = (0..octets.len() * 8 / 7).map(|i| {
+        let bit = i * 7;
+        let low = octets[bit / 8] as u16;
+        let high = octets.get(bit / 8 + 1).copied().unwrap_or(0) as u16;
+        (((high << 8 | low) >> (bit % 8)) & 0x7f) as u8
+    });
+    let mut text = String::new();
+    let mut escaped = false;
+    for septet in septets {
+        if escaped {
+            escaped = false;
+            text.push(match septet {
+                0x14 => '^',
+                0x28 => '{',
+                0x29 => '}',
+                0x2f => '\\',
+                0x3c => '[',
+                0x3d => '~',
+                0x3e => ']',
+                0x40 => '|',
+                0x65 => '€',
+                _ => ' ',
+            });
+        } else if septet == GSM7_ESCAPE {
+            escaped = true;
+        } else {
+            text.push(GSM7_ALPHABET[septet as usize]);
+        }
+    }
+    text
+}
+
+fn decode_ucs2(octets: &[u8]) -> String {
+    let units: Vec<u16> = octets.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Decodes one page of a cell broadcast message according to its data coding
+/// scheme, from TS 23.038 5.
+fn decode_page(page: &[u8], data_coding_scheme: Option<u8>) -> String {
+    let Some(dcs) = data_coding_scheme else {
+        return decode_gsm7(page);
+    };
+    match dcs >> 4 {
+        // UCS2, preceded by a two character language code in GSM 7 bit
+        0x1 if dcs & 0x0f == 1 => decode_ucs2(page.get(2..).unwrap_or_default()),
+        0x4..=0x7 | 0x9 => match (dcs >> 2) & 0x03 {
+            0 => decode_gsm7(page),
+            2 => decode_ucs2(page),
+            _ => String::from_utf8_lossy(page).into_owned(),
+        },
+        0xf if dcs & 0x04 != 0 => String::from_utf8_lossy(page).into_owned(),
+        _ => decode_gsm7(page),
+    }
+}
+
+/// Decodes a warning message's CB data (TS 23.041 9.4.2.2.5): a page count,
+/// followed by that many 82 octet pages, each with the length of their
+/// content.
+fn decode_warning_message(data: &[u8], data_coding_scheme: Option<u8>) -> String {
+    const PAGE_LENGTH: usize = 82;
+    let pages = data.first().copied().unwrap_or(0) as usize;
+    let mut text = String::new();
+    for i in 0..pages {
+        let start = 1 + i * (PAGE_LENGTH + 1);
+        let (Some(page), Some(length)) = (data.get(start..start + PAGE_LENGTH), data.get(start + PAGE_LENGTH)) else {
+            // not in the format we expected, so just decode what we have
+            return decode_page(data, data_coding_scheme).trim_end().to_string();
+        };
+        text.push_str(&decode_page(&page[..(*length as usize).min(PAGE_LENGTH)], data_coding_scheme));
+    }
+    text.trim_end().to_string()
+}
+
+/// The kind of alert a message identifier is for, from TS 23.041 9.4.1.2.2.
+fn alert_category(message_identifier: u16) -> &'static str {
+    match message_identifier {
+        0x1100..=0x1107 => "ETWS alert",
+        4370 | 4383 => "CMAS Presidential Alert",
+        4371..=4378 | 4384..=4391 => "CMAS Extreme/Severe Alert",
+        4379 | 4392 => "CMAS AMBER Alert",
+        4380 | 4393 => "CMAS Required Monthly Test",
+        4381 | 4394 => "CMAS Exercise",
+        _ => "CMAS alert",
+    }
+}
+
+// Alerts are identified by their message identifier and serial number, the
+// latter of which changes whenever the alert is updated
+type AlertId = (u16, u16);
+
+/// One segment of an ETWS (SIB11) or CMAS (SIB12) warning message.
+struct Segment<'a> {
+    id: AlertId,
+    number: u8,
+    last: bool,
+    data: &'a [u8],
+    data_coding_scheme: Option<u8>,
+}
+
+struct PartialAlert {
+    segments: BTreeMap<u8, Vec<u8>>,
+    last_segment: Option<u8>,
+    data_coding_scheme: Option<u8>,
+    // when we last received a segment
+    updated: DateTime<FixedOffset>,
+}
+
+impl PartialAlert {
+    fn new(updated: DateTime<FixedOffset>) -> Self {
+        PartialAlert {
+            segments: BTreeMap::new(),
+            last_segment: None,
+            data_coding_scheme: None,
+            updated,
+        }
+    }
+
+    /// Adds a segment, returning the whole warning message's text once we
+    /// have every segment.
+    fn add(&mut self, segment: &Segment) -> Option<String> {
+        self.segments.insert(segment.number, segment.data.to_vec());
+        if segment.last {
+            self.last_segment = Some(segment.number);
+        }
+        if segment.data_coding_scheme.is_some() {
+            self.data_coding_scheme = segment.data_coding_scheme;
+        }
+        let last_segment = self.last_segment?;
+        if !(0..=last_segment).all(|number| self.segments.contains_key(&number)) {
+            return None;
+        }
+        let data: Vec<u8> = self.segments.range(..=last_segment).flat_map(|(_, data)| data.iter().copied()).collect();
+        Some(decode_warning_message(&data, self.data_coding_scheme))
+    }
+}
+
+struct Alert {
+    id: AlertId,
+    received: DateTime<FixedOffset>,
+    // ETWS primary notifications (SIB10) have no text
+    text: Option<String>,
+    cells: HashSet<PhysicalCell>,
+}
+
+/// Emergency alerts (ETWS and CMAS, e.g. presidential alerts) aren't
+/// authenticated, so anyone with a fake base station can broadcast them. This
+/// reassembles the alerts broadcast in SIB10/11/12 and reports each one with
+/// its text, warning about alerts from cells which only just appeared, or
+/// which no other cell broadcast. Cells in the
+/// [known cells database](super::known_cells) count as having been around
+/// since we first saw them there, if they advertise the same identity in SIB1.
+pub struct EmergencyAlertAnalyzer {
+    known_cells: SharedKnownCellsDb,
+    first_seen: HashMap<PhysicalCell, DateTime<FixedOffset>>,
+    // the identity each cell advertised in its latest SIB1
+    identities: HashMap<PhysicalCell, KnownCellKey>,
+    partial_alerts: HashMap<(PhysicalCell, AlertId), PartialAlert>,
+    // the alerts received in this recording
+    alerts: HashMap<AlertId, Alert>,
+}
+
+impl EmergencyAlertAnalyzer {
+    pub fn new(known_cells: SharedKnownCellsDb) -> Self {
+        EmergencyAlertAnalyzer {
+            known_cells,
+            first_seen: HashMap::new(),
+            identities: HashMap::new(),
+            partial_alerts: HashMap::new(),
+            alerts: HashMap::new(),
+        }
+    }
+
+    fn segment(sib: &SystemInformation_r8_IEsSib_TypeAndInfo_Entry) -> Option<Segment<'_>> {
+        match sib {
+            SystemInformation_r8_IEsSib_TypeAndInfo_Entry::Sib11(sib11) => Some(Segment {
+                id: (
+                    bits_to_u32(sib11.message_identifier.0.iter().by_vals()) as u16,
+                    bits_to_u32(sib11.serial_number.0.iter().by_vals()) as u16,
+                ),
+                number: sib11.warning_message_segment_number.0,
+                last: sib11.warning_message_segment_type.0 == SystemInformationBlockType11WarningMessageSegmentType::LAST_SEGMENT,
+                data: &sib11.warning_message_segment.0,
+                data_coding_scheme: sib11.data_coding_scheme.as_ref().and_then(|dcs| dcs.0.first().copied()),
+            }),
+            SystemInformation_r8_IEsSib_TypeAndInfo_Entry::Sib12_v920(sib12) => Some(Segment {
+                id: (
+                    bits_to_u32(sib12.message_identifier_r9.0.iter().by_vals()) as u16,
+                    bits_to_u32(sib12.serial_number_r9.0.iter().by_vals()) as u16,
+                ),
+                number: sib12.warning_message_segment_number_r9.0,
+                last: sib12.warning_message_segment_type_r9.0 == SystemInformationBlockType12_r9WarningMessageSegmentType_r9::LAST_SEGMENT,
+                data: &sib12.warning_message_segment_r9.0,
+                data_coding_scheme: sib12.data_coding_scheme_r9.as_ref().and_then(|dcs| dcs.0.first().copied()),
+            }),
+            _ => None,
+        }
+    }
+
+    // Forgets partial alerts which have stopped progressing, and makes room
+    // for another if we're at the limit
+    fn expire_partial_alerts(&mut self, now: DateTime<FixedOffset>) {
+        self.partial_alerts.retain(|_, partial_alert| now - partial_alert.updated < PARTIAL_ALERT_TIMEOUT);
+        if self.partial_alerts.len() >= MAX_PARTIAL_ALERTS {
+            let oldest = self.partial_alerts.iter().min_by_key(|(_, partial_alert)| partial_alert.updated).map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                self.partial_alerts.remove(&oldest);
+            }
+        }
+    }
+
+    /// Records a complete alert, returning an event for it unless this cell
+    /// has already broadcast it.
+    fn record_alert(&mut self, id: AlertId, text: Option<String>, cell: PhysicalCell, context: &PacketContext) -> Option<Event> {
+        if !self.alerts.contains_key(&id) && self.alerts.len() >= MAX_ALERTS {
+            if let Some(oldest) = self.alerts.values().min_by_key(|alert| alert.received).map(|alert| alert.id) {
+                self.alerts.remove(&oldest);
+            }
+        }
+        let alert = self.alerts.entry(id)
+            .or_insert_with(|| Alert { id, received: context.timestamp, text, cells: HashSet::new() });
+        if !alert.cells.insert(cell) {
+            return None;
+        }
+
+        let (message_identifier, serial_number) = id;
+        let mut message = format!(
+            "{} (message ID {}, serial number {:#06x}) from PCI {} on EARFCN {}",
+            alert_category(message_identifier), message_identifier, serial_number, cell.phy_cell_id, cell.earfcn,
+        );
+        match &alert.text {
+            Some(text) => message.push_str(&format!(": \"{}\"", text)),
+            None => message.push_str(": ETWS primary notification"),
+        }
+        let known_first_seen = self.identities.get(&cell)
+            .and_then(|key| self.known_cells.lock().unwrap().get(key).map(|known| known.first_seen));
+        let first_seen = self.first_seen.get(&cell).copied().into_iter().chain(known_first_seen).min();
+        match first_seen.map(|first_seen| context.timestamp - first_seen) {
+            Some(age) if age < NEW_CELL_WINDOW => {
+                message.push_str(&format!(". The cell was first seen {} seconds earlier", age.num_seconds()));
+                Some(Event {
+                    event_type: EventType::QualitativeWarning { severity: Severity::Medium },
+                    message,
+                })
+            },
+            _ => Some(Event { event_type: EventType::Informational, message }),
+        }
+    }
+}
+
+impl Analyzer for EmergencyAlertAnalyzer {
+    fn get_id(&self) -> Cow<str> {
+        Cow::from("emergency_alert")
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("ETWS/CMAS Emergency Alert")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from("Records emergency alerts broadcast in SIB10/11/12, and tests whether they came from a new cell or only one cell.")
+    }
+
+    fn get_interests(&self) -> Interests {
+        Interests::lte_channels([LteChannel::BcchDlSch])
+    }
+
+    fn analyze_information_element(&mut self, ie: &InformationElement, context: &PacketContext) -> Option<Event> {
+        let cell = context.cell?;
+        self.first_seen.entry(cell).or_insert(context.timestamp);
+        if let Some(key) = KnownCellKey::from_sib1(ie, Some(cell)) {
+            self.identities.insert(cell, key);
+            return None;
+        }
+        let sibs = system_information_blocks(ie)?;
+
+        let mut events = Vec::new();
+        for sib in sibs {
+            if let SystemInformation_r8_IEsSib_TypeAndInfo_Entry::Sib10(sib10) = sib {
+                let id = (
+                    bits_to_u32(sib10.message_identifier.0.iter().by_vals()) as u16,
+                    bits_to_u32(sib10.serial_number.0.iter().by_vals()) as u16,
+                );
+                events.extend(self.record_alert(id, None, cell, context));
+                continue;
+            }
+            let Some(segment) = Self::segment(sib) else {
+                continue;
+            };
+            self.expire_partial_alerts(context.timestamp);
+            let partial_alert = self.partial_alerts.entry((cell, segment.id))
+                .or_insert_with(|| PartialAlert::new(context.timestamp));
+            partial_alert.updated = context.timestamp;
+            if let Some(text) = partial_alert.add(&segment) {
+                self.partial_alerts.remove(&(cell, segment.id));
+                events.extend(self.record_alert(segment.id, Some(text), cell, context));
+            }
+        }
+        // a single message rarely completes more than one alert, so prefer
+        // whichever is a warning
+        events.into_iter().max_by_key(|event| matches!(event.event_type, EventType::QualitativeWarning { .. }))
+    }
+
+    fn on_recording_start(&mut self) {
+        self.first_seen.clear();
+        self.identities.clear();
+        self.partial_alerts.clear();
+        self.alerts.clear();
+    }
+
+    fn on_recording_end(&mut self) -> Vec<Event> {
+        // with several cells in range, a real alert should be broadcast by
+        // more than one of them
+        if self.first_seen.len() < 2 {
+            return Vec::new();
+        }
+        let mut alerts: Vec<&Alert> = self.alerts.values().collect();
+        alerts.sort_by_key(|alert| alert.received);
+        alerts.into_iter()
+            .filter(|alert| alert.cells.len() == 1)
+            .map(|alert| {
+                let (message_identifier, serial_number) = alert.id;
+                let cell = alert.cells.iter().next().unwrap();
+                Event {
+                    event_type: EventType::QualitativeWarning { severity: Severity::Low },
+                    message: format!(
+                        "{} (message ID {}, serial number {:#06x}) was only broadcast by PCI {} on EARFCN {}, out of {} cells seen",
+                        alert_category(message_identifier), message_identifier, serial_number,
+                        cell.phy_cell_id, cell.earfcn, self.first_seen.len(),
+                    ),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use crate::analysis::known_cells::KnownCellsDb;
+    use crate::analysis::test_util::{self, context_on, time};
+    use crate::analysis::information_element::LteInformationElement;
+    use telcom_parser::lte_rrc::*;
+
+    const PRESIDENTIAL: u16 = 4370;
+
+    fn sib12(serial_number: u16, number: u8, last: bool, segment: &[u8]) -> SystemInformation_r8_IEsSib_TypeAndInfo_Entry {
+        SystemInformation_r8_IEsSib_TypeAndInfo_Entry::Sib12_v920(SystemInformationBlockType12_r9 {
+            message_identifier_r9: SystemInformationBlockType12_r9MessageIdentifier_r9(
+                (0..16).rev().map(|i| (PRESIDENTIAL >> i) & 1 == 1).collect()
+            ),
+            serial_number_r9: SystemInformationBlockType12_r9SerialNumber_r9(
+                (0..16).rev().map(|i| (serial_number >> i) & 1 == 1).collect()
+            ),
+            warning_message_segment_type_r9: SystemInformationBlockType12_r9WarningMessageSegmentType_r9(last as u8),
+            warning_message_segment_number_r9: SystemInformationBlockType12_r9WarningMessageSegmentNumber_r9(number),
+            warning_message_segment_r9: SystemInformationBlockType12_r9WarningMessageSegment_r9(segment.to_vec()),
+            data_coding_scheme_r9: (number == 0).then(|| SystemInformationBlockType12_r9DataCodingScheme_r9(vec![0x01])),
+            late_non_critical_extension: None,
+        })
+    }
+
+    fn system_information(sibs: Vec<SystemInformation_r8_IEsSib_TypeAndInfo_Entry>) -> InformationElement {
+        let message = BCCH_DL_SCH_Message {
+            message: BCCH_DL_SCH_MessageType::C1(BCCH_DL_SCH_MessageType_c1::SystemInformation(SystemInformation {
+                critical_extensions: SystemInformationCriticalExtensions::SystemInformation_r8(SystemInformation_r8_IEs {
+                    sib_type_and_info: SystemInformation_r8_IEsSib_TypeAndInfo(sibs),
+                    non_critical_extension: None,
+                }),
+            })),
+        };
+        InformationElement::LTE(Box::new(LteInformationElement::BcchDlSch(message)))
+    }
+
+    fn context(phy_cell_id: u16, seconds: u32) -> PacketContext {
+        PacketContext { timestamp: time(seconds.into()), ..context_on(5230, phy_cell_id) }
+    }
+
+    // "Test alert" in GSM 7 bit, as a single page of CB data
+    fn cb_data() -> Vec<u8> {
+        let mut page = vec![0xd4, 0xf2, 0x9c, 0x0e, 0x0a, 0xb3, 0xcb, 0x72, 0x3a];
+        let length = page.len() as u8;
+        page.resize(82, 0);
+        let mut data = vec![1];
+        data.extend(page);
+        data.push(length);
+        data
+    }
+
+    #[test]
+    fn test_decode_gsm7() {
+        assert_eq!(decode_warning_message(&cb_data(), Some(0x01)), "Test alert");
+    }
+
+    #[test]
+    fn test_segmented_alert() {
+        let mut analyzer = EmergencyAlertAnalyzer::new(Arc::new(Mutex::new(KnownCellsDb::new(None, 100))));
+        let data = cb_data();
+        let (first, second) = data.split_at(40);
+        assert!(analyzer.analyze_information_element(&system_information(vec![]), &context(1, 0)).is_none());
+        // segments can arrive out of order
+        assert!(analyzer.analyze_information_element(&system_information(vec![sib12(0x3000, 1, true, second)]), &context(1, 10)).is_none());
+        let event = analyzer.analyze_information_element(&system_information(vec![sib12(0x3000, 0, false, first)]), &context(1, 20)).unwrap();
+        assert_eq!(event.event_type, EventType::QualitativeWarning { severity: Severity::Medium });
+        assert_eq!(
+            event.message,
+            "CMAS Presidential Alert (message ID 4370, serial number 0x3000) from PCI 1 on EARFCN 5230: \"Test alert\". The cell was first seen 20 seconds earlier",
+        );
+        // rebroadcasts aren't reported again
+        assert!(analyzer.analyze_information_element(&system_information(vec![sib12(0x3000, 0, false, first)]), &context(1, 30)).is_none());
+        assert!(analyzer.analyze_information_element(&system_information(vec![sib12(0x3000, 1, true, second)]), &context(1, 40)).is_none());
+
+        // another cell we've seen for a while broadcasts a different alert
+        assert!(analyzer.analyze_information_element(&system_information(vec![]), &context(2, 0)).is_none());
+        let ie = system_information(vec![sib12(0x3001, 0, true, &data)]);
+        let event = analyzer.analyze_information_element(&ie, &context(2, 600)).unwrap();
+        assert_eq!(event.event_type, EventType::Informational);
+
+        let summary = analyzer.on_recording_end();
+        assert_eq!(summary.len(), 2);
+        assert!(summary[0].message.contains("serial number 0x3000) was only broadcast by PCI 1 on EARFCN 5230, out of 2 cells seen"));
+    }
+
+    #[test]
+    fn test_known_cell() {
+        let db = Arc::new(Mutex::new(KnownCellsDb::new(None, 100)));
+        let known = test_util::sib1(|_| {});
+        let key = KnownCellKey::from_sib1(&known, Some(PhysicalCell { earfcn: 5230, phy_cell_id: 1 })).unwrap();
+        db.lock().unwrap().observe(key, time(-86400));
+        let mut analyzer = EmergencyAlertAnalyzer::new(db);
+        let ie = system_information(vec![sib12(0x3000, 0, true, &cb_data())]);
+        let mut alert = |sib1: Option<&InformationElement>| {
+            analyzer.on_recording_start();
+            if let Some(sib1) = sib1 {
+                assert!(analyzer.analyze_information_element(sib1, &context(1, 0)).is_none());
+            }
+            analyzer.analyze_information_element(&ie, &context(1, 10)).unwrap()
+        };
+
+        assert_eq!(alert(Some(&known)).event_type, EventType::Informational);
+
+        // a different cell reusing the known cell's EARFCN and PCI
+        let impostor = test_util::sib1(|sib1| {
+            let cell_identity = &mut sib1.cell_access_related_info.cell_identity.0;
+            let bit = cell_identity[0];
+            cell_identity.set(0, !bit);
+        });
+        let event = alert(Some(&impostor));
+        assert_eq!(event.event_type, EventType::QualitativeWarning { severity: Severity::Medium });
+        assert!(event.message.ends_with("The cell was first seen 10 seconds earlier"));
+
+        // without a SIB1 we don't know which cell it was
+        let event = alert(None);
+        assert_eq!(event.event_type, EventType::QualitativeWarning { severity: Severity::Medium });
+    }
+
+    #[test]
+    fn test_alert_limits() {
+        let mut analyzer = EmergencyAlertAnalyzer::new(Arc::new(Mutex::new(KnownCellsDb::new(None, 100))));
+        let data = cb_data();
+        let (first, second) = data.split_at(40);
+        // a partial alert which stalls is forgotten
+        assert!(analyzer.analyze_information_element(&system_information(vec![sib12(0x3000, 0, false, first)]), &context(1, 0)).is_none());
+        assert!(analyzer.analyze_information_element(&system_information(vec![sib12(0x3000, 1, true, second)]), &context(1, 900)).is_none());
+        assert_eq!(analyzer.partial_alerts.len(), 1);
+
+        for serial_number in 0..(MAX_PARTIAL_ALERTS as u16 * 2) {
+            let ie = system_information(vec![sib12(serial_number, 0, false, first)]);
+            assert!(analyzer.analyze_information_element(&ie, &context(1, 1000 + serial_number as u32)).is_none());
+        }
+        assert_eq!(analyzer.partial_alerts.len(), MAX_PARTIAL_ALERTS);
+
+        for serial_number in 0..(MAX_ALERTS as u16 * 2) {
+            let ie = system_information(vec![sib12(serial_number, 0, true, &data)]);
+            assert!(analyzer.analyze_information_element(&ie, &context(1, 2000 + serial_number as u32)).is_some());
+        }
+        assert_eq!(analyzer.alerts.len(), MAX_ALERTS);
+        // the most recent alerts are the ones we kept
+        assert!(analyzer.alerts.contains_key(&(PRESIDENTIAL, MAX_ALERTS as u16 * 2 - 1)));
+        assert!(!analyzer.alerts.contains_key(&(PRESIDENTIAL, 0)));
+    }
+}
@@ -0,0 +1,165 @@
+use std::borrow::Cow;
+
+use telcom_parser::lte_rrc::{DL_DCCH_MessageType, DL_DCCH_MessageType_c1, IdleModeMobilityControlInfo, RRCConnectionReleaseCriticalExtensions, RRCConnectionReleaseCriticalExtensions_c1};
+
+use super::analyzer::{Analyzer, Event, EventType, Interests, Severity};
+use super::context::PacketContext;
+use super::information_element::{InformationElement, LteChannel, LteInformationElement};
+use super::priority_2g_downgrade::DowngradeCarrier;
+use super::util::unpack;
+
+// T320 is an enumeration of these values, in minutes
+const T320_MINUTES: [u32; 7] = [5, 10, 20, 30, 60, 120, 180];
+
+fn legacy_carriers(info: &IdleModeMobilityControlInfo) -> Vec<DowngradeCarrier> {
+    let geran = info.freq_priority_list_geran.iter()
+        .flat_map(|list| &list.0)
+        .map(|freqs| DowngradeCarrier {
+            generation: 2,
+            arfcn: freqs.carrier_freqs.starting_arfcn.0,
+            priority: freqs.cell_reselection_priority.0,
+        });
+    let utra_fdd = info.freq_priority_list_utra_fdd.iter()
+        .flat_map(|list| &list.0)
+        .map(|freq| DowngradeCarrier { generation: 3, arfcn: freq.carrier_freq.0, priority: freq.cell_reselection_priority.0 });
+    let utra_tdd = info.freq_priority_list_utra_tdd.iter()
+        .flat_map(|list| &list.0)
+        .map(|freq| DowngradeCarrier { generation: 3, arfcn: freq.carrier_freq.0, priority: freq.cell_reselection_priority.0 });
+    geran.chain(utra_fdd).chain(utra_tdd).collect()
+}
+
+fn validity(info: &IdleModeMobilityControlInfo) -> String {
+    match info.t320.as_ref().and_then(|t320| T320_MINUTES.get(t320.0 as usize)) {
+        Some(minutes) => format!("for {} minutes (T320)", minutes),
+        None => "until the UE next connects or reselects a PLMN".to_string(),
+    }
+}
+
+/// An RRCConnectionRelease can give the UE dedicated reselection priorities
+/// which override the ones broadcast in SIBs, for up to T320. Unlike a
+/// redirect, the UE doesn't move straight away, but once LTE is outranked by
+/// 2G or 3G it'll reselect to them as soon as it can.
+#[derive(Default)]
+pub struct IdleModeMobilityDowngradeAnalyzer {
+}
+
+impl Analyzer for IdleModeMobilityDowngradeAnalyzer {
+    fn get_id(&self) -> Cow<str> {
+        Cow::from("idle_mode_mobility_downgrade")
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Connection Release/Idle Mode Priority Downgrade")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from("Tests if a cell releases our connection with dedicated reselection priorities which rank 2G/3G above LTE.")
+    }
+
+    fn get_interests(&self) -> Interests {
+        Interests::lte_channels([LteChannel::DlDcch])
+    }
+
+    fn analyze_information_element(&mut self, ie: &InformationElement, _context: &PacketContext) -> Option<Event> {
+        unpack!(InformationElement::LTE(lte_ie) = ie);
+        unpack!(LteInformationElement::DlDcch(msg_cont) = &**lte_ie);
+        unpack!(DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::RrcConnectionRelease(release)) = &msg_cont.message);
+        unpack!(RRCConnectionReleaseCriticalExtensions::C1(c1) = &release.critical_extensions);
+        unpack!(RRCConnectionReleaseCriticalExtensions_c1::RrcConnectionRelease_r8(r8_ies) = c1);
+        unpack!(Some(info) = &r8_ies.idle_mode_mobility_control_info);
+
+        // LTE frequencies missing from the dedicated priorities aren't
+        // considered for reselection at all
+        let lte_priority = info.freq_priority_list_eutra.iter()
+            .flat_map(|list| &list.0)
+            .map(|freq| freq.cell_reselection_priority.0)
+            .max();
+        let downgrades: Vec<DowngradeCarrier> = legacy_carriers(info).into_iter()
+            .filter(|carrier| lte_priority.is_none_or(|lte_priority| carrier.priority > lte_priority))
+            .collect();
+        if downgrades.is_empty() {
+            return None;
+        }
+
+        let severity = if downgrades.iter().any(|carrier| carrier.generation == 2) {
+            Severity::High
+        } else {
+            Severity::Medium
+        };
+        let lte_priority = match lte_priority {
+            Some(priority) => format!("at most {}", priority),
+            None => "none".to_string(),
+        };
+        let carriers: Vec<String> = downgrades.iter().map(|carrier| carrier.to_string()).collect();
+        Some(Event {
+            event_type: EventType::QualitativeWarning { severity },
+            message: format!(
+                "RRCConnectionRelease ranked 2G/3G above LTE (LTE priority {}) {}: {}",
+                lte_priority, validity(info), carriers.join(", "),
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::test_util::context;
+    use telcom_parser::lte_rrc::*;
+
+    fn release(info: IdleModeMobilityControlInfo) -> InformationElement {
+        let release = RRCConnectionRelease {
+            rrc_transaction_identifier: RRC_TransactionIdentifier(0),
+            critical_extensions: RRCConnectionReleaseCriticalExtensions::C1(
+                RRCConnectionReleaseCriticalExtensions_c1::RrcConnectionRelease_r8(RRCConnectionRelease_r8_IEs {
+                    release_cause: ReleaseCause(ReleaseCause::OTHER),
+                    redirected_carrier_info: None,
+                    idle_mode_mobility_control_info: Some(info),
+                    non_critical_extension: None,
+                })
+            ),
+        };
+        let message = DL_DCCH_Message {
+            message: DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::RrcConnectionRelease(release)),
+        };
+        InformationElement::LTE(Box::new(LteInformationElement::DlDcch(Box::new(message))))
+    }
+
+    fn priorities(eutra: &[u8], utra_fdd: &[u8], t320: Option<u8>) -> IdleModeMobilityControlInfo {
+        IdleModeMobilityControlInfo {
+            freq_priority_list_eutra: Some(FreqPriorityListEUTRA(eutra.iter().map(|priority| FreqPriorityEUTRA {
+                carrier_freq: ARFCN_ValueEUTRA(5230),
+                cell_reselection_priority: CellReselectionPriority(*priority),
+            }).collect())),
+            freq_priority_list_geran: None,
+            freq_priority_list_utra_fdd: Some(FreqPriorityListUTRA_FDD(utra_fdd.iter().map(|priority| FreqPriorityUTRA_FDD {
+                carrier_freq: ARFCN_ValueUTRA(10700),
+                cell_reselection_priority: CellReselectionPriority(*priority),
+            }).collect())),
+            freq_priority_list_utra_tdd: None,
+            band_class_priority_list_hrpd: None,
+            band_class_priority_list1_xrtt: None,
+            t320: t320.map(IdleModeMobilityControlInfoT320),
+        }
+    }
+
+    #[test]
+    fn test_dedicated_priorities() {
+        let mut analyzer = IdleModeMobilityDowngradeAnalyzer::default();
+        assert!(analyzer.analyze_information_element(&release(priorities(&[5, 6], &[2], None)), &context()).is_none());
+
+        let event = analyzer.analyze_information_element(&release(priorities(&[5], &[7], Some(3))), &context()).unwrap();
+        assert_eq!(event.event_type, EventType::QualitativeWarning { severity: Severity::Medium });
+        assert_eq!(
+            event.message,
+            "RRCConnectionRelease ranked 2G/3G above LTE (LTE priority at most 5) for 30 minutes (T320): 3G carrier on UARFCN 10700 at priority 7",
+        );
+
+        let event = analyzer.analyze_information_element(&release(priorities(&[], &[0], None)), &context()).unwrap();
+        assert!(event.message.contains("(LTE priority none) until the UE next connects"));
+    }
+}
@@ -0,0 +1,195 @@
+use std::borrow::Cow;
+
+use telcom_parser::lte_rrc::{PCCH_MessageType, PCCH_MessageType_c1, PagingUE_Identity};
+
+use super::analyzer::{Analyzer, Event, EventType, Interests, Severity};
+use super::context::PacketContext;
+use super::information_element::{InformationElement, LteChannel, LteInformationElement};
+use super::nas::{EmmMessage, EMM_ATTACH_REQUEST, EMM_IDENTITY_RESPONSE};
+
+const MOBILE_IDENTITY_TYPE_IMSI: u8 = 0x01;
+
+/// Looks for IMSI-based paging. Networks page devices by their S-TMSI, so
+/// paging by IMSI reveals the IMSI to anyone listening, and is a common way
+/// for IMSI catchers to check whether a target is nearby. If we know our own
+/// IMSI, either from the config or because we sent it in a NAS message, we can
+/// also tell whether it's our device being paged.
+pub struct ImsiPagingAnalyzer {
+    imsi: Option<String>,
+}
+
+impl ImsiPagingAnalyzer {
+    pub fn new(imsi: Option<String>) -> Self {
+        Self { imsi }
+    }
+
+    // Never includes the paged IMSI, since it may be a bystander's
+    fn page_event(&self, paged_imsi: &str) -> Event {
+        match &self.imsi {
+            Some(imsi) if imsi == paged_imsi => Event {
+                event_type: EventType::QualitativeWarning { severity: Severity::High },
+                message: "Cell paged this device by its IMSI".to_string(),
+            },
+            Some(_) => Event {
+                event_type: EventType::QualitativeWarning { severity: Severity::Low },
+                message: "Cell paged another device by its IMSI".to_string(),
+            },
+            None => Event {
+                event_type: EventType::QualitativeWarning { severity: Severity::Medium },
+                message: "Cell paged a device by its IMSI, but this device's IMSI isn't known yet".to_string(),
+            },
+        }
+    }
+}
+
+// Extracts the IMSI from an uplink attach request or identity response, if
+// it's sent unencrypted
+fn imsi_from_nas(payload: &[u8]) -> Option<String> {
+    let message = EmmMessage::parse(payload)?;
+    // ciphered messages are logged after they're deciphered, but we only
+    // care about identities which were sent in the clear
+    if message.is_ciphered() {
+        return None;
+    }
+    let identity = match message.message_type {
+        // skip the NAS key set identifier and attach type
+        EMM_ATTACH_REQUEST => message.body.get(1..)?,
+        EMM_IDENTITY_RESPONSE => message.body,
+        _ => return None,
+    };
+    let length = *identity.first()? as usize;
+    decode_imsi(identity.get(1..1 + length)?)
+}
+
+// Decodes a mobile identity IE's value, per TS 24.008 10.5.1.4
+fn decode_imsi(value: &[u8]) -> Option<String> {
+    let first = *value.first()?;
+    if first & 0x07 != MOBILE_IDENTITY_TYPE_IMSI {
+        return None;
+    }
+    let mut digits = vec![first >> 4];
+    for octet in &value[1..] {
+        digits.push(octet & 0x0f);
+        digits.push(octet >> 4);
+    }
+    // an even number of digits is padded out with 0xf
+    if first & 0x08 == 0 {
+        digits.pop();
+    }
+    if digits.iter().any(|digit| *digit > 9) {
+        return None;
+    }
+    Some(digits.iter().map(|digit| digit.to_string()).collect())
+}
+
+impl Analyzer for ImsiPagingAnalyzer {
+    fn get_id(&self) -> Cow<str> {
+        Cow::from("imsi_paging")
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("IMSI Paging")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from("Tests whether a cell pages devices by their IMSI, and whether the IMSI paged is this device's")
+    }
+
+    fn get_interests(&self) -> Interests {
+        Interests::lte_channels([LteChannel::PCCH, LteChannel::NAS])
+    }
+
+    fn analyze_information_element(&mut self, ie: &InformationElement, _context: &PacketContext) -> Option<Event> {
+        let pcch_msg = match ie {
+            InformationElement::LTE(lte_ie) => match &** lte_ie {
+                LteInformationElement::PCCH(pcch_msg) => pcch_msg,
+                LteInformationElement::NAS(payload) => {
+                    if self.imsi.is_none() {
+                        self.imsi = imsi_from_nas(payload);
+                    }
+                    return None;
+                },
+                _ => return None,
+            }
+            _ => return None,
+        };
+        let PCCH_MessageType::C1(PCCH_MessageType_c1::Paging(paging)) = &pcch_msg.message else {
+            return None;
+        };
+        // a page for our device is more important than one for anyone else's
+        paging.paging_record_list.as_ref()?.0.iter()
+            .filter_map(|record| match &record.ue_identity {
+                PagingUE_Identity::Imsi(imsi) => Some(imsi.0.iter().map(|digit| digit.0.to_string()).collect::<String>()),
+                _ => None,
+            })
+            .map(|imsi| self.page_event(&imsi))
+            .max_by_key(|event| match event.event_type {
+                EventType::QualitativeWarning { severity } => severity as u8,
+                EventType::Informational => 0,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::test_util::context;
+    use telcom_parser::lte_rrc::{IMSI, IMSI_Digit, PCCH_Message, Paging, PagingRecord, PagingRecordCn_Domain, PagingRecordList};
+
+    const OUR_IMSI: &str = "310260123456789";
+
+    fn paging(imsi: &str) -> InformationElement {
+        let imsi = IMSI(imsi.chars().map(|digit| IMSI_Digit(digit.to_digit(10).unwrap() as u8)).collect());
+        let message = PCCH_Message {
+            message: PCCH_MessageType::C1(PCCH_MessageType_c1::Paging(Paging {
+                paging_record_list: Some(PagingRecordList(vec![PagingRecord {
+                    ue_identity: PagingUE_Identity::Imsi(imsi),
+                    cn_domain: PagingRecordCn_Domain(PagingRecordCn_Domain::PS),
+                }])),
+                system_info_modification: None,
+                etws_indication: None,
+                non_critical_extension: None,
+            })),
+        };
+        InformationElement::LTE(Box::new(LteInformationElement::PCCH(message)))
+    }
+
+    fn severity(event: Option<Event>) -> Severity {
+        match event.unwrap().event_type {
+            EventType::QualitativeWarning { severity } => severity,
+            EventType::Informational => panic!("expected a warning"),
+        }
+    }
+
+    #[test]
+    fn test_decode_imsi() {
+        // identity response with an odd number of digits
+        let identity_response = [0x07, 0x56, 0x08, 0x39, 0x01, 0x62, 0x10, 0x32, 0x54, 0x76, 0x98];
+        assert_eq!(imsi_from_nas(&identity_response).as_deref(), Some(OUR_IMSI));
+        // integrity protected attach request with an even number of digits
+        let attach_request = [0x17, 0, 0, 0, 0, 1, 0x07, 0x41, 0x71, 0x08, 0x31, 0x10, 0x62, 0x10, 0x32, 0x54, 0x76, 0xf8];
+        assert_eq!(imsi_from_nas(&attach_request).as_deref(), Some("30126012345678"));
+        // ciphered messages can't be read
+        assert_eq!(imsi_from_nas(&[0x27, 0, 0, 0, 0, 1, 0x07, 0x56]), None);
+        // identity request, ID type IMSI
+        assert_eq!(imsi_from_nas(&[0x07, 0x55, 0x01]), None);
+    }
+
+    #[test]
+    fn test_imsi_paging() {
+        let mut analyzer = ImsiPagingAnalyzer::new(None);
+        assert_eq!(severity(analyzer.analyze_information_element(&paging(OUR_IMSI), &context())), Severity::Medium);
+
+        let identity_response = vec![0x07, 0x56, 0x08, 0x39, 0x01, 0x62, 0x10, 0x32, 0x54, 0x76, 0x98];
+        let nas = InformationElement::LTE(Box::new(LteInformationElement::NAS(identity_response)));
+        assert!(analyzer.analyze_information_element(&nas, &context()).is_none());
+        assert_eq!(severity(analyzer.analyze_information_element(&paging(OUR_IMSI), &context())), Severity::High);
+        let event = analyzer.analyze_information_element(&paging("001010000000001"), &context());
+        assert!(!event.as_ref().unwrap().message.contains("001010000000001"));
+        assert_eq!(severity(event), Severity::Low);
+    }
+}
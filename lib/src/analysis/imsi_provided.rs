@@ -2,13 +2,26 @@ use std::borrow::Cow;
 
 use telcom_parser::lte_rrc::{PCCH_MessageType, PCCH_MessageType_c1, PagingUE_Identity};
 
-use super::analyzer::{Analyzer, Event, EventType, Severity};
-use super::information_element::{InformationElement, LteInformationElement};
+use super::analyzer::{Analyzer, Event, EventType, Interests, Severity};
+use super::context::PacketContext;
+use super::information_element::{InformationElement, LteChannel, LteInformationElement};
 
+/// Flags any IMSI-based paging at all, without telling apart pages for this
+/// device from pages for others.
+#[deprecated(note = "use imsi_paging::ImsiPagingAnalyzer, which the Harness runs instead")]
 pub struct ImsiProvidedAnalyzer {
 }
 
+#[allow(deprecated)]
 impl Analyzer for ImsiProvidedAnalyzer {
+    fn get_id(&self) -> Cow<str> {
+        Cow::from("imsi_provided")
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
     fn get_name(&self) -> Cow<str> {
         Cow::from("IMSI Provided")
     }
@@ -17,7 +30,11 @@ impl Analyzer for ImsiProvidedAnalyzer {
         Cow::from("Tests whether the UE's IMSI was ever provided to the cell")
     }
 
-    fn analyze_information_element(&mut self, ie: &InformationElement) -> Option<Event> {
+    fn get_interests(&self) -> Interests {
+        Interests::lte_channels([LteChannel::PCCH])
+    }
+
+    fn analyze_information_element(&mut self, ie: &InformationElement, _context: &PacketContext) -> Option<Event> {
         let pcch_msg = match ie {
             InformationElement::LTE(lte_ie) => match &** lte_ie {
                 LteInformationElement::PCCH(pcch_msg) => pcch_msg,
@@ -1,37 +1,139 @@
 use std::borrow::Cow;
+use std::fmt;
 
-use super::analyzer::{Analyzer, Event, EventType, Severity};
-use super::information_element::{InformationElement, LteInformationElement};
+use super::analyzer::{Analyzer, Event, EventType, Interests, Severity};
+use super::config::ImsiRequestedParams;
+use super::context::PacketContext;
+use super::information_element::{InformationElement, LteChannel, LteInformationElement};
+use super::nas::{EmmMessage, EMM_ATTACH_REQUEST, EMM_IDENTITY_REQUEST, EMM_SECURITY_MODE_COMPLETE, PLAIN_NAS_MESSAGE};
 
-const PACKET_THRESHHOLD: usize = 150;
+/// The identity types an EMM Identity Request can ask for, from TS 24.301
+/// 9.9.3.17.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IdentityType {
+    Imsi,
+    Imei,
+    Imeisv,
+    Tmsi,
+}
+
+impl IdentityType {
+    const ALL: [IdentityType; 4] = [IdentityType::Imsi, IdentityType::Imei, IdentityType::Imeisv, IdentityType::Tmsi];
+
+    fn from_octet(octet: u8) -> Option<Self> {
+        match octet & 0x07 {
+            1 => Some(IdentityType::Imsi),
+            2 => Some(IdentityType::Imei),
+            3 => Some(IdentityType::Imeisv),
+            4 => Some(IdentityType::Tmsi),
+            _ => None,
+        }
+    }
+
+    // IMSIs and IMEIs identify the subscriber or their device permanently,
+    // unlike TMSIs
+    fn is_permanent(&self) -> bool {
+        !matches!(self, IdentityType::Tmsi)
+    }
+}
+
+impl fmt::Display for IdentityType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            IdentityType::Imsi => "IMSI",
+            IdentityType::Imei => "IMEI",
+            IdentityType::Imeisv => "IMEISV",
+            IdentityType::Tmsi => "TMSI",
+        };
+        f.write_str(name)
+    }
+}
 
+/// Looks for NAS Identity Requests for any identity type, and whether they
+/// were sent before NAS security was established, in which case the identity
+/// is sent back in the clear.
 pub struct ImsiRequestedAnalyzer {
-    packet_num: usize,
+    packet_threshold: u64,
+    security_established: bool,
+    // how many times each IdentityType was requested
+    requests: [usize; 4],
 }
 
 impl Default for ImsiRequestedAnalyzer {
     fn default() -> Self {
-        Self::new()
+        Self::new(ImsiRequestedParams::default().packet_threshold)
     }
 }
 
 impl ImsiRequestedAnalyzer {
-    pub fn new() -> Self {
-        Self { packet_num: 0 }
+    pub fn new(packet_threshold: u64) -> Self {
+        Self { packet_threshold, security_established: false, requests: [0; 4] }
+    }
+
+    fn identity_request_event(&self, identity_type: IdentityType, context: &PacketContext) -> Event {
+        if self.security_established {
+            return Event {
+                event_type: if identity_type.is_permanent() {
+                    EventType::QualitativeWarning { severity: Severity::Low }
+                } else {
+                    EventType::Informational
+                },
+                message: format!("NAS {} identity request detected after security mode was established", identity_type),
+            };
+        }
+        if !identity_type.is_permanent() {
+            return Event {
+                event_type: EventType::QualitativeWarning { severity: Severity::Low },
+                message: format!("NAS {} identity request detected before security mode was established", identity_type),
+            };
+        }
+        if context.message_index < self.packet_threshold {
+            Event {
+                event_type: EventType::QualitativeWarning {
+                    severity: Severity::Medium
+                },
+                message: format!(
+                    "NAS {} identity request detected before security mode \
+                    was established, however it was within the first {} \
+                    packets of this analysis. If you just turned your device \
+                    on, this is likely a false-positive.",
+                    identity_type,
+                    self.packet_threshold
+                )
+            }
+        } else {
+            Event {
+                event_type: EventType::QualitativeWarning {
+                    severity: Severity::High
+                },
+                message: format!("NAS {} identity request detected before security mode was established", identity_type),
+            }
+        }
     }
 }
 
 impl Analyzer for ImsiRequestedAnalyzer {
+    fn get_id(&self) -> Cow<str> {
+        Cow::from("imsi_requested")
+    }
+
+    fn get_version(&self) -> u32 {
+        2
+    }
+
     fn get_name(&self) -> Cow<str> {
-        Cow::from("IMSI Requested")
+        Cow::from("Identity Requested")
     }
 
     fn get_description(&self) -> Cow<str> {
-        Cow::from("Tests whether the ME sends an IMSI Identity Request NAS message")
+        Cow::from("Tests whether the ME sends a NAS Identity Request for its IMSI, IMEI or IMEISV, and whether security mode was established beforehand")
+    }
+
+    fn get_interests(&self) -> Interests {
+        Interests::lte_channels([LteChannel::NAS])
     }
 
-    fn analyze_information_element(&mut self, ie: &InformationElement) -> Option<Event> {
-        self.packet_num += 1;
+    fn analyze_information_element(&mut self, ie: &InformationElement, context: &PacketContext) -> Option<Event> {
         let payload = match ie {
             InformationElement::LTE(inner) => match &**inner {
                 LteInformationElement::NAS(payload) => payload,
@@ -39,31 +141,100 @@ impl Analyzer for ImsiRequestedAnalyzer {
             }
             _ => return None,
         };
+        let message = EmmMessage::parse(payload)?;
 
-        // NAS identity request, ID type IMSI
-        if payload == &[0x07, 0x55, 0x01] {
-            if self.packet_num < PACKET_THRESHHOLD {
-                return Some(Event {
-                    event_type: EventType::QualitativeWarning {
-                        severity: Severity::Medium
-                    },
-                    message: format!(
-                        "NAS IMSI identity request detected, however it was within \
-                        the first {} packets of this analysis. If you just \
-                        turned your device on, this is likely a \
-                        false-positive.",
-                        PACKET_THRESHHOLD
-                    )
-                })
-            } else {
-                return Some(Event {
-                    event_type: EventType::QualitativeWarning {
-                        severity: Severity::High
-                    },
-                    message: "NAS IMSI identity request detected".to_owned(),
-                })
-            }
+        match message.message_type {
+            EMM_IDENTITY_REQUEST => {
+                let identity_type = IdentityType::from_octet(*message.body.first()?)?;
+                self.requests[identity_type as usize] += 1;
+                Some(self.identity_request_event(identity_type, context))
+            },
+            EMM_SECURITY_MODE_COMPLETE => {
+                self.security_established = true;
+                None
+            },
+            // a new attach starts over without a security context
+            EMM_ATTACH_REQUEST if message.security_header_type == PLAIN_NAS_MESSAGE => {
+                self.security_established = false;
+                None
+            },
+            _ => {
+                if message.is_ciphered() {
+                    self.security_established = true;
+                }
+                None
+            },
         }
-        None
+    }
+
+    fn on_recording_start(&mut self) {
+        self.security_established = false;
+        self.requests = [0; 4];
+    }
+
+    fn on_recording_end(&mut self) -> Vec<Event> {
+        IdentityType::ALL.iter()
+            .filter(|identity_type| self.requests[**identity_type as usize] > 0)
+            .map(|identity_type| {
+                let times = match self.requests[*identity_type as usize] {
+                    1 => "once".to_string(),
+                    count => format!("{} times", count),
+                };
+                Event {
+                    event_type: EventType::Informational,
+                    message: format!("{} was requested {} in this recording", identity_type, times),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::test_util;
+
+    fn nas(payload: &[u8]) -> InformationElement {
+        InformationElement::LTE(Box::new(LteInformationElement::NAS(payload.to_vec())))
+    }
+
+    fn context(message_index: u64) -> PacketContext {
+        PacketContext { message_index, ..test_util::context() }
+    }
+
+    fn severity(event: Option<Event>) -> Option<Severity> {
+        match event.unwrap().event_type {
+            EventType::QualitativeWarning { severity } => Some(severity),
+            EventType::Informational => None,
+        }
+    }
+
+    #[test]
+    fn test_identity_requests() {
+        let mut analyzer = ImsiRequestedAnalyzer::new(10);
+        assert_eq!(severity(analyzer.analyze_information_element(&nas(&[0x07, 0x55, 0x01]), &context(1))), Some(Severity::Medium));
+        assert_eq!(severity(analyzer.analyze_information_element(&nas(&[0x07, 0x55, 0x01]), &context(20))), Some(Severity::High));
+        // IMEISV with the spare half octet set, and an extra trailing octet
+        let event = analyzer.analyze_information_element(&nas(&[0x07, 0x55, 0xf3, 0x00]), &context(20));
+        assert!(event.as_ref().unwrap().message.contains("IMEISV"));
+        assert_eq!(severity(event), Some(Severity::High));
+        // not an identity request
+        assert!(analyzer.analyze_information_element(&nas(&[0x07, 0x56, 0x01]), &context(20)).is_none());
+
+        // security mode complete, ciphered with a new security context
+        let security_mode_complete = [0x47, 0, 0, 0, 0, 0, 0x07, 0x5e];
+        assert!(analyzer.analyze_information_element(&nas(&security_mode_complete), &context(21)).is_none());
+        let protected_request = [0x27, 0, 0, 0, 0, 1, 0x07, 0x55, 0x02];
+        let event = analyzer.analyze_information_element(&nas(&protected_request), &context(22));
+        assert!(event.as_ref().unwrap().message.contains("IMEI identity request detected after"));
+        assert_eq!(severity(event), Some(Severity::Low));
+
+        let summary = analyzer.on_recording_end();
+        let messages: Vec<&str> = summary.iter().map(|event| event.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "IMSI was requested 2 times in this recording",
+            "IMEI was requested once in this recording",
+            "IMEISV was requested once in this recording",
+        ]);
     }
 }
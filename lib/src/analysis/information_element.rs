@@ -3,6 +3,7 @@
 //! the term to refer to a structured, fully parsed message in any telcom
 //! standard.
 
+use serde::{Deserialize, Serialize};
 use telcom_parser::{decode, lte_rrc};
 use thiserror::Error;
 use crate::gsmtap::{GsmtapMessage, GsmtapType, LteNasSubtype, LteRrcSubtype};
@@ -15,7 +16,7 @@ pub enum InformationElementError {
     UnsupportedGsmtapType(GsmtapType),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum InformationElement {
     GSM,
     UMTS,
@@ -25,7 +26,7 @@ pub enum InformationElement {
     FiveG,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum LteInformationElement {
     DlCcch(lte_rrc::DL_CCCH_Message),
     // This element of the enum is substantially larger than the others,
@@ -59,6 +60,76 @@ pub enum LteInformationElement {
     //ScMcchNb(),
 }
 
+/// The logical channel an [LteInformationElement] was sent over, or `NAS` for
+/// NAS messages. Variant names match those of [LteInformationElement].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LteChannel {
+    DlCcch,
+    DlDcch,
+    UlCcch,
+    UlDcch,
+    BcchBch,
+    BcchDlSch,
+    PCCH,
+    MCCH,
+    ScMcch,
+    BcchBchMbms,
+    BcchDlSchBr,
+    BcchDlSchMbms,
+    SbcchSlBch,
+    SbcchSlBchV2x,
+    NAS,
+}
+
+impl LteChannel {
+    /// Returns the channel a GSMTAP message was sent over, without decoding
+    /// it, or `None` if it's not one we can decode.
+    pub fn from_gsmtap_type(gsmtap_type: GsmtapType) -> Option<Self> {
+        use LteRrcSubtype as L;
+        let channel = match gsmtap_type {
+            GsmtapType::LteRrc(L::DlCcch) => LteChannel::DlCcch,
+            GsmtapType::LteRrc(L::DlDcch) => LteChannel::DlDcch,
+            GsmtapType::LteRrc(L::UlCcch) => LteChannel::UlCcch,
+            GsmtapType::LteRrc(L::UlDcch) => LteChannel::UlDcch,
+            GsmtapType::LteRrc(L::BcchBch) => LteChannel::BcchBch,
+            GsmtapType::LteRrc(L::BcchDlSch) => LteChannel::BcchDlSch,
+            GsmtapType::LteRrc(L::PCCH) => LteChannel::PCCH,
+            GsmtapType::LteRrc(L::MCCH) => LteChannel::MCCH,
+            GsmtapType::LteRrc(L::ScMcch) => LteChannel::ScMcch,
+            GsmtapType::LteRrc(L::BcchBchMbms) => LteChannel::BcchBchMbms,
+            GsmtapType::LteRrc(L::BcchDlSchBr) => LteChannel::BcchDlSchBr,
+            GsmtapType::LteRrc(L::BcchDlSchMbms) => LteChannel::BcchDlSchMbms,
+            GsmtapType::LteRrc(L::SbcchSlBch) => LteChannel::SbcchSlBch,
+            GsmtapType::LteRrc(L::SbcchSlBchV2x) => LteChannel::SbcchSlBchV2x,
+            GsmtapType::LteNas(LteNasSubtype::Plain) => LteChannel::NAS,
+            _ => return None,
+        };
+        Some(channel)
+    }
+}
+
+impl LteInformationElement {
+    pub fn channel(&self) -> LteChannel {
+        match self {
+            LteInformationElement::DlCcch(_) => LteChannel::DlCcch,
+            LteInformationElement::DlDcch(_) => LteChannel::DlDcch,
+            LteInformationElement::UlCcch(_) => LteChannel::UlCcch,
+            LteInformationElement::UlDcch(_) => LteChannel::UlDcch,
+            LteInformationElement::BcchBch(_) => LteChannel::BcchBch,
+            LteInformationElement::BcchDlSch(_) => LteChannel::BcchDlSch,
+            LteInformationElement::PCCH(_) => LteChannel::PCCH,
+            LteInformationElement::MCCH(_) => LteChannel::MCCH,
+            LteInformationElement::ScMcch(_) => LteChannel::ScMcch,
+            LteInformationElement::BcchBchMbms(_) => LteChannel::BcchBchMbms,
+            LteInformationElement::BcchDlSchBr(_) => LteChannel::BcchDlSchBr,
+            LteInformationElement::BcchDlSchMbms(_) => LteChannel::BcchDlSchMbms,
+            LteInformationElement::SbcchSlBch(_) => LteChannel::SbcchSlBch,
+            LteInformationElement::SbcchSlBchV2x(_) => LteChannel::SbcchSlBchV2x,
+            LteInformationElement::NAS(_) => LteChannel::NAS,
+        }
+    }
+}
+
 impl TryFrom<&GsmtapMessage> for InformationElement {
     type Error = InformationElementError;
 
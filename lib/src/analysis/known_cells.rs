@@ -0,0 +1,335 @@
+//! A persistent database of every cell we've observed, so analyzers can tell
+//! the cells a user sees day to day apart from ones which have just appeared.
+//!
+//! Cells are keyed by the PLMN, TAC and cell ID they advertise in SIB1, along
+//! with the EARFCN and PCI they were seen on. The database is saved as JSON,
+//! and holds at most `max_cells` entries, evicting those seen least recently.
+
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{BCCH_DL_SCH_MessageType, BCCH_DL_SCH_MessageType_c1};
+use thiserror::Error;
+
+use super::cell_identity::CellIdentity;
+use super::context::{PacketContext, PhysicalCell};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::unpack;
+
+/// Enough for every cell in a metro area, while keeping the saved database to a
+/// couple of megabytes.
+pub const DEFAULT_MAX_KNOWN_CELLS: usize = 10000;
+
+#[derive(Error, Debug)]
+pub enum KnownCellsError {
+    #[error("Failed to read known cells database {0}: {1}")]
+    ReadError(PathBuf, std::io::Error),
+    #[error("Failed to parse known cells database {0}: {1}")]
+    ParseError(PathBuf, serde_json::Error),
+    #[error("Failed to write known cells database {0}: {1}")]
+    WriteError(PathBuf, std::io::Error),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KnownCellKey {
+    /// The cell's primary PLMN, formatted as "MCC-MNC".
+    pub plmn: String,
+    pub tac: u32,
+    pub cell_identity: u32,
+    pub earfcn: u32,
+    pub pci: u16,
+}
+
+impl KnownCellKey {
+    /// The key of the cell which sent this message, if it's a SIB1 and we
+    /// know which physical cell it was received on.
+    pub fn from_sib1(ie: &InformationElement, cell: Option<PhysicalCell>) -> Option<Self> {
+        unpack!(InformationElement::LTE(lte_ie) = ie);
+        unpack!(LteInformationElement::BcchDlSch(bcch_dl_sch_message) = &**lte_ie);
+        unpack!(BCCH_DL_SCH_MessageType::C1(BCCH_DL_SCH_MessageType_c1::SystemInformationBlockType1(sib1)) = &bcch_dl_sch_message.message);
+        let cell = cell?;
+        let identity = CellIdentity::from_sib1(sib1);
+        Some(KnownCellKey {
+            plmn: identity.plmns.first()?.clone(),
+            tac: identity.tac,
+            cell_identity: identity.cell_identity,
+            earfcn: cell.earfcn,
+            pci: cell.phy_cell_id,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KnownCell {
+    #[serde(flatten)]
+    pub key: KnownCellKey,
+    pub first_seen: DateTime<FixedOffset>,
+    pub last_seen: DateTime<FixedOffset>,
+    /// How many recordings the cell has been seen in.
+    pub observations: u64,
+}
+
+/// What we know about the cells in one tracking area.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AreaStats {
+    pub cells: usize,
+    /// The most recordings any one cell in the area has been seen in, which
+    /// approximates how many times we've visited it.
+    pub visits: u64,
+}
+
+/// The database is shared between every [Harness](super::analyzer::Harness),
+/// and the daemon's API.
+pub type SharedKnownCellsDb = Arc<Mutex<KnownCellsDb>>;
+
+#[derive(Debug)]
+pub struct KnownCellsDb {
+    path: Option<PathBuf>,
+    max_cells: usize,
+    cells: HashMap<KnownCellKey, KnownCell>,
+    // whether there are changes which haven't been saved yet
+    dirty: bool,
+}
+
+impl KnownCellsDb {
+    /// Creates an empty database which is only saved if it has a path.
+    pub fn new(path: Option<PathBuf>, max_cells: usize) -> Self {
+        KnownCellsDb {
+            path,
+            max_cells,
+            cells: HashMap::new(),
+            dirty: false,
+        }
+    }
+
+    /// Loads the database saved at `path`, or creates an empty one if there
+    /// isn't one yet.
+    pub fn load(path: &Path, max_cells: usize) -> Result<Self, KnownCellsError> {
+        let mut db = KnownCellsDb::new(Some(path.to_path_buf()), max_cells);
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(db),
+            Err(err) => return Err(KnownCellsError::ReadError(path.to_path_buf(), err)),
+        };
+        let cells: Vec<KnownCell> = serde_json::from_str(&contents)
+            .map_err(|err| KnownCellsError::ParseError(path.to_path_buf(), err))?;
+        db.cells = cells.into_iter().map(|cell| (cell.key.clone(), cell)).collect();
+        // the cap may have been lowered since the database was saved
+        db.dirty = db.evict();
+        Ok(db)
+    }
+
+    /// Writes the database to its path, if it has one and has changed since
+    /// it was last saved.
+    pub fn save(&mut self) -> Result<(), KnownCellsError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if !self.dirty {
+            return Ok(());
+        }
+        let write_error = |err| KnownCellsError::WriteError(path.clone(), err);
+        let contents = serde_json::to_string(&self.cells()).unwrap();
+        // write to a temporary file first, so losing power mid-write doesn't
+        // cost us the whole database
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, contents).map_err(write_error)?;
+        std::fs::rename(&tmp_path, path).map_err(write_error)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// An in-memory copy of the database which is never saved, so old
+    /// recordings can be re-analysed against what we know without counting
+    /// their cells as being seen again.
+    pub fn snapshot(&self) -> Self {
+        KnownCellsDb {
+            path: None,
+            max_cells: self.max_cells,
+            cells: self.cells.clone(),
+            dirty: false,
+        }
+    }
+
+    /// Records that a cell was seen in a recording, returning whether it was
+    /// new to us.
+    pub fn observe(&mut self, key: KnownCellKey, timestamp: DateTime<FixedOffset>) -> bool {
+        self.dirty = true;
+        if let Some(cell) = self.cells.get_mut(&key) {
+            // re-analysing old recordings can take us back in time
+            cell.first_seen = cell.first_seen.min(timestamp);
+            cell.last_seen = cell.last_seen.max(timestamp);
+            cell.observations += 1;
+            return false;
+        }
+        self.cells.insert(key.clone(), KnownCell {
+            key,
+            first_seen: timestamp,
+            last_seen: timestamp,
+            observations: 1,
+        });
+        self.evict();
+        true
+    }
+
+    /// Updates when an already known cell was last seen.
+    pub fn touch(&mut self, key: &KnownCellKey, timestamp: DateTime<FixedOffset>) {
+        if let Some(cell) = self.cells.get_mut(key) {
+            if timestamp > cell.last_seen {
+                cell.last_seen = timestamp;
+                self.dirty = true;
+            }
+        }
+    }
+
+    pub fn get(&self, key: &KnownCellKey) -> Option<&KnownCell> {
+        self.cells.get(key)
+    }
+
+    pub fn area_stats(&self, plmn: &str, tac: u32) -> AreaStats {
+        let mut stats = AreaStats::default();
+        for cell in self.cells.values().filter(|cell| cell.key.plmn == plmn && cell.key.tac == tac) {
+            stats.cells += 1;
+            stats.visits = stats.visits.max(cell.observations);
+        }
+        stats
+    }
+
+    /// Returns every known cell, most recently seen first.
+    pub fn cells(&self) -> Vec<&KnownCell> {
+        let mut cells: Vec<&KnownCell> = self.cells.values().collect();
+        cells.sort_by_key(|cell| Reverse(cell.last_seen));
+        cells
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn max_cells(&self) -> usize {
+        self.max_cells
+    }
+
+    /// Forgets every known cell.
+    pub fn reset(&mut self) {
+        self.cells.clear();
+        self.dirty = true;
+    }
+
+    // Drops the least recently seen cells until we're within the cap,
+    // returning whether any were dropped
+    fn evict(&mut self) -> bool {
+        if self.cells.len() <= self.max_cells {
+            return false;
+        }
+        let mut by_last_seen: Vec<(DateTime<FixedOffset>, KnownCellKey)> = self.cells.values()
+            .map(|cell| (cell.last_seen, cell.key.clone()))
+            .collect();
+        by_last_seen.sort_by_key(|(last_seen, _)| *last_seen);
+        let excess = self.cells.len() - self.max_cells;
+        for (_, key) in by_last_seen.into_iter().take(excess) {
+            self.cells.remove(&key);
+        }
+        true
+    }
+}
+
+/// Records every cell the [Harness](super::analyzer::Harness) sees a SIB1 from
+/// in the database, whichever analyzers are enabled. Cells are recorded after
+/// the analyzers have seen the message, so they can tell whether it's the
+/// first time we've seen the cell.
+pub struct KnownCellsRecorder {
+    db: SharedKnownCellsDb,
+    // the cells we've already recorded an observation of in this recording
+    seen: HashSet<KnownCellKey>,
+}
+
+impl KnownCellsRecorder {
+    pub fn new(db: SharedKnownCellsDb) -> Self {
+        KnownCellsRecorder {
+            db,
+            seen: HashSet::new(),
+        }
+    }
+
+    pub fn observe(&mut self, ie: &InformationElement, context: &PacketContext) {
+        let Some(key) = KnownCellKey::from_sib1(ie, context.cell) else {
+            return;
+        };
+        let mut db = self.db.lock().unwrap();
+        if self.seen.contains(&key) {
+            db.touch(&key, context.timestamp);
+        } else {
+            db.observe(key.clone(), context.timestamp);
+            self.seen.insert(key);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.seen.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::test_util::time;
+
+    fn key(cell_identity: u32) -> KnownCellKey {
+        KnownCellKey {
+            plmn: "310-260".to_string(),
+            tac: 1,
+            cell_identity,
+            earfcn: 5230,
+            pci: 7,
+        }
+    }
+
+    #[test]
+    fn test_observe_and_evict() {
+        let mut db = KnownCellsDb::new(None, 2);
+        assert!(db.observe(key(1), time(10)));
+        assert!(!db.observe(key(1), time(0)));
+        let cell = db.get(&key(1)).unwrap();
+        assert_eq!((cell.first_seen, cell.last_seen, cell.observations), (time(0), time(10), 2));
+
+        assert!(db.observe(key(2), time(20)));
+        db.touch(&key(1), time(30));
+        assert!(db.observe(key(3), time(40)));
+        assert_eq!(db.len(), 2);
+        assert!(db.get(&key(2)).is_none());
+        assert_eq!(db.area_stats("310-260", 1), AreaStats { cells: 2, visits: 2 });
+        assert_eq!(db.area_stats("310-260", 2), AreaStats::default());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("rayhunter_known_cells_{}.json", std::process::id()));
+        let mut db = KnownCellsDb::load(&path, 10).unwrap();
+        assert!(db.is_empty());
+        db.observe(key(1), time(0));
+        db.observe(key(2), time(10));
+        db.save().unwrap();
+
+        let mut db = KnownCellsDb::load(&path, 1).unwrap();
+        assert_eq!(db.cells().iter().map(|cell| cell.key.cell_identity).collect::<Vec<_>>(), vec![2]);
+        // changes to a snapshot stay in memory
+        let mut snapshot = db.snapshot();
+        snapshot.observe(key(3), time(20));
+        snapshot.save().unwrap();
+        assert!(KnownCellsDb::load(&path, 10).unwrap().get(&key(3)).is_none());
+
+        db.reset();
+        db.save().unwrap();
+        assert!(KnownCellsDb::load(&path, 10).unwrap().is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
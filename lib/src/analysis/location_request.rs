@@ -0,0 +1,404 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use chrono::{DateTime, FixedOffset};
+
+use telcom_parser::lte_rrc::{DL_DCCH_MessageType, DL_DCCH_MessageType_c1, EstablishmentCause, RRCConnectionReconfiguration, RRCConnectionReconfigurationCriticalExtensions, RRCConnectionReconfigurationCriticalExtensions_c1, RRCConnectionRequestCriticalExtensions, ReportConfigToAddModReportConfig, UEInformationRequest_r9CriticalExtensions, UEInformationRequest_r9CriticalExtensions_c1, UEInformationRequest_r9_IEs, UL_CCCH_MessageType, UL_CCCH_MessageType_c1};
+
+use super::analyzer::{Analyzer, Event, EventType, Interests, Severity};
+use super::context::{PacketContext, PhysicalCell};
+use super::information_element::{InformationElement, LteChannel, LteInformationElement};
+use super::known_cells::{KnownCellKey, SharedKnownCellsDb};
+use super::nas::{EmmMessage, EMM_DOWNLINK_GENERIC_NAS_TRANSPORT, GENERIC_CONTAINER_LOCATION_SERVICES, GENERIC_CONTAINER_LPP, SERVICE_TYPE_MO_CSFB_EMERGENCY};
+
+/// Returns the reports a UEInformationRequest asks for which include the UE's
+/// location (or, for the mobility history, the cells it's visited).
+fn requested_location_reports(r9_ies: &UEInformationRequest_r9_IEs) -> Vec<&'static str> {
+    let mut reports = Vec::new();
+    if r9_ies.rlf_report_req_r9.0 {
+        reports.push("radio link failure report");
+    }
+    let v1020 = r9_ies.non_critical_extension.as_ref().and_then(|v930| v930.non_critical_extension.as_ref());
+    if v1020.is_some_and(|v1020| v1020.log_meas_report_req_r10.is_some()) {
+        reports.push("logged measurements");
+    }
+    let v1130 = v1020.and_then(|v1020| v1020.non_critical_extension.as_ref());
+    if v1130.is_some_and(|v1130| v1130.conn_est_fail_report_req_r11.is_some()) {
+        reports.push("connection establishment failure report");
+    }
+    let v1250 = v1130.and_then(|v1130| v1130.non_critical_extension.as_ref());
+    if v1250.is_some_and(|v1250| v1250.mobility_history_report_req_r12.is_some()) {
+        reports.push("mobility history");
+    }
+    reports
+}
+
+/// Returns whether an RRCConnectionReconfiguration's measurement config asks
+/// for the UE's location to be included in its measurement reports.
+fn requests_location_info(reconfiguration: &RRCConnectionReconfiguration) -> bool {
+    let RRCConnectionReconfigurationCriticalExtensions::C1(
+        RRCConnectionReconfigurationCriticalExtensions_c1::RrcConnectionReconfiguration_r8(r8_ies)
+    ) = &reconfiguration.critical_extensions else {
+        return false;
+    };
+    let Some(report_configs) = r8_ies.meas_config.as_ref().and_then(|meas_config| meas_config.report_config_to_add_mod_list.as_ref()) else {
+        return false;
+    };
+    report_configs.0.iter().any(|report_config| matches!(
+        &report_config.report_config,
+        ReportConfigToAddModReportConfig::ReportConfigEUTRA(eutra) if eutra.include_location_info_r10.is_some()
+    ))
+}
+
+/// Flags the network asking for the UE's precise location, either with an RRC
+/// UEInformationRequest for reports which include it, a measurement config
+/// with includeLocationInfo set, or with LPP or location services messages
+/// tunnelled in NAS. These are expected during emergency calls, and less so
+/// from a cell we've never seen before, either earlier in the recording or in
+/// the [known cells database](super::known_cells).
+///
+/// Cells are looked up in the database by the identity they advertise in SIB1,
+/// so a fake base station reusing a known cell's EARFCN and PCI isn't taken
+/// for it.
+pub struct LocationRequestAnalyzer {
+    known_cells: SharedKnownCellsDb,
+    recording_start: Option<DateTime<FixedOffset>>,
+    // the identity each cell advertised in its latest SIB1
+    identities: HashMap<PhysicalCell, KnownCellKey>,
+    // the message index each cell was first seen at in this recording
+    first_seen: HashMap<PhysicalCell, u64>,
+    // the message index the current RRC connection was requested at
+    connection_start: Option<u64>,
+    // NAS messages don't come with a cell, so we attribute them to the last
+    // one we saw an RRC message on
+    current_cell: Option<PhysicalCell>,
+    emergency: bool,
+}
+
+impl LocationRequestAnalyzer {
+    pub fn new(known_cells: SharedKnownCellsDb) -> Self {
+        LocationRequestAnalyzer {
+            known_cells,
+            recording_start: None,
+            identities: HashMap::new(),
+            first_seen: HashMap::new(),
+            connection_start: None,
+            current_cell: None,
+            emergency: false,
+        }
+    }
+
+    /// Whether we'd seen this cell before the current connection.
+    fn is_known_cell(&self, cell: &PhysicalCell) -> bool {
+        let known_first_seen = self.identities.get(cell)
+            .and_then(|key| self.known_cells.lock().unwrap().get(key).map(|known| known.first_seen));
+        if known_first_seen.zip(self.recording_start).is_some_and(|(first_seen, start)| first_seen < start) {
+            return true;
+        }
+        match (self.first_seen.get(cell), self.connection_start) {
+            (Some(first_seen), Some(connection_start)) => *first_seen < connection_start,
+            _ => false,
+        }
+    }
+
+    fn location_request_event(&self, request: &str) -> Event {
+        if self.emergency {
+            return Event {
+                event_type: EventType::Informational,
+                message: format!("{} during an emergency call", request),
+            };
+        }
+        match self.current_cell {
+            Some(cell) if self.is_known_cell(&cell) => Event {
+                event_type: EventType::QualitativeWarning { severity: Severity::Low },
+                message: format!("{} from a previously seen cell (PCI {} on EARFCN {})", request, cell.phy_cell_id, cell.earfcn),
+            },
+            Some(cell) => Event {
+                event_type: EventType::QualitativeWarning { severity: Severity::Medium },
+                message: format!("{} from a cell we hadn't seen before (PCI {} on EARFCN {})", request, cell.phy_cell_id, cell.earfcn),
+            },
+            None => Event {
+                event_type: EventType::QualitativeWarning { severity: Severity::Medium },
+                message: format!("{} from an unknown cell", request),
+            },
+        }
+    }
+
+    fn analyze_nas(&mut self, payload: &[u8]) -> Option<Event> {
+        let message = EmmMessage::parse(payload)?;
+        if message.service_type() == Some(SERVICE_TYPE_MO_CSFB_EMERGENCY) {
+            self.emergency = true;
+            return None;
+        }
+        if message.message_type != EMM_DOWNLINK_GENERIC_NAS_TRANSPORT {
+            return None;
+        }
+        let request = match *message.body.first()? {
+            GENERIC_CONTAINER_LPP => "LPP positioning message",
+            GENERIC_CONTAINER_LOCATION_SERVICES => "Location services message",
+            _ => return None,
+        };
+        Some(self.location_request_event(request))
+    }
+}
+
+impl Analyzer for LocationRequestAnalyzer {
+    fn get_id(&self) -> Cow<str> {
+        Cow::from("location_request")
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Location Information Request")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from("Tests whether the network asks for the UE's location, and whether it was during an emergency call or from a cell we hadn't seen before.")
+    }
+
+    fn get_interests(&self) -> Interests {
+        Interests::lte_channels([LteChannel::BcchDlSch, LteChannel::UlCcch, LteChannel::DlDcch, LteChannel::NAS])
+    }
+
+    fn analyze_information_element(&mut self, ie: &InformationElement, context: &PacketContext) -> Option<Event> {
+        let InformationElement::LTE(lte_ie) = ie else {
+            return None;
+        };
+        self.recording_start.get_or_insert(context.timestamp);
+        // the UE reads a cell's SIB1 before connecting to it, so that doesn't
+        // count as having seen it before the connection
+        if let Some(key) = KnownCellKey::from_sib1(ie, context.cell) {
+            self.identities.insert(context.cell?, key);
+            return None;
+        }
+        if let Some(cell) = context.cell {
+            self.first_seen.entry(cell).or_insert(context.message_index);
+            self.current_cell = Some(cell);
+        }
+        match &**lte_ie {
+            LteInformationElement::NAS(payload) => self.analyze_nas(payload),
+            LteInformationElement::UlCcch(msg) => {
+                if let UL_CCCH_MessageType::C1(UL_CCCH_MessageType_c1::RrcConnectionRequest(request)) = &msg.message {
+                    self.connection_start = Some(context.message_index);
+                    self.emergency = matches!(
+                        &request.critical_extensions,
+                        RRCConnectionRequestCriticalExtensions::RrcConnectionRequest_r8(r8_ies)
+                            if r8_ies.establishment_cause.0 == EstablishmentCause::EMERGENCY
+                    );
+                }
+                None
+            },
+            LteInformationElement::DlDcch(msg) => match &msg.message {
+                DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::UeInformationRequest_r9(request)) => {
+                    let UEInformationRequest_r9CriticalExtensions::C1(
+                        UEInformationRequest_r9CriticalExtensions_c1::UeInformationRequest_r9(r9_ies)
+                    ) = &request.critical_extensions else {
+                        return None;
+                    };
+                    let reports = requested_location_reports(r9_ies);
+                    if reports.is_empty() {
+                        return None;
+                    }
+                    Some(self.location_request_event(&format!("UEInformationRequest for {}", reports.join(", "))))
+                },
+                DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::RrcConnectionReconfiguration(reconfiguration)) => {
+                    if !requests_location_info(reconfiguration) {
+                        return None;
+                    }
+                    Some(self.location_request_event("Measurement config including location info"))
+                },
+                DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::RrcConnectionRelease(_)) => {
+                    self.emergency = false;
+                    None
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn on_recording_start(&mut self) {
+        self.recording_start = None;
+        self.identities.clear();
+        self.first_seen.clear();
+        self.connection_start = None;
+        self.current_cell = None;
+        self.emergency = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use crate::analysis::known_cells::KnownCellsDb;
+    use crate::analysis::test_util::{self, time};
+    use telcom_parser::lte_rrc::{DL_DCCH_Message, InitialUE_Identity, InitialUE_Identity_randomValue, MeasConfig, RRCConnectionReconfiguration_r8_IEs, RRCConnectionRequest, RRCConnectionRequest_r8_IEs, RRCConnectionRequest_r8_IEsSpare, RRC_TransactionIdentifier, ReportConfigEUTRA, ReportConfigEUTRAIncludeLocationInfo_r10, ReportConfigEUTRAMaxReportCells, ReportConfigEUTRAReportAmount, ReportConfigEUTRAReportQuantity, ReportConfigEUTRATriggerQuantity, ReportConfigEUTRATriggerType, ReportConfigEUTRATriggerType_periodical, ReportConfigEUTRATriggerType_periodicalPurpose, ReportConfigId, ReportConfigToAddMod, ReportConfigToAddModList, ReportInterval, UEInformationRequest_r9, UEInformationRequest_r9_IEsRach_ReportReq_r9, UEInformationRequest_r9_IEsRlf_ReportReq_r9, UL_CCCH_Message};
+
+    const CELL: PhysicalCell = PhysicalCell { earfcn: 5230, phy_cell_id: 7 };
+
+    fn connection_request(establishment_cause: u8) -> InformationElement {
+        let message = UL_CCCH_Message {
+            message: UL_CCCH_MessageType::C1(UL_CCCH_MessageType_c1::RrcConnectionRequest(RRCConnectionRequest {
+                critical_extensions: RRCConnectionRequestCriticalExtensions::RrcConnectionRequest_r8(RRCConnectionRequest_r8_IEs {
+                    ue_identity: InitialUE_Identity::RandomValue(InitialUE_Identity_randomValue(Default::default())),
+                    establishment_cause: EstablishmentCause(establishment_cause),
+                    spare: RRCConnectionRequest_r8_IEsSpare(Default::default()),
+                }),
+            })),
+        };
+        InformationElement::LTE(Box::new(LteInformationElement::UlCcch(message)))
+    }
+
+    fn rlf_report_request() -> InformationElement {
+        let request = UEInformationRequest_r9 {
+            rrc_transaction_identifier: RRC_TransactionIdentifier(0),
+            critical_extensions: UEInformationRequest_r9CriticalExtensions::C1(
+                UEInformationRequest_r9CriticalExtensions_c1::UeInformationRequest_r9(UEInformationRequest_r9_IEs {
+                    rach_report_req_r9: UEInformationRequest_r9_IEsRach_ReportReq_r9(true),
+                    rlf_report_req_r9: UEInformationRequest_r9_IEsRlf_ReportReq_r9(true),
+                    non_critical_extension: None,
+                })
+            ),
+        };
+        let message = DL_DCCH_Message {
+            message: DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::UeInformationRequest_r9(request)),
+        };
+        InformationElement::LTE(Box::new(LteInformationElement::DlDcch(Box::new(message))))
+    }
+
+    fn meas_config(include_location_info_r10: Option<ReportConfigEUTRAIncludeLocationInfo_r10>) -> InformationElement {
+        let report_config = ReportConfigEUTRA {
+            trigger_type: ReportConfigEUTRATriggerType::Periodical(ReportConfigEUTRATriggerType_periodical {
+                purpose: ReportConfigEUTRATriggerType_periodicalPurpose(ReportConfigEUTRATriggerType_periodicalPurpose::REPORT_STRONGEST_CELLS),
+            }),
+            trigger_quantity: ReportConfigEUTRATriggerQuantity(ReportConfigEUTRATriggerQuantity::RSRP),
+            report_quantity: ReportConfigEUTRAReportQuantity(ReportConfigEUTRAReportQuantity::BOTH),
+            max_report_cells: ReportConfigEUTRAMaxReportCells(1),
+            report_interval: ReportInterval(ReportInterval::MS1024),
+            report_amount: ReportConfigEUTRAReportAmount(ReportConfigEUTRAReportAmount::INFINITY),
+            include_location_info_r10,
+        };
+        let meas_config = MeasConfig {
+            meas_object_to_remove_list: None,
+            meas_object_to_add_mod_list: None,
+            report_config_to_remove_list: None,
+            report_config_to_add_mod_list: Some(ReportConfigToAddModList(vec![ReportConfigToAddMod {
+                report_config_id: ReportConfigId(1),
+                report_config: ReportConfigToAddModReportConfig::ReportConfigEUTRA(report_config),
+            }])),
+            meas_id_to_remove_list: None,
+            meas_id_to_add_mod_list: None,
+            quantity_config: None,
+            meas_gap_config: None,
+            s_measure: None,
+            pre_registration_info_hrpd: None,
+            speed_state_pars: None,
+        };
+        let reconfiguration = RRCConnectionReconfiguration {
+            rrc_transaction_identifier: RRC_TransactionIdentifier(0),
+            critical_extensions: RRCConnectionReconfigurationCriticalExtensions::C1(
+                RRCConnectionReconfigurationCriticalExtensions_c1::RrcConnectionReconfiguration_r8(RRCConnectionReconfiguration_r8_IEs {
+                    meas_config: Some(meas_config),
+                    mobility_control_info: None,
+                    dedicated_info_nas_list: None,
+                    radio_resource_config_dedicated: None,
+                    security_config_ho: None,
+                    non_critical_extension: None,
+                })
+            ),
+        };
+        let message = DL_DCCH_Message {
+            message: DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::RrcConnectionReconfiguration(reconfiguration)),
+        };
+        InformationElement::LTE(Box::new(LteInformationElement::DlDcch(Box::new(message))))
+    }
+
+    fn lpp_transport() -> InformationElement {
+        // a ciphered Downlink Generic NAS Transport carrying an LPP message
+        let payload = vec![0x27, 0, 0, 0, 0, 1, 0x07, 0x68, 0x01, 0x00, 0x02, 0x12, 0x34];
+        InformationElement::LTE(Box::new(LteInformationElement::NAS(payload)))
+    }
+
+    fn context(cell: Option<PhysicalCell>, message_index: u64) -> PacketContext {
+        PacketContext { cell, message_index, ..test_util::context() }
+    }
+
+    fn severity(event: Event) -> Option<Severity> {
+        match event.event_type {
+            EventType::QualitativeWarning { severity } => Some(severity),
+            EventType::Informational => None,
+        }
+    }
+
+    fn analyzer() -> (LocationRequestAnalyzer, SharedKnownCellsDb) {
+        let db = Arc::new(Mutex::new(KnownCellsDb::new(None, 100)));
+        (LocationRequestAnalyzer::new(db.clone()), db)
+    }
+
+    #[test]
+    fn test_location_requests() {
+        let (mut analyzer, _) = analyzer();
+        analyzer.analyze_information_element(&connection_request(EstablishmentCause::MO_DATA), &context(Some(CELL), 0));
+        let event = analyzer.analyze_information_element(&rlf_report_request(), &context(Some(CELL), 1)).unwrap();
+        assert_eq!(event.message, "UEInformationRequest for radio link failure report from a cell we hadn't seen before (PCI 7 on EARFCN 5230)");
+        assert_eq!(severity(event), Some(Severity::Medium));
+
+        // by the next connection, we've seen the cell before
+        analyzer.analyze_information_element(&connection_request(EstablishmentCause::MO_DATA), &context(Some(CELL), 2));
+        let event = analyzer.analyze_information_element(&lpp_transport(), &context(None, 3)).unwrap();
+        assert!(event.message.starts_with("LPP positioning message from a previously seen cell"));
+        assert_eq!(severity(event), Some(Severity::Low));
+
+        analyzer.analyze_information_element(&connection_request(EstablishmentCause::EMERGENCY), &context(Some(CELL), 4));
+        let event = analyzer.analyze_information_element(&lpp_transport(), &context(None, 5)).unwrap();
+        assert_eq!(event.message, "LPP positioning message during an emergency call");
+        assert_eq!(severity(event), None);
+    }
+
+    #[test]
+    fn test_include_location_info() {
+        let (mut analyzer, _) = analyzer();
+        analyzer.analyze_information_element(&connection_request(EstablishmentCause::MO_DATA), &context(Some(CELL), 0));
+        assert!(analyzer.analyze_information_element(&meas_config(None), &context(Some(CELL), 1)).is_none());
+        let location_info = Some(ReportConfigEUTRAIncludeLocationInfo_r10(ReportConfigEUTRAIncludeLocationInfo_r10::TRUE));
+        let event = analyzer.analyze_information_element(&meas_config(location_info), &context(Some(CELL), 2)).unwrap();
+        assert_eq!(event.message, "Measurement config including location info from a cell we hadn't seen before (PCI 7 on EARFCN 5230)");
+    }
+
+    #[test]
+    fn test_known_cell() {
+        let (mut analyzer, db) = analyzer();
+        let known = test_util::sib1(|_| {});
+        let key = KnownCellKey::from_sib1(&known, Some(CELL)).unwrap();
+        db.lock().unwrap().observe(key, time(-86400));
+        let request = |analyzer: &mut LocationRequestAnalyzer, sib1: Option<&InformationElement>| {
+            analyzer.on_recording_start();
+            if let Some(sib1) = sib1 {
+                analyzer.analyze_information_element(sib1, &context(Some(CELL), 0));
+            }
+            analyzer.analyze_information_element(&connection_request(EstablishmentCause::MO_DATA), &context(Some(CELL), 1));
+            analyzer.analyze_information_element(&rlf_report_request(), &context(Some(CELL), 2)).unwrap()
+        };
+
+        let event = request(&mut analyzer, Some(&known));
+        assert!(event.message.ends_with("from a previously seen cell (PCI 7 on EARFCN 5230)"));
+
+        // a different cell reusing the known cell's EARFCN and PCI
+        let impostor = test_util::sib1(|sib1| {
+            let cell_identity = &mut sib1.cell_access_related_info.cell_identity.0;
+            let bit = cell_identity[0];
+            cell_identity.set(0, !bit);
+        });
+        let event = request(&mut analyzer, Some(&impostor));
+        assert!(event.message.ends_with("from a cell we hadn't seen before (PCI 7 on EARFCN 5230)"));
+        assert_eq!(severity(event), Some(Severity::Medium));
+
+        // without a SIB1 we don't know which cell it was
+        let event = request(&mut analyzer, None);
+        assert!(event.message.ends_with("from a cell we hadn't seen before (PCI 7 on EARFCN 5230)"));
+    }
+}
@@ -0,0 +1,114 @@
+//! Counters for how much work a [Harness](super::analyzer::Harness) and each of
+//! its [Analyzers](super::analyzer::Analyzer) are doing, so slow or noisy
+//! heuristics can be caught before they're run on a device for hours at a time.
+
+use std::time::Duration;
+
+use serde::{Serialize, Serializer};
+
+fn as_micros<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_micros() as u64)
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct AnalyzerMetrics {
+    pub id: String,
+    /// How many times the analyzer was called, i.e. how many messages it saw.
+    pub calls: u64,
+    pub events: u64,
+    /// Total time spent in the analyzer, in microseconds.
+    #[serde(rename = "analysis_time_us", serialize_with = "as_micros")]
+    pub analysis_time: Duration,
+}
+
+impl AnalyzerMetrics {
+    pub fn new(id: String) -> Self {
+        AnalyzerMetrics {
+            id,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn record_call(&mut self, elapsed: Duration, events: usize) {
+        self.calls += 1;
+        self.events += events as u64;
+        self.analysis_time += elapsed;
+    }
+
+    /// The average time the analyzer took per message.
+    pub fn mean_analysis_time(&self) -> Duration {
+        match u32::try_from(self.calls) {
+            Ok(0) => Duration::ZERO,
+            Ok(calls) => self.analysis_time / calls,
+            Err(_) => Duration::from_secs_f64(self.analysis_time.as_secs_f64() / self.calls as f64),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct HarnessMetrics {
+    /// How many messages were read, including those which couldn't be decoded.
+    pub messages: u64,
+    pub skipped_messages: u64,
+    /// Total time spent decoding messages before they're handed to analyzers,
+    /// in microseconds.
+    #[serde(rename = "decode_time_us", serialize_with = "as_micros")]
+    pub decode_time: Duration,
+    pub analyzers: Vec<AnalyzerMetrics>,
+}
+
+impl HarnessMetrics {
+    /// Adds another recording's metrics to these, e.g. to profile a batch of
+    /// recordings.
+    pub fn add(&mut self, other: &HarnessMetrics) {
+        self.messages += other.messages;
+        self.skipped_messages += other.skipped_messages;
+        self.decode_time += other.decode_time;
+        for metrics in &other.analyzers {
+            match self.analyzers.iter_mut().find(|analyzer| analyzer.id == metrics.id) {
+                Some(analyzer) => {
+                    analyzer.calls += metrics.calls;
+                    analyzer.events += metrics.events;
+                    analyzer.analysis_time += metrics.analysis_time;
+                }
+                None => self.analyzers.push(metrics.clone()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_call() {
+        let mut metrics = AnalyzerMetrics::new("test".to_string());
+        assert_eq!(metrics.mean_analysis_time(), Duration::ZERO);
+        metrics.record_call(Duration::from_micros(10), 0);
+        metrics.record_call(Duration::from_micros(30), 1);
+        assert_eq!((metrics.calls, metrics.events), (2, 1));
+        assert_eq!(metrics.analysis_time, Duration::from_micros(40));
+        assert_eq!(metrics.mean_analysis_time(), Duration::from_micros(20));
+    }
+
+    #[test]
+    fn test_add() {
+        let mut analyzer = AnalyzerMetrics::new("test".to_string());
+        analyzer.record_call(Duration::from_micros(10), 1);
+        let recording = HarnessMetrics {
+            messages: 5,
+            skipped_messages: 1,
+            decode_time: Duration::from_micros(100),
+            analyzers: vec![analyzer],
+        };
+        let mut total = HarnessMetrics::default();
+        total.add(&recording);
+        total.add(&recording);
+        assert_eq!((total.messages, total.skipped_messages), (10, 2));
+        assert_eq!(total.decode_time, Duration::from_micros(200));
+        assert_eq!(total.analyzers.len(), 1);
+        assert_eq!((total.analyzers[0].calls, total.analyzers[0].events), (2, 2));
+        assert_eq!(total.analyzers[0].analysis_time, Duration::from_micros(20));
+    }
+}
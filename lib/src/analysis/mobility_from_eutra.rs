@@ -0,0 +1,298 @@
+use std::borrow::Cow;
+
+use telcom_parser::lte_rrc::{CellChangeOrder, CellChangeOrderTargetRAT_Type, DL_DCCH_MessageType, DL_DCCH_MessageType_c1, Handover, HandoverTargetRAT_Type, MobilityFromEUTRACommand, MobilityFromEUTRACommandCriticalExtensions, MobilityFromEUTRACommandCriticalExtensions_c1, MobilityFromEUTRACommand_r8_IEsPurpose, MobilityFromEUTRACommand_r9_IEsPurpose};
+
+use super::analyzer::{Analyzer, Event, EventType, Interests, Severity};
+use super::context::PacketContext;
+use super::information_element::{InformationElement, LteChannel, LteInformationElement};
+use super::nas::EmmMessage;
+use super::util::unpack;
+
+/// Where a MobilityFromEUTRACommand sends the UE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Geran,
+    Utra,
+    /// CDMA2000, NR or even LTE, none of which are a downgrade we care about.
+    Other(&'static str),
+}
+
+/// The parts of a MobilityFromEUTRACommand we care about, from either its r8 or
+/// r9 form.
+struct Mobility {
+    cs_fallback: bool,
+    kind: &'static str,
+    target: Target,
+}
+
+fn handover_target(handover: &Handover) -> Target {
+    match handover.target_rat_type.0 {
+        HandoverTargetRAT_Type::GERAN => Target::Geran,
+        HandoverTargetRAT_Type::UTRA => Target::Utra,
+        HandoverTargetRAT_Type::CDMA2000_1XRTT => Target::Other("CDMA2000 1xRTT"),
+        HandoverTargetRAT_Type::CDMA2000_HRPD => Target::Other("CDMA2000 HRPD"),
+        HandoverTargetRAT_Type::NR => Target::Other("NR"),
+        HandoverTargetRAT_Type::EUTRA => Target::Other("LTE"),
+        _ => Target::Other("an unknown RAT"),
+    }
+}
+
+fn cell_change_order_target(order: &CellChangeOrder) -> Target {
+    match order.target_rat_type {
+        CellChangeOrderTargetRAT_Type::Geran(_) => Target::Geran,
+    }
+}
+
+fn mobility(command: &MobilityFromEUTRACommand) -> Option<Mobility> {
+    unpack!(MobilityFromEUTRACommandCriticalExtensions::C1(c1) = &command.critical_extensions);
+    let (cs_fallback, kind, target) = match c1 {
+        MobilityFromEUTRACommandCriticalExtensions_c1::MobilityFromEUTRACommand_r8(r8_ies) => match &r8_ies.purpose {
+            MobilityFromEUTRACommand_r8_IEsPurpose::Handover(handover) =>
+                (r8_ies.cs_fallback_indicator.0, "handover", handover_target(handover)),
+            MobilityFromEUTRACommand_r8_IEsPurpose::CellChangeOrder(order) =>
+                (r8_ies.cs_fallback_indicator.0, "cell change order", cell_change_order_target(order)),
+        },
+        MobilityFromEUTRACommandCriticalExtensions_c1::MobilityFromEUTRACommand_r9(r9_ies) => match &r9_ies.purpose {
+            MobilityFromEUTRACommand_r9_IEsPurpose::Handover(handover) =>
+                (r9_ies.cs_fallback_indicator.0, "handover", handover_target(handover)),
+            MobilityFromEUTRACommand_r9_IEsPurpose::CellChangeOrder(order) =>
+                (r9_ies.cs_fallback_indicator.0, "cell change order", cell_change_order_target(order)),
+            // enhanced CS fallback to CDMA2000 1xRTT is always CSFB
+            MobilityFromEUTRACommand_r9_IEsPurpose::E_CSFB_r9(_) => (true, "enhanced CS fallback", Target::Other("CDMA2000")),
+        },
+        _ => return None,
+    };
+    Some(Mobility { cs_fallback, kind, target })
+}
+
+/// Whether an EMM Extended Service Request's service type (TS 24.301
+/// 9.9.3.27) asks for CS fallback. The unused values up to 4 are treated as
+/// mobile originating CS fallback.
+fn is_csfb_service_request(message: &EmmMessage) -> bool {
+    message.service_type().is_some_and(|service_type| service_type <= 4)
+}
+
+/// Like [ConnectionRedirect2GDowngradeAnalyzer](super::connection_redirect_downgrade::ConnectionRedirect2GDowngradeAnalyzer),
+/// but for connected phones being handed over or ordered to a 2G/3G cell with
+/// a MobilityFromEUTRACommand. Networks without VoLTE do this for voice calls
+/// (CS fallback), so commands which follow the UE asking for CS fallback with
+/// an Extended Service Request are only informational.
+#[derive(Default)]
+pub struct MobilityFromEutraAnalyzer {
+    // whether the UE asked for CS fallback during the current connection
+    csfb_requested: bool,
+}
+
+impl MobilityFromEutraAnalyzer {
+    fn mobility_event(&self, mobility: &Mobility) -> Event {
+        let (generation, downgrade_severity) = match mobility.target {
+            Target::Geran => ("2G", Severity::High),
+            Target::Utra => ("3G", Severity::Medium),
+            Target::Other(network) => return Event {
+                event_type: EventType::Informational,
+                message: format!("MobilityFromEUTRACommand {} to {}", mobility.kind, network),
+            },
+        };
+        if mobility.cs_fallback && self.csfb_requested {
+            Event {
+                event_type: EventType::Informational,
+                message: format!("CS fallback {} to {} after the UE requested CS fallback", mobility.kind, generation),
+            }
+        } else if mobility.cs_fallback {
+            Event {
+                event_type: EventType::QualitativeWarning { severity: Severity::Medium },
+                message: format!("CS fallback {} to {} without the UE requesting CS fallback", mobility.kind, generation),
+            }
+        } else {
+            Event {
+                event_type: EventType::QualitativeWarning { severity: downgrade_severity },
+                message: format!("Detected {} downgrade by MobilityFromEUTRACommand {}", generation, mobility.kind),
+            }
+        }
+    }
+}
+
+impl Analyzer for MobilityFromEutraAnalyzer {
+    fn get_id(&self) -> Cow<str> {
+        Cow::from("mobility_from_eutra_downgrade")
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("MobilityFromEUTRACommand 2G/3G Downgrade")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from("Tests if a cell hands over or orders a connected UE to a 2G/3G cell, other than for a CS fallback call the UE asked for.")
+    }
+
+    fn get_interests(&self) -> Interests {
+        Interests::lte_channels([LteChannel::DlDcch, LteChannel::NAS])
+    }
+
+    fn analyze_information_element(&mut self, ie: &InformationElement, _context: &PacketContext) -> Option<Event> {
+        unpack!(InformationElement::LTE(lte_ie) = ie);
+        let message = match &**lte_ie {
+            LteInformationElement::NAS(payload) => {
+                if EmmMessage::parse(payload).is_some_and(|message| is_csfb_service_request(&message)) {
+                    self.csfb_requested = true;
+                }
+                return None;
+            },
+            LteInformationElement::DlDcch(msg_cont) => &msg_cont.message,
+            _ => return None,
+        };
+        unpack!(DL_DCCH_MessageType::C1(c1) = message);
+        match c1 {
+            DL_DCCH_MessageType_c1::MobilityFromEUTRACommand(command) => {
+                let event = self.mobility_event(&mobility(command)?);
+                self.csfb_requested = false;
+                Some(event)
+            },
+            DL_DCCH_MessageType_c1::RrcConnectionRelease(_) => {
+                self.csfb_requested = false;
+                None
+            },
+            _ => None,
+        }
+    }
+
+    fn on_recording_start(&mut self) {
+        self.csfb_requested = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::test_util::context;
+    use telcom_parser::lte_rrc::{
+        ARFCN_ValueGERAN, BandIndicatorGERAN, CarrierFreqGERAN, CellChangeOrderT304, CellChangeOrderTargetRAT_Type_geran,
+        DL_DCCH_Message, E_CSFB_r9, HandoverTargetRAT_MessageContainer, MobilityFromEUTRACommand_r8_IEs,
+        MobilityFromEUTRACommand_r8_IEsCs_FallbackIndicator, MobilityFromEUTRACommand_r9_IEs,
+        MobilityFromEUTRACommand_r9_IEsCs_FallbackIndicator, PhysCellIdGERAN, PhysCellIdGERANBaseStationColourCode,
+        PhysCellIdGERANNetworkColourCode, RRC_TransactionIdentifier,
+    };
+
+    fn handover_purpose(target_rat_type: u8) -> Handover {
+        Handover {
+            target_rat_type: HandoverTargetRAT_Type(target_rat_type),
+            target_rat_message_container: HandoverTargetRAT_MessageContainer(vec![]),
+            nas_security_param_from_eutra: None,
+            system_information: None,
+        }
+    }
+
+    fn cell_change_order_purpose() -> CellChangeOrder {
+        CellChangeOrder {
+            t304: CellChangeOrderT304(CellChangeOrderT304::MS1000),
+            target_rat_type: CellChangeOrderTargetRAT_Type::Geran(CellChangeOrderTargetRAT_Type_geran {
+                phys_cell_id: PhysCellIdGERAN {
+                    network_colour_code: PhysCellIdGERANNetworkColourCode(Default::default()),
+                    base_station_colour_code: PhysCellIdGERANBaseStationColourCode(Default::default()),
+                },
+                carrier_freq: CarrierFreqGERAN {
+                    arfcn: ARFCN_ValueGERAN(1),
+                    band_indicator: BandIndicatorGERAN(BandIndicatorGERAN::DCS1800),
+                },
+                network_control_order: None,
+                system_information: None,
+            }),
+        }
+    }
+
+    fn command(c1: MobilityFromEUTRACommandCriticalExtensions_c1) -> InformationElement {
+        let command = MobilityFromEUTRACommand {
+            rrc_transaction_identifier: RRC_TransactionIdentifier(0),
+            critical_extensions: MobilityFromEUTRACommandCriticalExtensions::C1(c1),
+        };
+        let message = DL_DCCH_Message {
+            message: DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::MobilityFromEUTRACommand(command)),
+        };
+        InformationElement::LTE(Box::new(LteInformationElement::DlDcch(Box::new(message))))
+    }
+
+    fn r8(purpose: MobilityFromEUTRACommand_r8_IEsPurpose, cs_fallback: bool) -> InformationElement {
+        command(MobilityFromEUTRACommandCriticalExtensions_c1::MobilityFromEUTRACommand_r8(MobilityFromEUTRACommand_r8_IEs {
+            cs_fallback_indicator: MobilityFromEUTRACommand_r8_IEsCs_FallbackIndicator(cs_fallback),
+            purpose,
+            non_critical_extension: None,
+        }))
+    }
+
+    fn r9(purpose: MobilityFromEUTRACommand_r9_IEsPurpose, cs_fallback: bool) -> InformationElement {
+        command(MobilityFromEUTRACommandCriticalExtensions_c1::MobilityFromEUTRACommand_r9(MobilityFromEUTRACommand_r9_IEs {
+            cs_fallback_indicator: MobilityFromEUTRACommand_r9_IEsCs_FallbackIndicator(cs_fallback),
+            purpose,
+            non_critical_extension: None,
+        }))
+    }
+
+    fn handover(target_rat_type: u8, cs_fallback: bool) -> InformationElement {
+        r8(MobilityFromEUTRACommand_r8_IEsPurpose::Handover(handover_purpose(target_rat_type)), cs_fallback)
+    }
+
+    #[test]
+    fn test_mobility_from_eutra() {
+        let mut analyzer = MobilityFromEutraAnalyzer::default();
+        let event = analyzer.analyze_information_element(&handover(HandoverTargetRAT_Type::GERAN, false), &context()).unwrap();
+        assert_eq!(event.event_type, EventType::QualitativeWarning { severity: Severity::High });
+        let event = analyzer.analyze_information_element(&handover(HandoverTargetRAT_Type::UTRA, false), &context()).unwrap();
+        assert_eq!(event.event_type, EventType::QualitativeWarning { severity: Severity::Medium });
+        let event = analyzer.analyze_information_element(&handover(HandoverTargetRAT_Type::GERAN, true), &context()).unwrap();
+        assert_eq!(event.event_type, EventType::QualitativeWarning { severity: Severity::Medium });
+        assert!(event.message.contains("without the UE requesting"));
+
+        // an integrity protected mobile originating CSFB Extended Service
+        // Request
+        let service_request = InformationElement::LTE(Box::new(LteInformationElement::NAS(
+            vec![0x17, 0, 0, 0, 0, 1, 0x07, 0x4c, 0x00, 0x05, 0xf4, 0, 0, 0, 0]
+        )));
+        assert!(analyzer.analyze_information_element(&service_request, &context()).is_none());
+        let event = analyzer.analyze_information_element(&handover(HandoverTargetRAT_Type::GERAN, true), &context()).unwrap();
+        assert_eq!(event.event_type, EventType::Informational);
+        // the request only covers one command
+        let event = analyzer.analyze_information_element(&handover(HandoverTargetRAT_Type::GERAN, true), &context()).unwrap();
+        assert_eq!(event.event_type, EventType::QualitativeWarning { severity: Severity::Medium });
+    }
+
+    #[test]
+    fn test_cell_change_order() {
+        let mut analyzer = MobilityFromEutraAnalyzer::default();
+        let ie = r8(MobilityFromEUTRACommand_r8_IEsPurpose::CellChangeOrder(cell_change_order_purpose()), false);
+        let event = analyzer.analyze_information_element(&ie, &context()).unwrap();
+        assert_eq!(event.event_type, EventType::QualitativeWarning { severity: Severity::High });
+        assert_eq!(event.message, "Detected 2G downgrade by MobilityFromEUTRACommand cell change order");
+    }
+
+    #[test]
+    fn test_r9() {
+        let mut analyzer = MobilityFromEutraAnalyzer::default();
+        let ie = r9(MobilityFromEUTRACommand_r9_IEsPurpose::Handover(handover_purpose(HandoverTargetRAT_Type::UTRA)), false);
+        let event = analyzer.analyze_information_element(&ie, &context()).unwrap();
+        assert_eq!(event.event_type, EventType::QualitativeWarning { severity: Severity::Medium });
+        assert_eq!(event.message, "Detected 3G downgrade by MobilityFromEUTRACommand handover");
+
+        let ie = r9(MobilityFromEUTRACommand_r9_IEsPurpose::CellChangeOrder(cell_change_order_purpose()), true);
+        let event = analyzer.analyze_information_element(&ie, &context()).unwrap();
+        assert_eq!(event.event_type, EventType::QualitativeWarning { severity: Severity::Medium });
+        assert_eq!(event.message, "CS fallback cell change order to 2G without the UE requesting CS fallback");
+
+        let ie = r9(MobilityFromEUTRACommand_r9_IEsPurpose::E_CSFB_r9(E_CSFB_r9 {
+            message_cont_cdma2000_1xrtt_r9: None,
+            mobility_cdma2000_hrpd_r9: None,
+            message_cont_cdma2000_hrpd_r9: None,
+            redirect_carrier_cdma2000_hrpd_r9: None,
+        }), false);
+        let event = analyzer.analyze_information_element(&ie, &context()).unwrap();
+        assert_eq!(event.event_type, EventType::Informational);
+        assert_eq!(event.message, "MobilityFromEUTRACommand enhanced CS fallback to CDMA2000");
+
+        let event = analyzer.analyze_information_element(&handover(HandoverTargetRAT_Type::CDMA2000_1XRTT, true), &context()).unwrap();
+        assert_eq!(event.event_type, EventType::Informational);
+        assert_eq!(event.message, "MobilityFromEUTRACommand handover to CDMA2000 1xRTT");
+    }
+}
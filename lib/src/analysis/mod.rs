@@ -1,8 +1,33 @@
 pub mod analyzer;
+pub mod cell_identity;
+pub mod cell_reference;
+pub mod cell_reference_mismatch;
+pub mod cell_reselection;
+pub mod config;
+pub mod context;
+pub mod correlation;
+pub mod dedup;
+pub mod emergency_alert;
+pub mod idle_mode_mobility;
 pub mod information_element;
+pub mod known_cells;
+pub mod location_request;
+pub mod metrics;
+pub mod mobility_from_eutra;
+pub mod nas;
+pub mod novel_cell;
 pub mod priority_2g_downgrade;
 pub mod connection_redirect_downgrade;
+pub mod connection_reject;
+pub mod imsi_paging;
 pub mod imsi_provided;
 pub mod imsi_requested;
 pub mod null_cipher;
+pub mod rrc_state;
+pub mod rules;
+pub mod script;
+#[cfg(test)]
+mod test_util;
+pub mod transient_cell;
+pub mod ue_capability_enquiry;
 pub mod util;
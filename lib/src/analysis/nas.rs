@@ -0,0 +1,73 @@
+//! Minimal parsing of LTE NAS EPS mobility management (EMM) messages, per TS
+//! 24.301. We don't have a full NAS decoder, so [InformationElement::NAS]
+//! payloads are raw bytes, and analyzers only need a handful of fields from
+//! them.
+//!
+//! [InformationElement::NAS]: super::information_element::LteInformationElement::NAS
+
+pub const EMM_PROTOCOL_DISCRIMINATOR: u8 = 0x07;
+
+// EMM message types, from TS 24.301 9.8
+pub const EMM_ATTACH_REQUEST: u8 = 0x41;
+pub const EMM_EXTENDED_SERVICE_REQUEST: u8 = 0x4c;
+pub const EMM_IDENTITY_REQUEST: u8 = 0x55;
+pub const EMM_IDENTITY_RESPONSE: u8 = 0x56;
+pub const EMM_SECURITY_MODE_COMMAND: u8 = 0x5d;
+pub const EMM_SECURITY_MODE_COMPLETE: u8 = 0x5e;
+pub const EMM_DOWNLINK_GENERIC_NAS_TRANSPORT: u8 = 0x68;
+
+// Generic message container types, from TS 24.301 9.9.3.42
+pub const GENERIC_CONTAINER_LPP: u8 = 1;
+pub const GENERIC_CONTAINER_LOCATION_SERVICES: u8 = 2;
+
+// Extended Service Request service types, from TS 24.301 9.9.3.27
+pub const SERVICE_TYPE_MO_CSFB_EMERGENCY: u8 = 2;
+
+// Security header types, from TS 24.301 9.3.1
+pub const PLAIN_NAS_MESSAGE: u8 = 0;
+pub const INTEGRITY_PROTECTED_AND_CIPHERED: u8 = 2;
+pub const INTEGRITY_PROTECTED_AND_CIPHERED_NEW_CONTEXT: u8 = 4;
+
+/// A NAS EMM message, with its security header (if any) removed.
+pub struct EmmMessage<'a> {
+    pub security_header_type: u8,
+    pub message_type: u8,
+    /// Everything after the message type.
+    pub body: &'a [u8],
+}
+
+impl<'a> EmmMessage<'a> {
+    /// Parses an EMM message, returning `None` for other protocols or
+    /// truncated messages.
+    pub fn parse(payload: &'a [u8]) -> Option<Self> {
+        let security_header_type = payload.first()? >> 4;
+        // the modem logs NAS messages after deciphering them, so the plain
+        // message follows the MAC and sequence number even if it was ciphered
+        let plain = match security_header_type {
+            PLAIN_NAS_MESSAGE => payload,
+            1..=4 => payload.get(6..)?,
+            _ => return None,
+        };
+        if *plain.first()? != EMM_PROTOCOL_DISCRIMINATOR {
+            return None;
+        }
+        Some(EmmMessage {
+            security_header_type,
+            message_type: *plain.get(1)?,
+            body: &plain[2..],
+        })
+    }
+
+    /// Returns the service type of an Extended Service Request, or `None` for
+    /// other messages.
+    pub fn service_type(&self) -> Option<u8> {
+        if self.message_type != EMM_EXTENDED_SERVICE_REQUEST {
+            return None;
+        }
+        Some(self.body.first()? & 0x0f)
+    }
+
+    pub fn is_ciphered(&self) -> bool {
+        matches!(self.security_header_type, INTEGRITY_PROTECTED_AND_CIPHERED | INTEGRITY_PROTECTED_AND_CIPHERED_NEW_CONTEXT)
+    }
+}
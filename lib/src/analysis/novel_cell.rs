@@ -0,0 +1,135 @@
+use std::borrow::Cow;
+
+use super::analyzer::{Analyzer, Event, EventType, Interests, Severity};
+use super::config::NovelCellParams;
+use super::context::PacketContext;
+use super::information_element::{InformationElement, LteChannel};
+use super::known_cells::{KnownCellKey, SharedKnownCellsDb};
+
+/// Flags cells missing from the [known cells database](super::known_cells) in
+/// a tracking area we've visited regularly. A fake base station has to pick a
+/// PLMN and TAC to advertise, and one parked near somewhere the user spends
+/// their time will show up as a stranger among cells we've seen many times.
+///
+/// Cells in unfamiliar areas aren't reported, since everything is new when
+/// travelling.
+///
+/// This only reads the database: the [Harness](super::analyzer::Harness)
+/// records each cell once every analyzer has seen its SIB1, so a cell is only
+/// novel the first time.
+pub struct NovelCellAnalyzer {
+    db: SharedKnownCellsDb,
+    min_area_visits: u64,
+}
+
+impl NovelCellAnalyzer {
+    pub fn new(db: SharedKnownCellsDb, params: &NovelCellParams) -> Self {
+        NovelCellAnalyzer {
+            db,
+            min_area_visits: params.min_area_visits,
+        }
+    }
+}
+
+impl Analyzer for NovelCellAnalyzer {
+    fn get_id(&self) -> Cow<str> {
+        Cow::from("novel_cell")
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Novel Cell in a Familiar Area")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from("Tests whether a cell we've never seen before appears in a tracking area we visit regularly.")
+    }
+
+    fn get_interests(&self) -> Interests {
+        Interests::lte_channels([LteChannel::BcchDlSch])
+    }
+
+    fn analyze_information_element(&mut self, ie: &InformationElement, context: &PacketContext) -> Option<Event> {
+        let key = KnownCellKey::from_sib1(ie, context.cell)?;
+        let db = self.db.lock().unwrap();
+        if db.get(&key).is_some() {
+            return None;
+        }
+        let area = db.area_stats(&key.plmn, key.tac);
+        drop(db);
+        if area.visits < self.min_area_visits {
+            return None;
+        }
+        Some(Event {
+            event_type: EventType::QualitativeWarning { severity: Severity::Medium },
+            message: format!(
+                "Cell we've never seen before (PLMN {}, TAC {}, cell ID {}, PCI {} on EARFCN {}) in an area where we've seen {} other cells over {} recordings",
+                key.plmn, key.tac, key.cell_identity, key.pci, key.earfcn, area.cells, area.visits,
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use crate::analysis::known_cells::{KnownCellsDb, KnownCellsRecorder};
+    use crate::analysis::test_util::{self, context_on};
+
+    // a SIB1 whose cell ID has its lowest bits set to `low_bits`
+    fn sib1(low_bits: u8) -> InformationElement {
+        test_util::sib1(|sib1| {
+            let cell_identity = &mut sib1.cell_access_related_info.cell_identity.0;
+            let len = cell_identity.len();
+            for bit in 0..4 {
+                cell_identity.set(len - 1 - bit, low_bits & (1 << bit) != 0);
+            }
+        })
+    }
+
+    fn context(phy_cell_id: u16) -> PacketContext {
+        context_on(5230, phy_cell_id)
+    }
+
+    // Analyzes a message, then records its cell like the harness does
+    fn analyze(analyzer: &mut NovelCellAnalyzer, recorder: &mut KnownCellsRecorder, ie: &InformationElement, context: &PacketContext) -> Option<Event> {
+        let event = analyzer.analyze_information_element(ie, context);
+        recorder.observe(ie, context);
+        event
+    }
+
+    #[test]
+    fn test_novel_cell_in_familiar_area() {
+        let db = Arc::new(Mutex::new(KnownCellsDb::new(None, 100)));
+        let mut analyzer = NovelCellAnalyzer::new(db.clone(), &NovelCellParams { min_area_visits: 2 });
+        let mut recorder = KnownCellsRecorder::new(db.clone());
+        for _ in 0..2 {
+            recorder.reset();
+            assert!(analyze(&mut analyzer, &mut recorder, &sib1(1), &context(1)).is_none());
+            assert!(analyze(&mut analyzer, &mut recorder, &sib1(1), &context(1)).is_none());
+        }
+        assert_eq!(db.lock().unwrap().len(), 1);
+
+        recorder.reset();
+        let event = analyze(&mut analyzer, &mut recorder, &sib1(2), &context(2)).unwrap();
+        assert_eq!(event.event_type, EventType::QualitativeWarning { severity: Severity::Medium });
+        assert!(event.message.ends_with("PCI 2 on EARFCN 5230) in an area where we've seen 1 other cells over 2 recordings"));
+        // it's only novel once
+        assert!(analyze(&mut analyzer, &mut recorder, &sib1(2), &context(2)).is_none());
+        recorder.reset();
+        assert!(analyze(&mut analyzer, &mut recorder, &sib1(2), &context(2)).is_none());
+    }
+
+    #[test]
+    fn test_unfamiliar_area() {
+        let db = Arc::new(Mutex::new(KnownCellsDb::new(None, 100)));
+        let mut analyzer = NovelCellAnalyzer::new(db.clone(), &NovelCellParams { min_area_visits: 2 });
+        let mut recorder = KnownCellsRecorder::new(db);
+        assert!(analyze(&mut analyzer, &mut recorder, &sib1(1), &context(1)).is_none());
+        assert!(analyze(&mut analyzer, &mut recorder, &sib1(2), &context(2)).is_none());
+    }
+}
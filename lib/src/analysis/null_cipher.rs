@@ -2,8 +2,9 @@ use std::borrow::Cow;
 
 use telcom_parser::lte_rrc::{CipheringAlgorithm_r12, DL_DCCH_MessageType, DL_DCCH_MessageType_c1, RRCConnectionReconfiguration, RRCConnectionReconfigurationCriticalExtensions, RRCConnectionReconfigurationCriticalExtensions_c1, SCG_Configuration_r12, SecurityConfigHO_v1530HandoverType_v1530, SecurityModeCommand, SecurityModeCommandCriticalExtensions, SecurityModeCommandCriticalExtensions_c1};
 
-use super::analyzer::{Analyzer, Event, EventType, Severity};
-use super::information_element::{InformationElement, LteInformationElement};
+use super::analyzer::{Analyzer, Event, EventType, Interests, Severity};
+use super::context::PacketContext;
+use super::information_element::{InformationElement, LteChannel, LteInformationElement};
 
 pub struct NullCipherAnalyzer {
 }
@@ -84,6 +85,14 @@ impl NullCipherAnalyzer {
 }
 
 impl Analyzer for NullCipherAnalyzer {
+    fn get_id(&self) -> Cow<str> {
+        Cow::from("null_cipher")
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
     fn get_name(&self) -> Cow<str> {
         Cow::from("Null Cipher")
     }
@@ -92,7 +101,11 @@ impl Analyzer for NullCipherAnalyzer {
         Cow::from("Tests whether the cell suggests using a null cipher (EEA0)")
     }
 
-    fn analyze_information_element(&mut self, ie: &InformationElement) -> Option<Event> {
+    fn get_interests(&self) -> Interests {
+        Interests::lte_channels([LteChannel::DlDcch])
+    }
+
+    fn analyze_information_element(&mut self, ie: &InformationElement, _context: &PacketContext) -> Option<Event> {
         let dcch_msg = match ie {
             InformationElement::LTE(lte_ie) => match &** lte_ie {
                 LteInformationElement::DlDcch(dcch_msg) => dcch_msg,
@@ -1,30 +1,89 @@
 use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
 
-use super::analyzer::{Analyzer, Event, EventType, Severity};
-use super::information_element::{InformationElement, LteInformationElement};
-use telcom_parser::lte_rrc::{BCCH_DL_SCH_MessageType, BCCH_DL_SCH_MessageType_c1, CellReselectionPriority, SystemInformationBlockType7, SystemInformationCriticalExtensions, SystemInformation_r8_IEsSib_TypeAndInfo, SystemInformation_r8_IEsSib_TypeAndInfo_Entry};
+use super::analyzer::{Analyzer, Event, EventType, Interests, Severity};
+use super::context::{PacketContext, PhysicalCell};
+use super::information_element::{InformationElement, LteChannel};
+use super::util::system_information_blocks;
+use telcom_parser::lte_rrc::{CellReselectionPriority, SystemInformationBlockType6, SystemInformationBlockType7, SystemInformation_r8_IEsSib_TypeAndInfo_Entry};
 
-/// Based on heuristic T7 from Shinjo Park's "Why We Cannot Win".
-pub struct LteSib6And7DowngradeAnalyzer {
+/// A 2G or 3G carrier advertised for reselection, e.g. in SIB6 or SIB7.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DowngradeCarrier {
+    pub generation: u8,
+    pub arfcn: u16,
+    pub priority: u8,
 }
 
-impl LteSib6And7DowngradeAnalyzer {
-    fn unpack_system_information<'a>(&self, ie: &'a InformationElement) -> Option<&'a SystemInformation_r8_IEsSib_TypeAndInfo> {
-        if let InformationElement::LTE(lte_ie) = ie {
-            if let LteInformationElement::BcchDlSch(bcch_dl_sch_message) = &**lte_ie {
-                if let BCCH_DL_SCH_MessageType::C1(BCCH_DL_SCH_MessageType_c1::SystemInformation(system_information)) = &bcch_dl_sch_message.message {
-                    if let SystemInformationCriticalExtensions::SystemInformation_r8(sib) = &system_information.critical_extensions {
-                        return Some(&sib.sib_type_and_info);
-                    }
-                }
-            }
-        }
-        None
+impl fmt::Display for DowngradeCarrier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arfcn_name = if self.generation == 3 { "UARFCN" } else { "ARFCN" };
+        write!(f, "{}G carrier on {} {} at priority {}", self.generation, arfcn_name, self.arfcn, self.priority)
+    }
+}
+
+fn utra_carriers(sib6: &SystemInformationBlockType6) -> Vec<DowngradeCarrier> {
+    let fdd = sib6.carrier_freq_list_utra_fdd.iter()
+        .flat_map(|list| &list.0)
+        .map(|carrier| (carrier.carrier_freq.0, carrier.cell_reselection_priority.as_ref()));
+    let tdd = sib6.carrier_freq_list_utra_tdd.iter()
+        .flat_map(|list| &list.0)
+        .map(|carrier| (carrier.carrier_freq.0, carrier.cell_reselection_priority.as_ref()));
+    fdd.chain(tdd)
+        .filter_map(|(arfcn, priority)| Some(DowngradeCarrier { generation: 3, arfcn, priority: priority?.0 }))
+        .collect()
+}
+
+fn geran_carriers(sib7: &SystemInformationBlockType7) -> Vec<DowngradeCarrier> {
+    sib7.carrier_freqs_info_list.iter()
+        .flat_map(|list| &list.0)
+        .filter_map(|carrier| {
+            let CellReselectionPriority(priority) = carrier.common_info.cell_reselection_priority.as_ref()?;
+            Some(DowngradeCarrier { generation: 2, arfcn: carrier.carrier_freqs.starting_arfcn.0, priority: *priority })
+        })
+        .collect()
+}
+
+/// The reselection priorities a cell has advertised so far.
+#[derive(Default)]
+struct CellPriorities {
+    // from SIB3
+    serving: Option<u8>,
+    // from SIB5
+    inter_freq: Vec<u8>,
+    // from SIB6
+    utra: Vec<DowngradeCarrier>,
+    // from SIB7
+    geran: Vec<DowngradeCarrier>,
+}
+
+impl CellPriorities {
+    /// The highest priority of any LTE frequency, if we've seen the serving
+    /// cell's.
+    fn lte_priority(&self) -> Option<u8> {
+        let serving = self.serving?;
+        Some(self.inter_freq.iter().copied().fold(serving, u8::max))
     }
 }
 
-// TODO: keep track of SIB state to compare LTE reselection blocks w/ 2g/3g ones
+/// Based on heuristic T7 from Shinjo Park's "Why We Cannot Win": a cell which
+/// gives 2G/3G carriers a higher reselection priority than any LTE frequency
+/// is pushing phones off of LTE.
+#[derive(Default)]
+pub struct LteSib6And7DowngradeAnalyzer {
+    cells: HashMap<Option<PhysicalCell>, CellPriorities>,
+}
+
 impl Analyzer for LteSib6And7DowngradeAnalyzer {
+    fn get_id(&self) -> Cow<str> {
+        Cow::from("lte_sib6_and_7_downgrade")
+    }
+
+    fn get_version(&self) -> u32 {
+        2
+    }
+
     fn get_name(&self) -> Cow<str> {
         Cow::from("LTE SIB 6/7 Downgrade")
     }
@@ -33,51 +92,132 @@ impl Analyzer for LteSib6And7DowngradeAnalyzer {
         Cow::from("Tests for LTE cells broadcasting a SIB type 6 and 7 which include 2G/3G frequencies with higher priorities.")
     }
 
-    fn analyze_information_element(&mut self, ie: &InformationElement) -> Option<super::analyzer::Event> {
-        let sibs = &self.unpack_system_information(ie)?.0;
+    fn get_interests(&self) -> Interests {
+        Interests::lte_channels([LteChannel::BcchDlSch])
+    }
+
+    fn analyze_information_element(&mut self, ie: &InformationElement, context: &PacketContext) -> Option<Event> {
+        let sibs = system_information_blocks(ie)?;
+        let priorities = self.cells.entry(context.cell).or_default();
+        let mut updated = false;
         for sib in sibs {
             match sib {
-                SystemInformation_r8_IEsSib_TypeAndInfo_Entry::Sib6(sib6) => {
-                    if let Some(carrier_info_list) = sib6.carrier_freq_list_utra_fdd.as_ref() {
-                        for carrier_info in &carrier_info_list.0 {
-                            if let Some(CellReselectionPriority(p)) = carrier_info.cell_reselection_priority {
-                                if p == 0 {
-                                    return Some(Event {
-                                        event_type: EventType::QualitativeWarning { severity: Severity::High },
-                                        message: "LTE cell advertised a 3G cell for priority 0 reselection".to_string(),
-                                    });
-                                }
-                            }
-                        }
-                    }
-                    if let Some(carrier_info_list) = sib6.carrier_freq_list_utra_tdd.as_ref() {
-                        for carrier_info in &carrier_info_list.0 {
-                            if let Some(CellReselectionPriority(p)) = carrier_info.cell_reselection_priority {
-                                if p == 0 {
-                                    return Some(Event {
-                                        event_type: EventType::QualitativeWarning { severity: Severity::High },
-                                        message: "LTE cell advertised a 3G cell for priority 0 reselection".to_string(),
-                                    });
-                                }
-                            }
-                        }
-                    }
+                SystemInformation_r8_IEsSib_TypeAndInfo_Entry::Sib3(sib3) => {
+                    priorities.serving = Some(sib3.cell_reselection_serving_freq_info.cell_reselection_priority.0);
                 },
-                SystemInformation_r8_IEsSib_TypeAndInfo_Entry::Sib7(SystemInformationBlockType7 { carrier_freqs_info_list: Some(carrier_info_list), .. }) => {
-                    for carrier_info in &carrier_info_list.0 {
-                        if let Some(CellReselectionPriority(p)) = carrier_info.common_info.cell_reselection_priority {
-                            if p == 0 {
-                                return Some(Event {
-                                    event_type: EventType::QualitativeWarning { severity: Severity::High },
-                                    message: "LTE cell advertised a 2G cell for priority 0 reselection".to_string(),
-                                });
-                            }
-                        }
-                    }
+                SystemInformation_r8_IEsSib_TypeAndInfo_Entry::Sib5(sib5) => {
+                    priorities.inter_freq = sib5.inter_freq_carrier_freq_list.0.iter()
+                        .filter_map(|carrier| Some(carrier.cell_reselection_priority.as_ref()?.0))
+                        .collect();
                 },
-                _ => {},
+                SystemInformation_r8_IEsSib_TypeAndInfo_Entry::Sib6(sib6) => priorities.utra = utra_carriers(sib6),
+                SystemInformation_r8_IEsSib_TypeAndInfo_Entry::Sib7(sib7) => priorities.geran = geran_carriers(sib7),
+                _ => continue,
             }
+            updated = true;
+        }
+        if !updated {
+            return None;
         }
-        None
+
+        let lte_priority = priorities.lte_priority()?;
+        let downgrades: Vec<String> = priorities.utra.iter()
+            .chain(&priorities.geran)
+            .filter(|carrier| carrier.priority > lte_priority)
+            .map(|carrier| carrier.to_string())
+            .collect();
+        if downgrades.is_empty() {
+            return None;
+        }
+        Some(Event {
+            event_type: EventType::QualitativeWarning { severity: Severity::High },
+            message: format!(
+                "LTE cell advertised a higher reselection priority than any LTE frequency (at most {}) for: {}",
+                lte_priority, downgrades.join(", "),
+            ),
+        })
+    }
+
+    fn on_recording_start(&mut self) {
+        self.cells.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::test_util::context_on;
+    use crate::analysis::information_element::LteInformationElement;
+    use telcom_parser::lte_rrc::*;
+
+    fn sib3(priority: u8) -> SystemInformation_r8_IEsSib_TypeAndInfo_Entry {
+        SystemInformation_r8_IEsSib_TypeAndInfo_Entry::Sib3(SystemInformationBlockType3 {
+            cell_reselection_info_common: SystemInformationBlockType3CellReselectionInfoCommon {
+                q_hyst: SystemInformationBlockType3CellReselectionInfoCommonQ_Hyst(0),
+                speed_state_reselection_pars: None,
+            },
+            cell_reselection_serving_freq_info: SystemInformationBlockType3CellReselectionServingFreqInfo {
+                s_non_intra_search: None,
+                thresh_serving_low: ReselectionThreshold(0),
+                cell_reselection_priority: CellReselectionPriority(priority),
+            },
+            intra_freq_cell_reselection_info: SystemInformationBlockType3IntraFreqCellReselectionInfo {
+                q_rx_lev_min: Q_RxLevMin(-60),
+                p_max: None,
+                s_intra_search: None,
+                allowed_meas_bandwidth: None,
+                presence_antenna_port1: PresenceAntennaPort1(false),
+                neigh_cell_config: NeighCellConfig(Default::default()),
+                t_reselection_eutra: T_Reselection(1),
+                t_reselection_eutra_sf: None,
+            },
+        })
+    }
+
+    fn sib6(uarfcn: u16, priority: u8) -> SystemInformation_r8_IEsSib_TypeAndInfo_Entry {
+        SystemInformation_r8_IEsSib_TypeAndInfo_Entry::Sib6(SystemInformationBlockType6 {
+            carrier_freq_list_utra_fdd: Some(CarrierFreqListUTRA_FDD(vec![CarrierFreqUTRA_FDD {
+                carrier_freq: ARFCN_ValueUTRA(uarfcn),
+                cell_reselection_priority: Some(CellReselectionPriority(priority)),
+                thresh_x_high: ReselectionThreshold(0),
+                thresh_x_low: ReselectionThreshold(0),
+                q_rx_lev_min: CarrierFreqUTRA_FDDQ_RxLevMin(-60),
+                p_max_utra: CarrierFreqUTRA_FDDP_MaxUTRA(24),
+                q_qual_min: CarrierFreqUTRA_FDDQ_QualMin(-20),
+            }])),
+            carrier_freq_list_utra_tdd: None,
+            t_reselection_utra: T_Reselection(1),
+            t_reselection_utra_sf: None,
+        })
+    }
+
+    fn system_information(sibs: Vec<SystemInformation_r8_IEsSib_TypeAndInfo_Entry>) -> InformationElement {
+        let message = BCCH_DL_SCH_Message {
+            message: BCCH_DL_SCH_MessageType::C1(BCCH_DL_SCH_MessageType_c1::SystemInformation(SystemInformation {
+                critical_extensions: SystemInformationCriticalExtensions::SystemInformation_r8(SystemInformation_r8_IEs {
+                    sib_type_and_info: SystemInformation_r8_IEsSib_TypeAndInfo(sibs),
+                    non_critical_extension: None,
+                }),
+            })),
+        };
+        InformationElement::LTE(Box::new(LteInformationElement::BcchDlSch(message)))
+    }
+
+    fn context(phy_cell_id: u16) -> PacketContext {
+        context_on(1, phy_cell_id)
+    }
+
+    #[test]
+    fn test_relative_priorities() {
+        let mut analyzer = LteSib6And7DowngradeAnalyzer::default();
+        // we can't compare until we know the LTE priorities
+        assert!(analyzer.analyze_information_element(&system_information(vec![sib6(10700, 6)]), &context(1)).is_none());
+        let event = analyzer.analyze_information_element(&system_information(vec![sib3(5)]), &context(1)).unwrap();
+        assert_eq!(event.event_type, EventType::QualitativeWarning { severity: Severity::High });
+        assert!(event.message.contains("(at most 5) for: 3G carrier on UARFCN 10700 at priority 6"));
+
+        // a lower priority 3G carrier is normal, including at priority 0
+        assert!(analyzer.analyze_information_element(&system_information(vec![sib3(5), sib6(10700, 0)]), &context(2)).is_none());
+        assert!(analyzer.analyze_information_element(&system_information(vec![sib6(10700, 5)]), &context(2)).is_none());
     }
 }
@@ -0,0 +1,248 @@
+//! Tracks the state of the UE's RRC connection, and whether AS security is
+//! active on it, so [Analyzers](super::analyzer::Analyzer) can tell e.g.
+//! whether a message was sent before security was set up without each having
+//! to follow the protocol themselves.
+//!
+//! The [Harness](super::analyzer::Harness) runs a single [RrcStateTracker] over
+//! every message, and hands analyzers the resulting [RrcConnectionState] in
+//! each [PacketContext](super::context::PacketContext).
+
+use serde::Serialize;
+use telcom_parser::lte_rrc::{DL_CCCH_MessageType, DL_CCCH_MessageType_c1, DL_DCCH_MessageType, DL_DCCH_MessageType_c1, UL_DCCH_MessageType, UL_DCCH_MessageType_c1};
+
+use super::information_element::{InformationElement, LteChannel, LteInformationElement};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RrcState {
+    /// We haven't seen the connection being set up or released yet, e.g.
+    /// because the recording started part way through a connection.
+    #[default]
+    Unknown,
+    Idle,
+    Connected,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AsSecurityState {
+    #[default]
+    Unknown,
+    Inactive,
+    /// The network sent a SecurityModeCommand, but the UE hasn't completed it.
+    Commanded,
+    Active,
+}
+
+/// The state of the RRC connection when a message was sent or received.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RrcConnectionState {
+    pub rrc: RrcState,
+    pub security: AsSecurityState,
+}
+
+impl RrcConnectionState {
+    /// Whether we know the UE is connected, and that the network hasn't
+    /// started activating AS security yet.
+    pub fn is_connected_without_security(&self) -> bool {
+        self.rrc == RrcState::Connected && self.security == AsSecurityState::Inactive
+    }
+}
+
+/// Follows RRCConnectionSetup, SecurityModeCommand/Complete and
+/// RRCConnectionRelease (among others) to keep an [RrcConnectionState] up to
+/// date.
+#[derive(Default)]
+pub struct RrcStateTracker {
+    state: RrcConnectionState,
+}
+
+impl RrcStateTracker {
+    /// The channels the tracker needs to see messages on.
+    pub const CHANNELS: [LteChannel; 3] = [LteChannel::DlCcch, LteChannel::DlDcch, LteChannel::UlDcch];
+
+    pub fn state(&self) -> RrcConnectionState {
+        self.state
+    }
+
+    pub fn reset(&mut self) {
+        self.state = RrcConnectionState::default();
+    }
+
+    pub fn observe(&mut self, ie: &InformationElement) {
+        let InformationElement::LTE(lte_ie) = ie else {
+            return;
+        };
+        let (rrc, security) = match &**lte_ie {
+            LteInformationElement::DlCcch(msg) => match &msg.message {
+                DL_CCCH_MessageType::C1(DL_CCCH_MessageType_c1::RrcConnectionSetup(_)) =>
+                    (RrcState::Connected, AsSecurityState::Inactive),
+                // reestablishment resumes security with the previous config
+                DL_CCCH_MessageType::C1(DL_CCCH_MessageType_c1::RrcConnectionReestablishment(_)) =>
+                    (RrcState::Connected, AsSecurityState::Active),
+                DL_CCCH_MessageType::C1(
+                    DL_CCCH_MessageType_c1::RrcConnectionReject(_) | DL_CCCH_MessageType_c1::RrcConnectionReestablishmentReject(_)
+                ) => (RrcState::Idle, AsSecurityState::Inactive),
+                _ => return,
+            },
+            LteInformationElement::DlDcch(msg) => match &msg.message {
+                DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::SecurityModeCommand(_)) =>
+                    (RrcState::Connected, AsSecurityState::Commanded),
+                DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::RrcConnectionRelease(_)) =>
+                    (RrcState::Idle, AsSecurityState::Inactive),
+                _ => return,
+            },
+            LteInformationElement::UlDcch(msg) => match &msg.message {
+                UL_DCCH_MessageType::C1(UL_DCCH_MessageType_c1::SecurityModeComplete(_)) =>
+                    (RrcState::Connected, AsSecurityState::Active),
+                UL_DCCH_MessageType::C1(UL_DCCH_MessageType_c1::SecurityModeFailure(_)) =>
+                    (RrcState::Connected, AsSecurityState::Inactive),
+                _ => return,
+            },
+            _ => return,
+        };
+        self.state = RrcConnectionState { rrc, security };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use telcom_parser::lte_rrc::{
+        DL_CCCH_Message, DL_DCCH_Message, RRC_TransactionIdentifier, RRCConnectionReject,
+        RRCConnectionRejectCriticalExtensions, RRCConnectionRejectCriticalExtensions_criticalExtensionsFuture,
+        RRCConnectionRelease, RRCConnectionReleaseCriticalExtensions,
+        RRCConnectionReleaseCriticalExtensions_criticalExtensionsFuture, RRCConnectionReestablishment,
+        RRCConnectionReestablishmentCriticalExtensions,
+        RRCConnectionReestablishmentCriticalExtensions_criticalExtensionsFuture, RRCConnectionSetup,
+        RRCConnectionSetupCriticalExtensions, RRCConnectionSetupCriticalExtensions_criticalExtensionsFuture,
+        SecurityModeCommand, SecurityModeCommandCriticalExtensions,
+        SecurityModeCommandCriticalExtensions_criticalExtensionsFuture, SecurityModeComplete,
+        SecurityModeCompleteCriticalExtensions, SecurityModeCompleteCriticalExtensions_criticalExtensionsFuture,
+        SecurityModeFailure, SecurityModeFailureCriticalExtensions,
+        SecurityModeFailureCriticalExtensions_criticalExtensionsFuture, UL_DCCH_Message,
+    };
+
+    fn dl_ccch(c1: DL_CCCH_MessageType_c1) -> InformationElement {
+        let message = DL_CCCH_Message { message: DL_CCCH_MessageType::C1(c1) };
+        InformationElement::LTE(Box::new(LteInformationElement::DlCcch(message)))
+    }
+
+    fn dl_dcch(c1: DL_DCCH_MessageType_c1) -> InformationElement {
+        let message = DL_DCCH_Message { message: DL_DCCH_MessageType::C1(c1) };
+        InformationElement::LTE(Box::new(LteInformationElement::DlDcch(Box::new(message))))
+    }
+
+    fn ul_dcch(c1: UL_DCCH_MessageType_c1) -> InformationElement {
+        let message = UL_DCCH_Message { message: UL_DCCH_MessageType::C1(c1) };
+        InformationElement::LTE(Box::new(LteInformationElement::UlDcch(message)))
+    }
+
+    fn connection_setup() -> InformationElement {
+        dl_ccch(DL_CCCH_MessageType_c1::RrcConnectionSetup(RRCConnectionSetup {
+            rrc_transaction_identifier: RRC_TransactionIdentifier(0),
+            critical_extensions: RRCConnectionSetupCriticalExtensions::CriticalExtensionsFuture(
+                RRCConnectionSetupCriticalExtensions_criticalExtensionsFuture {}
+            ),
+        }))
+    }
+
+    fn connection_reestablishment() -> InformationElement {
+        dl_ccch(DL_CCCH_MessageType_c1::RrcConnectionReestablishment(RRCConnectionReestablishment {
+            rrc_transaction_identifier: RRC_TransactionIdentifier(0),
+            critical_extensions: RRCConnectionReestablishmentCriticalExtensions::CriticalExtensionsFuture(
+                RRCConnectionReestablishmentCriticalExtensions_criticalExtensionsFuture {}
+            ),
+        }))
+    }
+
+    fn connection_reject() -> InformationElement {
+        dl_ccch(DL_CCCH_MessageType_c1::RrcConnectionReject(RRCConnectionReject {
+            critical_extensions: RRCConnectionRejectCriticalExtensions::CriticalExtensionsFuture(
+                RRCConnectionRejectCriticalExtensions_criticalExtensionsFuture {}
+            ),
+        }))
+    }
+
+    fn security_mode_command() -> InformationElement {
+        dl_dcch(DL_DCCH_MessageType_c1::SecurityModeCommand(SecurityModeCommand {
+            rrc_transaction_identifier: RRC_TransactionIdentifier(0),
+            critical_extensions: SecurityModeCommandCriticalExtensions::CriticalExtensionsFuture(
+                SecurityModeCommandCriticalExtensions_criticalExtensionsFuture {}
+            ),
+        }))
+    }
+
+    fn connection_release() -> InformationElement {
+        dl_dcch(DL_DCCH_MessageType_c1::RrcConnectionRelease(RRCConnectionRelease {
+            rrc_transaction_identifier: RRC_TransactionIdentifier(0),
+            critical_extensions: RRCConnectionReleaseCriticalExtensions::CriticalExtensionsFuture(
+                RRCConnectionReleaseCriticalExtensions_criticalExtensionsFuture {}
+            ),
+        }))
+    }
+
+    fn security_mode_complete() -> InformationElement {
+        ul_dcch(UL_DCCH_MessageType_c1::SecurityModeComplete(SecurityModeComplete {
+            rrc_transaction_identifier: RRC_TransactionIdentifier(0),
+            critical_extensions: SecurityModeCompleteCriticalExtensions::CriticalExtensionsFuture(
+                SecurityModeCompleteCriticalExtensions_criticalExtensionsFuture {}
+            ),
+        }))
+    }
+
+    fn security_mode_failure() -> InformationElement {
+        ul_dcch(UL_DCCH_MessageType_c1::SecurityModeFailure(SecurityModeFailure {
+            rrc_transaction_identifier: RRC_TransactionIdentifier(0),
+            critical_extensions: SecurityModeFailureCriticalExtensions::CriticalExtensionsFuture(
+                SecurityModeFailureCriticalExtensions_criticalExtensionsFuture {}
+            ),
+        }))
+    }
+
+    fn state(rrc: RrcState, security: AsSecurityState) -> RrcConnectionState {
+        RrcConnectionState { rrc, security }
+    }
+
+    #[test]
+    fn test_connection_lifecycle() {
+        let mut tracker = RrcStateTracker::default();
+        assert_eq!(tracker.state(), state(RrcState::Unknown, AsSecurityState::Unknown));
+        assert!(!tracker.state().is_connected_without_security());
+
+        tracker.observe(&connection_setup());
+        assert_eq!(tracker.state(), state(RrcState::Connected, AsSecurityState::Inactive));
+        assert!(tracker.state().is_connected_without_security());
+
+        tracker.observe(&security_mode_command());
+        assert_eq!(tracker.state(), state(RrcState::Connected, AsSecurityState::Commanded));
+        assert!(!tracker.state().is_connected_without_security());
+
+        tracker.observe(&security_mode_complete());
+        assert_eq!(tracker.state(), state(RrcState::Connected, AsSecurityState::Active));
+
+        tracker.observe(&connection_release());
+        assert_eq!(tracker.state(), state(RrcState::Idle, AsSecurityState::Inactive));
+        assert!(!tracker.state().is_connected_without_security());
+    }
+
+    #[test]
+    fn test_other_transitions() {
+        let mut tracker = RrcStateTracker::default();
+        tracker.observe(&connection_reestablishment());
+        assert_eq!(tracker.state(), state(RrcState::Connected, AsSecurityState::Active));
+
+        tracker.observe(&connection_reject());
+        assert_eq!(tracker.state(), state(RrcState::Idle, AsSecurityState::Inactive));
+
+        tracker.observe(&connection_setup());
+        tracker.observe(&security_mode_command());
+        tracker.observe(&security_mode_failure());
+        assert_eq!(tracker.state(), state(RrcState::Connected, AsSecurityState::Inactive));
+
+        // unrelated messages leave the state alone
+        tracker.observe(&InformationElement::LTE(Box::new(LteInformationElement::NAS(vec![]))));
+        assert_eq!(tracker.state(), state(RrcState::Connected, AsSecurityState::Inactive));
+
+        tracker.reset();
+        assert_eq!(tracker.state(), RrcConnectionState::default());
+    }
+}
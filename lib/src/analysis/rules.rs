@@ -0,0 +1,408 @@
+//! A small rule engine which lets heuristics be shipped as data files instead
+//! of compiled [Analyzers](Analyzer). Rule files are TOML or JSON, and each
+//! rule matches field paths and values in a decoded LTE message. For example:
+//!
+//! ```toml
+//! [[rules]]
+//! id = "geran_redirect_rule"
+//! name = "GERAN Redirect"
+//! channel = "DlDcch"
+//! severity = "High"
+//! message = "Cell released our connection and redirected us to a 2G carrier"
+//!
+//! [[rules.conditions]]
+//! path = "message.C1.RrcConnectionRelease.critical_extensions.C1.RrcConnectionRelease_r8.redirected_carrier_info.Geran"
+//! present = true
+//! ```
+//!
+//! Paths are matched against the message's serde representation: struct
+//! fields go by their snake_case names, CHOICE alternatives by their variant
+//! names, and ENUMERATED/INTEGER values are plain numbers. A `*` segment
+//! matches every element of a list. A rule fires when all of its conditions
+//! hold, and may set `dedup_seconds` to merge repeats like the built-in
+//! analyzers do.
+
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use serde_json::Value;
+use thiserror::Error;
+
+use super::analyzer::{Analyzer, AnalyzerOptions, Event, EventType, Interests, Severity};
+use super::context::PacketContext;
+use super::information_element::{InformationElement, LteChannel};
+
+#[derive(Error, Debug)]
+pub enum RuleError {
+    #[error("Failed to read rules from {0}: {1}")]
+    ReadError(PathBuf, std::io::Error),
+    #[error("Failed to parse TOML rules file {0}: {1}")]
+    TomlParsingError(PathBuf, toml::de::Error),
+    #[error("Failed to parse JSON rules file {0}: {1}")]
+    JsonParsingError(PathBuf, serde_json::Error),
+    #[error("Rules file {0} must have a .toml or .json extension")]
+    UnsupportedExtension(PathBuf),
+    #[error("Invalid rule \"{0}\": {1}")]
+    InvalidRule(String, String),
+    #[error("Duplicate analyzer ID \"{0}\"")]
+    DuplicateId(String),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default)]
+    rules: Vec<Rule>,
+}
+
+fn default_version() -> u32 {
+    1
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Used as the [Analyzer] ID, so must be unique among all analyzers.
+    pub id: String,
+    #[serde(default = "default_version")]
+    pub version: u32,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub channel: LteChannel,
+    /// Matches are reported as `QualitativeWarning`s with this severity, or
+    /// as `Informational` events if unset.
+    pub severity: Option<Severity>,
+    pub message: String,
+    /// If set, repeats of the rule's event on the same cell within this many
+    /// seconds of each other are merged (see [dedup](super::dedup)).
+    pub dedup_seconds: Option<u32>,
+    pub conditions: Vec<Condition>,
+}
+
+/// A test against the value(s) found at `path`. If more than one test is
+/// given, a single value must pass all of them.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Condition {
+    pub path: String,
+    /// `true` if some non-null value must exist at `path`, `false` if none may.
+    pub present: Option<bool>,
+    pub equals: Option<Value>,
+    pub one_of: Option<Vec<Value>>,
+    pub less_than: Option<f64>,
+    pub greater_than: Option<f64>,
+}
+
+impl Rule {
+    /// Returns the options the [Harness](super::analyzer::Harness) should run
+    /// the rule with.
+    pub fn options(&self) -> AnalyzerOptions {
+        AnalyzerOptions::new(self.severity, self.dedup_seconds)
+    }
+
+    fn validate(&self) -> Result<(), RuleError> {
+        let invalid = |reason: &str| Err(RuleError::InvalidRule(self.id.clone(), reason.to_string()));
+        if self.id.is_empty() {
+            return invalid("id must not be empty");
+        }
+        if self.conditions.is_empty() {
+            return invalid("rules must have at least one condition");
+        }
+        for condition in &self.conditions {
+            if condition.path.split('.').any(str::is_empty) {
+                return invalid(&format!("malformed path \"{}\"", condition.path));
+            }
+            let has_value_test = condition.equals.is_some()
+                || condition.one_of.is_some()
+                || condition.less_than.is_some()
+                || condition.greater_than.is_some();
+            match condition.present {
+                None if !has_value_test => {
+                    return invalid(&format!("condition on \"{}\" doesn't test anything", condition.path));
+                }
+                Some(false) if has_value_test => {
+                    return invalid(&format!("condition on \"{}\" can't test the value of a field that must not be present", condition.path));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Condition {
+    fn matches(&self, root: &Value) -> bool {
+        let segments: Vec<&str> = self.path.split('.').collect();
+        let mut values = Vec::new();
+        resolve_path(root, &segments, &mut values);
+        let mut present = values.into_iter().filter(|value| !value.is_null());
+        if self.present == Some(false) {
+            return present.next().is_none();
+        }
+        present.any(|value| self.value_matches(value))
+    }
+
+    fn value_matches(&self, value: &Value) -> bool {
+        if let Some(expected) = &self.equals {
+            if value != expected {
+                return false;
+            }
+        }
+        if let Some(options) = &self.one_of {
+            if !options.contains(value) {
+                return false;
+            }
+        }
+        if self.less_than.is_some() || self.greater_than.is_some() {
+            let Some(number) = value.as_f64() else {
+                return false;
+            };
+            if self.less_than.is_some_and(|max| number >= max) {
+                return false;
+            }
+            if self.greater_than.is_some_and(|min| number <= min) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+// Collects every value reachable by following `segments` from `value`
+fn resolve_path<'a>(value: &'a Value, segments: &[&str], out: &mut Vec<&'a Value>) {
+    let Some((segment, rest)) = segments.split_first() else {
+        out.push(value);
+        return;
+    };
+    match value {
+        Value::Array(items) if *segment == "*" => {
+            for item in items {
+                resolve_path(item, rest, out);
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item) = segment.parse::<usize>().ok().and_then(|i| items.get(i)) {
+                resolve_path(item, rest, out);
+            }
+        }
+        Value::Object(fields) => {
+            if let Some(field) = fields.get(*segment) {
+                resolve_path(field, rest, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+pub fn parse_toml_rules(rules: &str, path: &Path) -> Result<Vec<Rule>, RuleError> {
+    let file: RulesFile = toml::from_str(rules)
+        .map_err(|err| RuleError::TomlParsingError(path.to_path_buf(), err))?;
+    file.rules.iter().try_for_each(Rule::validate)?;
+    Ok(file.rules)
+}
+
+pub fn parse_json_rules(rules: &str, path: &Path) -> Result<Vec<Rule>, RuleError> {
+    let file: RulesFile = serde_json::from_str(rules)
+        .map_err(|err| RuleError::JsonParsingError(path.to_path_buf(), err))?;
+    file.rules.iter().try_for_each(Rule::validate)?;
+    Ok(file.rules)
+}
+
+/// Loads the rules in a single TOML/JSON file, or in every TOML/JSON file in a
+/// directory.
+pub fn load_rules(path: &Path) -> Result<Vec<Rule>, RuleError> {
+    let read_error = |err| RuleError::ReadError(path.to_path_buf(), err);
+    if path.is_dir() {
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(path).map_err(read_error)? {
+            let entry_path = entry.map_err(read_error)?.path();
+            if matches!(entry_path.extension().and_then(|ext| ext.to_str()), Some("toml" | "json")) {
+                paths.push(entry_path);
+            }
+        }
+        // load them in a consistent order, so analyzers are too
+        paths.sort();
+        let mut rules = Vec::new();
+        for entry_path in paths {
+            rules.extend(load_rules(&entry_path)?);
+        }
+        return Ok(rules);
+    }
+
+    let contents = std::fs::read_to_string(path).map_err(read_error)?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => parse_toml_rules(&contents, path),
+        Some("json") => parse_json_rules(&contents, path),
+        _ => Err(RuleError::UnsupportedExtension(path.to_path_buf())),
+    }
+}
+
+/// Runs a single [Rule] as an [Analyzer].
+pub struct RuleAnalyzer {
+    rule: Rule,
+}
+
+impl RuleAnalyzer {
+    pub fn new(rule: Rule) -> Self {
+        Self { rule }
+    }
+}
+
+impl Analyzer for RuleAnalyzer {
+    fn get_id(&self) -> Cow<str> {
+        Cow::from(&self.rule.id)
+    }
+
+    fn get_version(&self) -> u32 {
+        self.rule.version
+    }
+
+    fn get_name(&self) -> Cow<str> {
+        Cow::from(&self.rule.name)
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(&self.rule.description)
+    }
+
+    fn get_interests(&self) -> Interests {
+        Interests::lte_channels([self.rule.channel])
+    }
+
+    fn wants_serialized_messages(&self) -> bool {
+        true
+    }
+
+    fn analyze_information_element(&mut self, ie: &InformationElement, context: &PacketContext) -> Option<Event> {
+        let InformationElement::LTE(lte_ie) = ie else {
+            return None;
+        };
+        if lte_ie.channel() != self.rule.channel {
+            return None;
+        }
+        self.analyze_serialized_message(&serde_json::to_value(lte_ie).ok()?, context)
+    }
+
+    // the Harness only hands us messages on the rule's channel, thanks to our
+    // interests
+    fn analyze_serialized_message(&mut self, serialized: &Value, _context: &PacketContext) -> Option<Event> {
+        // LteInformationElement serializes as {"<channel>": <message>}, and
+        // rule paths are relative to the message itself
+        let message = serialized.as_object()?.values().next()?;
+        if !self.rule.conditions.iter().all(|condition| condition.matches(message)) {
+            return None;
+        }
+        let event_type = match self.rule.severity {
+            Some(severity) => EventType::QualitativeWarning { severity },
+            None => EventType::Informational,
+        };
+        Some(Event {
+            event_type,
+            message: self.rule.message.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+    use crate::analysis::test_util::context;
+    use crate::analysis::information_element::LteInformationElement;
+    use telcom_parser::lte_rrc::{
+        ARFCN_ValueGERAN, BandIndicatorGERAN, CarrierFreqsGERAN, CarrierFreqsGERANFollowingARFCNs,
+        DL_DCCH_Message, DL_DCCH_MessageType, DL_DCCH_MessageType_c1, ExplicitListOfARFCNs,
+        RRCConnectionRelease, RRCConnectionReleaseCriticalExtensions,
+        RRCConnectionReleaseCriticalExtensions_c1, RRCConnectionRelease_r8_IEs,
+        RRC_TransactionIdentifier, RedirectedCarrierInfo, ReleaseCause,
+    };
+
+    const GERAN_REDIRECT_RULE: &str = r#"
+        [[rules]]
+        id = "geran_redirect_rule"
+        name = "GERAN Redirect"
+        channel = "DlDcch"
+        severity = "High"
+        message = "redirected to 2G"
+
+        [[rules.conditions]]
+        path = "message.C1.RrcConnectionRelease.critical_extensions.C1.RrcConnectionRelease_r8.redirected_carrier_info.Geran"
+        present = true
+
+        [[rules.conditions]]
+        path = "message.C1.RrcConnectionRelease.critical_extensions.C1.RrcConnectionRelease_r8.release_cause"
+        one_of = [0, 1]
+    "#;
+
+    fn release(redirected_carrier_info: Option<RedirectedCarrierInfo>) -> InformationElement {
+        let release = RRCConnectionRelease {
+            rrc_transaction_identifier: RRC_TransactionIdentifier(0),
+            critical_extensions: RRCConnectionReleaseCriticalExtensions::C1(
+                RRCConnectionReleaseCriticalExtensions_c1::RrcConnectionRelease_r8(RRCConnectionRelease_r8_IEs {
+                    release_cause: ReleaseCause(ReleaseCause::OTHER),
+                    redirected_carrier_info,
+                    idle_mode_mobility_control_info: None,
+                    non_critical_extension: None,
+                })
+            ),
+        };
+        let message = DL_DCCH_Message {
+            message: DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::RrcConnectionRelease(release)),
+        };
+        InformationElement::LTE(Box::new(LteInformationElement::DlDcch(Box::new(message))))
+    }
+
+    #[test]
+    fn test_rule_matching() {
+        let rules = parse_toml_rules(GERAN_REDIRECT_RULE, Path::new("test.toml")).unwrap();
+        let mut analyzer = RuleAnalyzer::new(rules[0].clone());
+        let geran = RedirectedCarrierInfo::Geran(CarrierFreqsGERAN {
+            starting_arfcn: ARFCN_ValueGERAN(10),
+            band_indicator: BandIndicatorGERAN(BandIndicatorGERAN::DCS1800),
+            following_arfc_ns: CarrierFreqsGERANFollowingARFCNs::ExplicitListOfARFCNs(ExplicitListOfARFCNs(vec![])),
+        });
+        let event = analyzer.analyze_information_element(&release(Some(geran.clone())), &context()).unwrap();
+        assert!(matches!(event.event_type, EventType::QualitativeWarning { severity: Severity::High }));
+        assert_eq!(event.message, "redirected to 2G");
+        assert!(analyzer.analyze_information_element(&release(None), &context()).is_none());
+
+        let InformationElement::LTE(lte_ie) = release(Some(geran)) else {
+            unreachable!();
+        };
+        let serialized = serde_json::to_value(lte_ie).unwrap();
+        assert_eq!(analyzer.analyze_serialized_message(&serialized, &context()), Some(event));
+    }
+
+    #[test]
+    fn test_rule_options() {
+        let mut rules = parse_toml_rules(GERAN_REDIRECT_RULE, Path::new("test.toml")).unwrap();
+        let options = rules[0].options();
+        assert_eq!(options.severity_override, Some(Severity::High));
+        assert_eq!(options.dedup_window, None);
+        rules[0].dedup_seconds = Some(30);
+        assert_eq!(rules[0].options().dedup_window, Some(TimeDelta::seconds(30)));
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        let no_test = r#"
+            [[rules]]
+            id = "foo"
+            name = "Foo"
+            channel = "PCCH"
+            message = "foo"
+            conditions = [{ path = "message" }]
+        "#;
+        assert!(matches!(parse_toml_rules(no_test, Path::new("test.toml")), Err(RuleError::InvalidRule(..))));
+        let bad_channel = r#"{"rules": [{"id": "foo", "name": "Foo", "channel": "Nope", "message": "foo", "conditions": []}]}"#;
+        assert!(matches!(parse_json_rules(bad_channel, Path::new("test.json")), Err(RuleError::JsonParsingError(..))));
+    }
+}
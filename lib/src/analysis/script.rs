@@ -0,0 +1,457 @@
+//! Analyzers written as [Rhai](https://rhai.rs) scripts, for stateful
+//! heuristics which don't warrant a Rust toolchain. This requires building
+//! rayhunter with the `scripting` feature. Scripts are listed in the
+//! `[analyzers]` section of the config file:
+//!
+//! ```toml
+//! [[analyzers.scripts]]
+//! id = "lots_of_paging"
+//! path = "/data/rayhunter/scripts/lots_of_paging.rhai"
+//! channels = ["PCCH"]
+//!
+//! [analyzers.scripts.limits]
+//! max_operations = 50000
+//! ```
+//!
+//! A script must define an `analyze(ie, context)` function, which is called
+//! with the [LteInformationElement] and [PacketContext] as maps, and may
+//! return a map with a `message` and optional `severity` to emit an [Event].
+//! State is kept in `this`, which starts out as the return value of the
+//! optional `init()` function (or an empty map), and is reset whenever a new
+//! recording starts. An optional `on_recording_end()` function may return an
+//! event, or an array of them, to summarize the recording. Scripts may also
+//! define `name()`, `description()` and `version()`:
+//!
+//! ```rhai
+//! fn init() { #{ count: 0 } }
+//!
+//! fn analyze(ie, context) {
+//!     this.count += 1;
+//!     if this.count % 1000 == 0 {
+//!         return #{ severity: "Low", message: `${this.count} paging messages` };
+//!     }
+//! }
+//! ```
+//!
+//! [LteInformationElement]: super::information_element::LteInformationElement
+//! [PacketContext]: super::context::PacketContext
+//! [Event]: super::analyzer::Event
+
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::analyzer::{AnalyzerOptions, Severity};
+use super::information_element::LteChannel;
+
+#[derive(Error, Debug)]
+pub enum ScriptError {
+    #[error("Failed to read script {0}: {1}")]
+    ReadError(PathBuf, std::io::Error),
+    #[error("Can't load script {0}, rayhunter was built without the \"scripting\" feature")]
+    ScriptingDisabled(PathBuf),
+    #[cfg(feature = "scripting")]
+    #[error("Failed to compile script {0}: {1}")]
+    CompileError(PathBuf, rhai::ParseError),
+    #[error("Script {0} doesn't define an analyze(ie, context) function")]
+    MissingAnalyzeFunction(PathBuf),
+    #[error("Script {0} failed to initialize: {1}")]
+    InitError(PathBuf, String),
+    #[error("Duplicate analyzer ID \"{0}\"")]
+    DuplicateId(String),
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ScriptConfig {
+    /// Used as the analyzer ID, so must be unique among all analyzers.
+    pub id: String,
+    pub path: PathBuf,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Overrides the severity of any `QualitativeWarning` this script emits.
+    pub severity: Option<Severity>,
+    /// If set, repeats of the same event on the same cell within this many
+    /// seconds of each other are merged (see [dedup](super::dedup)).
+    pub dedup_seconds: Option<u32>,
+    /// If set, the script is only called for messages on these channels,
+    /// which saves converting every message for it.
+    pub channels: Option<Vec<LteChannel>>,
+    #[serde(default)]
+    pub limits: ScriptLimits,
+    /// The script's source, read from `path` by
+    /// [AnalyzersConfig::load_scripts](super::config::AnalyzersConfig::load_scripts).
+    #[serde(skip)]
+    pub source: String,
+}
+
+/// Caps on how much work a script may do per call, and how large the values
+/// it builds may get, so a misbehaving script can't stall the diag thread or
+/// exhaust the device's memory.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScriptLimits {
+    pub max_operations: u64,
+    pub max_call_levels: usize,
+    pub max_string_size: usize,
+    pub max_array_size: usize,
+    pub max_map_size: usize,
+}
+
+impl Default for ScriptLimits {
+    fn default() -> Self {
+        ScriptLimits {
+            max_operations: 100_000,
+            max_call_levels: 16,
+            max_string_size: 4096,
+            max_array_size: 1024,
+            max_map_size: 1024,
+        }
+    }
+}
+
+impl ScriptConfig {
+    /// Reads the script's source and, if scripting is enabled, makes sure it
+    /// compiles.
+    pub fn load(&mut self) -> Result<(), ScriptError> {
+        if !cfg!(feature = "scripting") {
+            return Err(ScriptError::ScriptingDisabled(self.path.clone()));
+        }
+        self.source = std::fs::read_to_string(&self.path)
+            .map_err(|err| ScriptError::ReadError(self.path.clone(), err))?;
+        #[cfg(feature = "scripting")]
+        ScriptAnalyzer::new(self)?;
+        Ok(())
+    }
+
+    /// Returns the options the [Harness](super::analyzer::Harness) should run
+    /// the script with.
+    pub fn options(&self) -> AnalyzerOptions {
+        AnalyzerOptions::new(self.severity, self.dedup_seconds)
+    }
+}
+
+#[cfg(feature = "scripting")]
+pub use self::engine::ScriptAnalyzer;
+
+#[cfg(feature = "scripting")]
+mod engine {
+    use std::borrow::Cow;
+
+    use log::{error, warn};
+    use rhai::{CallFnOptions, Dynamic, Engine, Scope, AST};
+    use serde::Deserialize;
+
+    use super::{ScriptConfig, ScriptError};
+    use crate::analysis::analyzer::{Analyzer, Event, EventType, Interests, Severity};
+    use crate::analysis::context::PacketContext;
+    use crate::analysis::information_element::InformationElement;
+
+    // After this many errors in a row, we assume the script is broken rather
+    // than unlucky, and stop calling it
+    const MAX_CONSECUTIVE_ERRORS: usize = 10;
+
+    #[derive(Deserialize)]
+    struct ScriptEvent {
+        message: String,
+        severity: Option<Severity>,
+    }
+
+    /// Runs a Rhai script as an [Analyzer].
+    pub struct ScriptAnalyzer {
+        config: ScriptConfig,
+        engine: Engine,
+        ast: AST,
+        state: Dynamic,
+        name: String,
+        description: String,
+        version: u32,
+        consecutive_errors: usize,
+    }
+
+    impl ScriptAnalyzer {
+        pub fn new(config: &ScriptConfig) -> Result<Self, ScriptError> {
+            let mut engine = Engine::new();
+            engine.set_max_operations(config.limits.max_operations)
+                .set_max_call_levels(config.limits.max_call_levels)
+                .set_max_string_size(config.limits.max_string_size)
+                .set_max_array_size(config.limits.max_array_size)
+                .set_max_map_size(config.limits.max_map_size);
+            let ast = engine.compile(&config.source)
+                .map_err(|err| ScriptError::CompileError(config.path.clone(), err))?;
+            if !ast.iter_functions().any(|f| f.name == "analyze" && f.params.len() == 2) {
+                return Err(ScriptError::MissingAnalyzeFunction(config.path.clone()));
+            }
+
+            let mut analyzer = ScriptAnalyzer {
+                config: config.clone(),
+                engine,
+                ast,
+                state: Dynamic::from_map(Default::default()),
+                name: config.id.clone(),
+                description: String::new(),
+                version: 1,
+                consecutive_errors: 0,
+            };
+            let init_error = |err: String| ScriptError::InitError(config.path.clone(), err);
+            if let Some(state) = analyzer.call_optional::<Dynamic>("init").map_err(init_error)? {
+                analyzer.state = state;
+            }
+            if let Some(name) = analyzer.call_optional::<String>("name").map_err(init_error)? {
+                analyzer.name = name;
+            }
+            if let Some(description) = analyzer.call_optional::<String>("description").map_err(init_error)? {
+                analyzer.description = description;
+            }
+            if let Some(version) = analyzer.call_optional::<i64>("version").map_err(init_error)? {
+                analyzer.version = u32::try_from(version).map_err(|err| init_error(err.to_string()))?;
+            }
+            Ok(analyzer)
+        }
+
+        // Calls a no-argument function, if the script defines one
+        fn call_optional<T: Clone + Send + Sync + 'static>(&self, name: &str) -> Result<Option<T>, String> {
+            if !self.ast.iter_functions().any(|f| f.name == name && f.params.is_empty()) {
+                return Ok(None);
+            }
+            self.engine.call_fn::<T>(&mut Scope::new(), &self.ast, name, ())
+                .map(Some)
+                .map_err(|err| err.to_string())
+        }
+
+        fn call_analyze(&mut self, ie: Dynamic, context: Dynamic) -> Result<Option<Event>, String> {
+            let options = CallFnOptions::new().bind_this_ptr(&mut self.state);
+            let result: Dynamic = self.engine
+                .call_fn_with_options(options, &mut Scope::new(), &self.ast, "analyze", (ie, context))
+                .map_err(|err| err.to_string())?;
+            if result.is_unit() {
+                return Ok(None);
+            }
+            self.to_event(&result).map(Some)
+        }
+
+        fn call_on_recording_end(&mut self) -> Result<Vec<Event>, String> {
+            if !self.ast.iter_functions().any(|f| f.name == "on_recording_end" && f.params.is_empty()) {
+                return Ok(Vec::new());
+            }
+            let options = CallFnOptions::new().bind_this_ptr(&mut self.state);
+            let result: Dynamic = self.engine
+                .call_fn_with_options(options, &mut Scope::new(), &self.ast, "on_recording_end", ())
+                .map_err(|err| err.to_string())?;
+            if result.is_unit() {
+                Ok(Vec::new())
+            } else if let Some(events) = result.read_lock::<rhai::Array>() {
+                events.iter().map(|event| self.to_event(event)).collect()
+            } else {
+                Ok(vec![self.to_event(&result)?])
+            }
+        }
+
+        fn to_event(&self, value: &Dynamic) -> Result<Event, String> {
+            let script_event: ScriptEvent = rhai::serde::from_dynamic(value)
+                .map_err(|err| format!("invalid event returned: {}", err))?;
+            let event_type = match script_event.severity {
+                Some(severity) => EventType::QualitativeWarning { severity },
+                None => EventType::Informational,
+            };
+            Ok(Event {
+                event_type,
+                message: script_event.message,
+            })
+        }
+    }
+
+    impl Analyzer for ScriptAnalyzer {
+        fn get_id(&self) -> Cow<str> {
+            Cow::from(&self.config.id)
+        }
+
+        fn get_version(&self) -> u32 {
+            self.version
+        }
+
+        fn get_name(&self) -> Cow<str> {
+            Cow::from(&self.name)
+        }
+
+        fn get_description(&self) -> Cow<str> {
+            Cow::from(&self.description)
+        }
+
+        fn get_interests(&self) -> Interests {
+            match &self.config.channels {
+                Some(channels) => Interests::lte_channels(channels.iter().copied()),
+                None => Interests::All,
+            }
+        }
+
+        fn analyze_information_element(&mut self, ie: &InformationElement, context: &PacketContext) -> Option<Event> {
+            if self.consecutive_errors >= MAX_CONSECUTIVE_ERRORS {
+                return None;
+            }
+            let InformationElement::LTE(lte_ie) = ie else {
+                return None;
+            };
+            if let Some(channels) = &self.config.channels {
+                if !channels.contains(&lte_ie.channel()) {
+                    return None;
+                }
+            }
+            let args = rhai::serde::to_dynamic(lte_ie)
+                .and_then(|ie| Ok((ie, rhai::serde::to_dynamic(context)?)));
+            let result = match args {
+                Ok((ie, context)) => self.call_analyze(ie, context),
+                Err(err) => Err(err.to_string()),
+            };
+            match result {
+                Ok(event) => {
+                    self.consecutive_errors = 0;
+                    event
+                }
+                Err(err) => {
+                    self.consecutive_errors += 1;
+                    warn!("script {} failed: {}", self.config.id, err);
+                    if self.consecutive_errors == MAX_CONSECUTIVE_ERRORS {
+                        error!("script {} failed {} times in a row, disabling it", self.config.id, MAX_CONSECUTIVE_ERRORS);
+                    }
+                    None
+                }
+            }
+        }
+
+        fn on_recording_start(&mut self) {
+            self.consecutive_errors = 0;
+            match self.call_optional::<Dynamic>("init") {
+                Ok(state) => self.state = state.unwrap_or_else(|| Dynamic::from_map(Default::default())),
+                Err(err) => error!("script {} failed to reinitialize: {}", self.config.id, err),
+            }
+        }
+
+        fn on_recording_end(&mut self) -> Vec<Event> {
+            if self.consecutive_errors >= MAX_CONSECUTIVE_ERRORS {
+                return Vec::new();
+            }
+            self.call_on_recording_end().unwrap_or_else(|err| {
+                warn!("script {} failed: {}", self.config.id, err);
+                Vec::new()
+            })
+        }
+    }
+}
+
+#[cfg(all(test, feature = "scripting"))]
+mod tests {
+    use super::*;
+    use crate::analysis::test_util::context;
+    use crate::analysis::analyzer::{Analyzer, EventType};
+    use crate::analysis::information_element::{InformationElement, LteInformationElement};
+    use telcom_parser::lte_rrc::{PCCH_Message, PCCH_MessageType, PCCH_MessageType_c1, Paging};
+
+    fn script(source: &str) -> ScriptConfig {
+        ScriptConfig {
+            id: "test_script".to_string(),
+            path: PathBuf::from("test.rhai"),
+            enabled: true,
+            severity: None,
+            dedup_seconds: None,
+            channels: None,
+            limits: ScriptLimits::default(),
+            source: source.to_string(),
+        }
+    }
+
+    fn paging() -> InformationElement {
+        let message = PCCH_Message {
+            message: PCCH_MessageType::C1(PCCH_MessageType_c1::Paging(Paging {
+                paging_record_list: None,
+                system_info_modification: None,
+                etws_indication: None,
+                non_critical_extension: None,
+            })),
+        };
+        InformationElement::LTE(Box::new(LteInformationElement::PCCH(message)))
+    }
+
+    #[test]
+    fn test_stateful_script() {
+        let mut analyzer = ScriptAnalyzer::new(&script(r#"
+            fn init() { #{ count: 0 } }
+            fn name() { "Paging counter" }
+            fn version() { 3 }
+            fn analyze(ie, context) {
+                if "PCCH" in ie && context.direction == "Downlink" {
+                    this.count += 1;
+                }
+                if this.count == 2 {
+                    return #{ severity: "High", message: `seen ${this.count} pages` };
+                }
+            }
+            fn on_recording_end() {
+                [#{ message: `${this.count} pages total` }]
+            }
+        "#)).unwrap();
+        assert_eq!(analyzer.get_name(), "Paging counter");
+        assert_eq!(analyzer.get_version(), 3);
+        assert!(analyzer.analyze_information_element(&paging(), &context()).is_none());
+        let event = analyzer.analyze_information_element(&paging(), &context()).unwrap();
+        assert_eq!(event.message, "seen 2 pages");
+        assert!(matches!(event.event_type, EventType::QualitativeWarning { severity: Severity::High }));
+        assert!(analyzer.analyze_information_element(&paging(), &context()).is_none());
+        assert_eq!(analyzer.on_recording_end()[0].message, "3 pages total");
+        analyzer.on_recording_start();
+        assert_eq!(analyzer.on_recording_end()[0].message, "0 pages total");
+    }
+
+    #[test]
+    fn test_script_limits() {
+        assert!(matches!(
+            ScriptAnalyzer::new(&script("fn init() { #{} }")),
+            Err(ScriptError::MissingAnalyzeFunction(_))
+        ));
+        assert!(matches!(
+            ScriptAnalyzer::new(&script("fn analyze(ie, context) {")),
+            Err(ScriptError::CompileError(..))
+        ));
+        let mut analyzer = ScriptAnalyzer::new(&script("fn analyze(ie, context) { loop {} }")).unwrap();
+        assert!(analyzer.analyze_information_element(&paging(), &context()).is_none());
+
+        // each of these returns an event unless its limit is enforced
+        for source in [
+            r#"fn analyze(ie, context) { let s = ""; for i in 0..100 { s += "x"; } #{ message: s } }"#,
+            r#"fn analyze(ie, context) { let a = []; for i in 0..100 { a.push(i); } #{ message: `${a.len()}` } }"#,
+            r#"fn analyze(ie, context) { let m = #{}; for i in 0..100 { m[`${i}`] = i; } #{ message: `${m.len()}` } }"#,
+        ] {
+            let mut config = script(source);
+            assert!(ScriptAnalyzer::new(&config).unwrap().analyze_information_element(&paging(), &context()).is_some());
+            config.limits.max_string_size = 50;
+            config.limits.max_array_size = 50;
+            config.limits.max_map_size = 50;
+            let mut analyzer = ScriptAnalyzer::new(&config).unwrap();
+            assert!(analyzer.analyze_information_element(&paging(), &context()).is_none(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_severity_override() {
+        let mut config = script(r#"
+            fn analyze(ie, context) {
+                if "PCCH" in ie { #{ severity: "Low", message: "warning" } } else { #{ message: "info" } }
+            }
+        "#);
+        config.severity = Some(Severity::High);
+        config.channels = Some(vec![LteChannel::PCCH, LteChannel::NAS]);
+        // the override is left to the Harness, which only applies it to warnings
+        assert_eq!(config.options().severity_override, Some(Severity::High));
+        let mut analyzer = ScriptAnalyzer::new(&config).unwrap();
+        let event = analyzer.analyze_information_element(&paging(), &context()).unwrap();
+        assert_eq!(event.event_type, EventType::QualitativeWarning { severity: Severity::Low });
+        let nas = InformationElement::LTE(Box::new(LteInformationElement::NAS(vec![])));
+        let event = analyzer.analyze_information_element(&nas, &context()).unwrap();
+        assert_eq!(event.event_type, EventType::Informational);
+    }
+}
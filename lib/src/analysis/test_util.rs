@@ -0,0 +1,50 @@
+//! Fixtures shared between the analyzers' tests.
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use telcom_parser::decode;
+use telcom_parser::lte_rrc::{BCCH_DL_SCH_Message, BCCH_DL_SCH_MessageType, BCCH_DL_SCH_MessageType_c1, SystemInformationBlockType1};
+
+use super::context::{Direction, PacketContext, PhysicalCell};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::rrc_state::RrcConnectionState;
+
+// A SIB1 captured from a real cell
+const SIB1: &[u8] = &[
+    0x48, 0x4c, 0x46, 0x90, 0x10, 0x60, 0x00, 0x18, 0xfd, 0x1a, 0x92, 0x07, 0xe2, 0x21,
+    0x03, 0x10, 0x8a, 0xc2, 0x1b, 0xdc, 0x09, 0x80, 0x22, 0x92, 0xcd, 0xd2, 0x00, 0x00,
+];
+
+/// Midnight on 1 January 2024, plus `seconds`.
+pub fn time(seconds: i64) -> DateTime<FixedOffset> {
+    DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z").unwrap() + TimeDelta::seconds(seconds)
+}
+
+/// A downlink packet logged at [time(0)](time) with no known cell, index or
+/// RRC connection. Tests override the fields they care about.
+pub fn context() -> PacketContext {
+    PacketContext {
+        timestamp: time(0),
+        direction: Direction::Downlink,
+        cell: None,
+        message_index: 0,
+        rrc_state: RrcConnectionState::default(),
+    }
+}
+
+/// Like [context], but received on the given cell.
+pub fn context_on(earfcn: u32, phy_cell_id: u16) -> PacketContext {
+    PacketContext {
+        cell: Some(PhysicalCell { earfcn, phy_cell_id }),
+        ..context()
+    }
+}
+
+/// Decodes the captured SIB1, letting the test alter it first.
+pub fn sib1(change: impl FnOnce(&mut SystemInformationBlockType1)) -> InformationElement {
+    let mut message: BCCH_DL_SCH_Message = decode(SIB1).unwrap();
+    let BCCH_DL_SCH_MessageType::C1(BCCH_DL_SCH_MessageType_c1::SystemInformationBlockType1(sib1)) = &mut message.message else {
+        panic!("not a SIB1");
+    };
+    change(sib1);
+    InformationElement::LTE(Box::new(LteInformationElement::BcchDlSch(message)))
+}
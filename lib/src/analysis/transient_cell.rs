@@ -0,0 +1,226 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+
+use super::analyzer::{Analyzer, Event, EventType, Interests, Severity};
+use super::config::TransientCellParams;
+use super::context::{PacketContext, PhysicalCell};
+use super::information_element::{InformationElement, LteChannel};
+
+struct CellLifetime {
+    first_seen: DateTime<FixedOffset>,
+    last_seen: DateTime<FixedOffset>,
+    reported: bool,
+}
+
+impl CellLifetime {
+    fn lifetime(&self) -> TimeDelta {
+        self.last_seen - self.first_seen
+    }
+}
+
+/// Tracks how long each cell is visible for during a recording, using the PCI
+/// and EARFCN from the headers of the system information it broadcasts. Cells
+/// rebroadcast this regularly, and only listening to the broadcast channels
+/// spares us decoding every dedicated message. Mobile IMSI catchers tend to be
+/// switched on for a few minutes and then vanish, while real cells stay put,
+/// so a cell which comes and goes much faster than the rest of the
+/// environment is reported.
+///
+/// Cells are checked once they've been gone for `vanish_seconds`, at the end
+/// of every `window_seconds` of the recording and when it ends. Cells seen
+/// within `vanish_seconds` of the start or end of the recording may have been
+/// around for longer than we saw, so are never reported.
+pub struct TransientCellAnalyzer {
+    max_lifetime: TimeDelta,
+    max_fraction_of_median: f64,
+    min_cells: usize,
+    window: TimeDelta,
+    vanish: TimeDelta,
+    cells: HashMap<PhysicalCell, CellLifetime>,
+    recording_start: Option<DateTime<FixedOffset>>,
+    window_end: Option<DateTime<FixedOffset>>,
+    latest: Option<DateTime<FixedOffset>>,
+}
+
+impl TransientCellAnalyzer {
+    pub fn new(params: &TransientCellParams) -> Self {
+        Self {
+            max_lifetime: TimeDelta::seconds(params.max_lifetime_seconds.into()),
+            max_fraction_of_median: params.max_fraction_of_median,
+            min_cells: params.min_cells,
+            window: TimeDelta::seconds(params.window_seconds.into()),
+            vanish: TimeDelta::seconds(params.vanish_seconds.into()),
+            cells: HashMap::new(),
+            recording_start: None,
+            window_end: None,
+            latest: None,
+        }
+    }
+
+    // Reports the cells which vanished before `now` after an abnormally short
+    // lifetime, compared to every other cell seen so far
+    fn check_vanished_cells(&mut self, now: DateTime<FixedOffset>) -> Option<Event> {
+        let recording_start = self.recording_start?;
+        let mut lifetimes: Vec<TimeDelta> = self.cells.values().map(CellLifetime::lifetime).collect();
+        lifetimes.sort();
+
+        let mut transients: Vec<(&PhysicalCell, &mut CellLifetime)> = self.cells.iter_mut()
+            .filter(|(_, cell)| !cell.reported)
+            .filter(|(_, cell)| cell.first_seen - recording_start >= self.vanish && now - cell.last_seen >= self.vanish)
+            .filter(|(_, cell)| cell.lifetime() <= self.max_lifetime)
+            .collect();
+        // the rest of the environment needs to be big enough to compare against
+        if lifetimes.len() - transients.len() < self.min_cells {
+            return None;
+        }
+        let median = lifetimes[lifetimes.len() / 2];
+        let max_lifetime_seconds = median.num_seconds() as f64 * self.max_fraction_of_median;
+        transients.retain(|(_, cell)| (cell.lifetime().num_seconds() as f64) <= max_lifetime_seconds);
+        if transients.is_empty() {
+            return None;
+        }
+
+        transients.sort_by_key(|(_, cell)| cell.first_seen);
+        let descriptions: Vec<String> = transients.iter_mut()
+            .map(|(cell, lifetime)| {
+                lifetime.reported = true;
+                format!(
+                    "PCI {} on EARFCN {} for {} seconds from {}",
+                    cell.phy_cell_id, cell.earfcn, lifetime.lifetime().num_seconds(), lifetime.first_seen.format("%H:%M:%S"),
+                )
+            })
+            .collect();
+        Some(Event {
+            event_type: EventType::QualitativeWarning { severity: Severity::Low },
+            message: format!(
+                "Cells visible for abnormally short times: {} (the median of the {} cells seen is {} seconds)",
+                descriptions.join(", "), lifetimes.len(), median.num_seconds(),
+            ),
+        })
+    }
+}
+
+impl Analyzer for TransientCellAnalyzer {
+    fn get_id(&self) -> Cow<str> {
+        Cow::from("transient_cell")
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Transient Cell")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from("Tests whether a cell appears and vanishes again much more quickly than the other cells seen during the recording.")
+    }
+
+    fn get_interests(&self) -> Interests {
+        Interests::lte_channels([LteChannel::BcchBch, LteChannel::BcchDlSch])
+    }
+
+    fn analyze_information_element(&mut self, _ie: &InformationElement, context: &PacketContext) -> Option<Event> {
+        let cell = context.cell?;
+        let now = context.timestamp;
+        // diag timestamps can jump around slightly, so never go backwards
+        let now = self.latest.map_or(now, |latest| latest.max(now));
+        self.latest = Some(now);
+        self.recording_start.get_or_insert(now);
+        let lifetime = self.cells.entry(cell).or_insert(CellLifetime { first_seen: now, last_seen: now, reported: false });
+        lifetime.last_seen = now;
+
+        let window_end = *self.window_end.get_or_insert(now + self.window);
+        if now < window_end {
+            return None;
+        }
+        self.window_end = Some(now + self.window);
+        self.check_vanished_cells(now)
+    }
+
+    fn on_recording_start(&mut self) {
+        self.cells.clear();
+        self.recording_start = None;
+        self.window_end = None;
+        self.latest = None;
+    }
+
+    fn on_recording_end(&mut self) -> Vec<Event> {
+        let Some(latest) = self.latest else {
+            return Vec::new();
+        };
+        self.check_vanished_cells(latest).into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::test_util::{self, context_on, time};
+
+    fn context(phy_cell_id: u16, minutes: i64) -> PacketContext {
+        PacketContext { timestamp: time(minutes * 60), ..context_on(5230, phy_cell_id) }
+    }
+
+    fn params() -> TransientCellParams {
+        TransientCellParams {
+            max_lifetime_seconds: 600,
+            max_fraction_of_median: 0.1,
+            min_cells: 3,
+            window_seconds: 3600,
+            vanish_seconds: 300,
+        }
+    }
+
+    // any system information will do, since only the packet's cell matters
+    fn message() -> InformationElement {
+        test_util::sib1(|_| {})
+    }
+
+    #[test]
+    fn test_transient_cell() {
+        let mut analyzer = TransientCellAnalyzer::new(&params());
+        analyzer.on_recording_start();
+        for minutes in 0..=100 {
+            // three long-lived cells, and one which only shows up for 3 minutes
+            let pci = match minutes {
+                20..=23 => 9,
+                _ => (minutes % 3) as u16,
+            };
+            let event = analyzer.analyze_information_element(&message(), &context(pci, minutes));
+            if minutes == 60 {
+                let event = event.unwrap();
+                assert_eq!(event.event_type, EventType::QualitativeWarning { severity: Severity::Low });
+                assert_eq!(
+                    event.message,
+                    "Cells visible for abnormally short times: PCI 9 on EARFCN 5230 for 180 seconds from 00:20:00 (the median of the 4 cells seen is 3420 seconds)",
+                );
+            } else {
+                assert!(event.is_none());
+            }
+        }
+        // it's only reported once
+        assert!(analyzer.on_recording_end().is_empty());
+    }
+
+    #[test]
+    fn test_recording_end() {
+        let mut analyzer = TransientCellAnalyzer::new(&params());
+        analyzer.on_recording_start();
+        for minutes in 0..=50 {
+            let pci = match minutes {
+                30..=31 => 9,
+                // a cell which only appeared right at the end
+                49..=50 => 8,
+                _ => (minutes % 3) as u16,
+            };
+            assert!(analyzer.analyze_information_element(&message(), &context(pci, minutes)).is_none());
+        }
+        let events = analyzer.on_recording_end();
+        assert_eq!(events.len(), 1);
+        assert!(events[0].message.starts_with("Cells visible for abnormally short times: PCI 9 on EARFCN 5230 for 60 seconds"));
+    }
+}
@@ -0,0 +1,147 @@
+use std::borrow::Cow;
+
+use telcom_parser::lte_rrc::{DL_DCCH_MessageType, DL_DCCH_MessageType_c1, RAT_Type, UECapabilityEnquiry, UECapabilityEnquiryCriticalExtensions, UECapabilityEnquiryCriticalExtensions_c1};
+
+use super::analyzer::{Analyzer, Event, EventType, Interests, Severity};
+use super::context::PacketContext;
+use super::information_element::{InformationElement, LteChannel, LteInformationElement};
+use super::util::unpack;
+
+fn rat_type_name(rat_type: &RAT_Type) -> &'static str {
+    match rat_type.0 {
+        RAT_Type::EUTRA => "EUTRA",
+        RAT_Type::UTRA => "UTRA",
+        RAT_Type::GERAN_CS => "GERAN-CS",
+        RAT_Type::GERAN_PS => "GERAN-PS",
+        RAT_Type::CDMA2000_1XRTT => "CDMA2000-1XRTT",
+        RAT_Type::NR => "NR",
+        RAT_Type::EUTRA_NR => "EUTRA-NR",
+        _ => "unknown",
+    }
+}
+
+fn requested_rat_types(enquiry: &UECapabilityEnquiry) -> Option<&[RAT_Type]> {
+    unpack!(UECapabilityEnquiryCriticalExtensions::C1(c1) = &enquiry.critical_extensions);
+    unpack!(UECapabilityEnquiryCriticalExtensions_c1::UeCapabilityEnquiry_r8(r8_ies) = c1);
+    Some(&r8_ies.ue_capability_request.0)
+}
+
+/// IMSI catchers often fingerprint a phone by asking for its capabilities
+/// before bothering to set up AS security, which a real network would usually
+/// do first. Asking for 2G/3G capabilities suggests the cell is preparing to
+/// downgrade the connection.
+#[derive(Default)]
+pub struct UeCapabilityEnquiryAnalyzer {
+}
+
+impl Analyzer for UeCapabilityEnquiryAnalyzer {
+    fn get_id(&self) -> Cow<str> {
+        Cow::from("ue_capability_enquiry_before_security")
+    }
+
+    fn get_version(&self) -> u32 {
+        2
+    }
+
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("UE Capability Enquiry Before Security")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from("Tests whether the cell asks for the UE's capabilities before activating AS security")
+    }
+
+    fn get_interests(&self) -> Interests {
+        Interests::lte_channels([LteChannel::DlDcch])
+    }
+
+    fn analyze_information_element(&mut self, ie: &InformationElement, context: &PacketContext) -> Option<Event> {
+        unpack!(InformationElement::LTE(lte_ie) = ie);
+        unpack!(LteInformationElement::DlDcch(dcch_msg) = &**lte_ie);
+        unpack!(DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::UeCapabilityEnquiry(enquiry)) = &dcch_msg.message);
+        if !context.rrc_state.is_connected_without_security() {
+            return None;
+        }
+        let rat_types = requested_rat_types(enquiry)?;
+        let downgrade = rat_types.iter()
+            .any(|rat_type| matches!(rat_type.0, RAT_Type::UTRA | RAT_Type::GERAN_CS | RAT_Type::GERAN_PS));
+        let names: Vec<&str> = rat_types.iter().map(rat_type_name).collect();
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: if downgrade { Severity::Medium } else { Severity::Low },
+            },
+            message: format!("UE capabilities for {} were requested before AS security was activated", names.join(", ")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::test_util;
+    use crate::analysis::rrc_state::RrcStateTracker;
+    use telcom_parser::lte_rrc::{DL_CCCH_Message, DL_CCCH_MessageType, DL_CCCH_MessageType_c1, DL_DCCH_Message, RRCConnectionSetup, RRCConnectionSetupCriticalExtensions, RRCConnectionSetupCriticalExtensions_criticalExtensionsFuture, RRC_TransactionIdentifier, SecurityModeCommand, SecurityModeCommandCriticalExtensions, SecurityModeCommandCriticalExtensions_criticalExtensionsFuture, UECapabilityEnquiry_r8_IEs, UE_CapabilityRequest};
+
+    fn connection_setup() -> InformationElement {
+        let message = DL_CCCH_Message {
+            message: DL_CCCH_MessageType::C1(DL_CCCH_MessageType_c1::RrcConnectionSetup(RRCConnectionSetup {
+                rrc_transaction_identifier: RRC_TransactionIdentifier(0),
+                critical_extensions: RRCConnectionSetupCriticalExtensions::CriticalExtensionsFuture(
+                    RRCConnectionSetupCriticalExtensions_criticalExtensionsFuture {}
+                ),
+            })),
+        };
+        InformationElement::LTE(Box::new(LteInformationElement::DlCcch(message)))
+    }
+
+    fn dl_dcch(c1: DL_DCCH_MessageType_c1) -> InformationElement {
+        let message = DL_DCCH_Message { message: DL_DCCH_MessageType::C1(c1) };
+        InformationElement::LTE(Box::new(LteInformationElement::DlDcch(Box::new(message))))
+    }
+
+    fn security_mode_command() -> InformationElement {
+        dl_dcch(DL_DCCH_MessageType_c1::SecurityModeCommand(SecurityModeCommand {
+            rrc_transaction_identifier: RRC_TransactionIdentifier(0),
+            critical_extensions: SecurityModeCommandCriticalExtensions::CriticalExtensionsFuture(
+                SecurityModeCommandCriticalExtensions_criticalExtensionsFuture {}
+            ),
+        }))
+    }
+
+    fn capability_enquiry(rat_types: &[u8]) -> InformationElement {
+        dl_dcch(DL_DCCH_MessageType_c1::UeCapabilityEnquiry(UECapabilityEnquiry {
+            rrc_transaction_identifier: RRC_TransactionIdentifier(0),
+            critical_extensions: UECapabilityEnquiryCriticalExtensions::C1(
+                UECapabilityEnquiryCriticalExtensions_c1::UeCapabilityEnquiry_r8(UECapabilityEnquiry_r8_IEs {
+                    ue_capability_request: UE_CapabilityRequest(rat_types.iter().map(|rat_type| RAT_Type(*rat_type)).collect()),
+                    non_critical_extension: None,
+                })
+            ),
+        }))
+    }
+
+    fn context(tracker: &RrcStateTracker) -> PacketContext {
+        PacketContext { rrc_state: tracker.state(), ..test_util::context() }
+    }
+
+    #[test]
+    fn test_capability_enquiry_before_security() {
+        let mut analyzer = UeCapabilityEnquiryAnalyzer::default();
+        let mut tracker = RrcStateTracker::default();
+        let enquiry = capability_enquiry(&[RAT_Type::EUTRA, RAT_Type::GERAN_CS]);
+        // we don't know whether security was activated before the recording
+        assert!(analyzer.analyze_information_element(&enquiry, &context(&tracker)).is_none());
+
+        tracker.observe(&connection_setup());
+        let event = analyzer.analyze_information_element(&enquiry, &context(&tracker)).unwrap();
+        assert_eq!(event.event_type, EventType::QualitativeWarning { severity: Severity::Medium });
+        assert!(event.message.contains("EUTRA, GERAN-CS"));
+        let event = analyzer.analyze_information_element(&capability_enquiry(&[RAT_Type::EUTRA]), &context(&tracker)).unwrap();
+        assert_eq!(event.event_type, EventType::QualitativeWarning { severity: Severity::Low });
+
+        tracker.observe(&security_mode_command());
+        assert!(analyzer.analyze_information_element(&enquiry, &context(&tracker)).is_none());
+        tracker.reset();
+        assert!(analyzer.analyze_information_element(&enquiry, &context(&tracker)).is_none());
+    }
+}
@@ -1,3 +1,7 @@
+use telcom_parser::lte_rrc::{BCCH_DL_SCH_MessageType, BCCH_DL_SCH_MessageType_c1, SystemInformationCriticalExtensions, SystemInformation_r8_IEsSib_TypeAndInfo_Entry};
+
+use super::information_element::{InformationElement, LteInformationElement};
+
 
 // Unpacks a pattern, or returns None.
 //
@@ -30,3 +34,18 @@ macro_rules! unpack {
 
 // this is apparently how you make a macro publicly usable from this module
 pub(crate) use unpack;
+
+/// Returns the SIBs carried in a SystemInformation message, or `None` for any
+/// other message. SIB1 is sent in its own message type, so it's never included.
+pub(crate) fn system_information_blocks(ie: &InformationElement) -> Option<&[SystemInformation_r8_IEsSib_TypeAndInfo_Entry]> {
+    unpack!(InformationElement::LTE(lte_ie) = ie);
+    unpack!(LteInformationElement::BcchDlSch(bcch_dl_sch_message) = &**lte_ie);
+    unpack!(BCCH_DL_SCH_MessageType::C1(BCCH_DL_SCH_MessageType_c1::SystemInformation(system_information)) = &bcch_dl_sch_message.message);
+    unpack!(SystemInformationCriticalExtensions::SystemInformation_r8(sib) = &system_information.critical_extensions);
+    Some(&sib.sib_type_and_info.0)
+}
+
+/// Reads an ASN.1 BIT STRING, most significant bit first, as an integer.
+pub(crate) fn bits_to_u32(bits: impl Iterator<Item = bool>) -> u32 {
+    bits.fold(0, |acc, bit| (acc << 1) | bit as u32)
+}
@@ -1,4 +1,4 @@
-use rayhunter::{diag::{
+use rayhunter::{analysis::context::PhysicalCell, diag::{
     LogBody, LteRrcOtaPacket, Message, Timestamp
 }, gsmtap_parser};
 use deku::prelude::*;
@@ -37,6 +37,10 @@ fn test_lte_rrc_ota() {
             }
         }
     });
+    assert_eq!(PhysicalCell::from_message(&parsed), Some(PhysicalCell {
+        earfcn: 1811,
+        phy_cell_id: 270,
+    }));
     let (_, gsmtap_msg) = gsmtap_parser::parse(parsed).unwrap().unwrap();
     assert_eq!(&gsmtap_msg.payload, &[0x10, 0x15]);
     assert_eq!(gsmtap_msg.header.packet_type, 13);
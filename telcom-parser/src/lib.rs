@@ -2,6 +2,7 @@ use asn1_codecs::{uper::UperCodec, PerCodecData, PerCodecError};
 use thiserror::Error;
 #[allow(warnings, unused, unreachable_patterns, non_camel_case_types)]
 pub mod lte_rrc;
+mod lte_rrc_extensions;
 
 #[derive(Error, Debug)]
 pub enum ParsingError {
@@ -13557,8 +13557,9 @@ impl ReleaseCause {
     pub const RRC_SUSPEND_V1320: u8 = 3u8;
 }
 
-#[derive(asn1_codecs_derive :: UperCodec, Clone, PartialEq, serde :: Serialize, Debug)]
-#[asn(type = "SEQUENCE", extensible = true)]
+// UperCodec is implemented by hand in lte_rrc_extensions.rs, to decode the
+// includeLocationInfo-r10 extension addition
+#[derive(Clone, PartialEq, serde :: Serialize, Debug)]
 pub struct ReportConfigEUTRA {
     pub trigger_type: ReportConfigEUTRATriggerType,
     pub trigger_quantity: ReportConfigEUTRATriggerQuantity,
@@ -13566,6 +13567,7 @@ pub struct ReportConfigEUTRA {
     pub max_report_cells: ReportConfigEUTRAMaxReportCells,
     pub report_interval: ReportInterval,
     pub report_amount: ReportConfigEUTRAReportAmount,
+    pub include_location_info_r10: Option<ReportConfigEUTRAIncludeLocationInfo_r10>,
 }
 
 #[derive(asn1_codecs_derive :: UperCodec, Clone, PartialEq, serde :: Serialize, Debug)]
@@ -41683,6 +41685,13 @@ impl ReportConfigEUTRAReportQuantity {
 #[asn(type = "INTEGER", lb = "1", ub = "8")]
 pub struct ReportConfigEUTRAMaxReportCells(pub u8);
 
+#[derive(asn1_codecs_derive :: UperCodec, Clone, PartialEq, serde :: Serialize, Debug)]
+#[asn(type = "ENUMERATED", lb = "0", ub = "0")]
+pub struct ReportConfigEUTRAIncludeLocationInfo_r10(pub u8);
+impl ReportConfigEUTRAIncludeLocationInfo_r10 {
+    pub const TRUE: u8 = 0u8;
+}
+
 #[derive(asn1_codecs_derive :: UperCodec, Clone, PartialEq, serde :: Serialize, Debug)]
 #[asn(type = "ENUMERATED", lb = "0", ub = "7")]
 pub struct ReportConfigEUTRAReportAmount(pub u8);
@@ -0,0 +1,172 @@
+//! Hand-written codecs for LTE RRC types whose extension additions we need to
+//! read. hampi doesn't generate fields for extension additions, and the codecs
+//! it derives don't skip over them, so these have to be kept in sync with the
+//! generated types in [lte_rrc](crate::lte_rrc) when it's regenerated.
+
+use asn1_codecs::uper::{decode, encode, UperCodec};
+use asn1_codecs::{PerCodecData, PerCodecError};
+use bitvec::prelude::*;
+
+use crate::lte_rrc::{
+    ReportConfigEUTRA, ReportConfigEUTRAIncludeLocationInfo_r10, ReportConfigEUTRAMaxReportCells,
+    ReportConfigEUTRAReportAmount, ReportConfigEUTRAReportQuantity, ReportConfigEUTRATriggerQuantity,
+    ReportConfigEUTRATriggerType, ReportInterval,
+};
+
+// The index of the extension addition group holding includeLocationInfo-r10
+// and reportAddNeighMeas-r10, after the r9 group
+const REPORT_CONFIG_EUTRA_R10_GROUP: usize = 1;
+
+// Reads the bitmap of which extension addition groups are present
+fn decode_extension_bitmap(data: &mut PerCodecData) -> Result<BitVec<u8, Msb0>, PerCodecError> {
+    let count = decode::decode_length_determinent(data, None, None, true)?;
+    decode::decode_bitstring(data, Some(count as i128), Some(count as i128), false)
+}
+
+// Each extension addition group is encoded as an open type, which is the
+// group's own encoding preceded by its length in octets
+fn decode_open_type(data: &mut PerCodecData) -> Result<PerCodecData, PerCodecError> {
+    let octets = decode::decode_octetstring(data, None, None, false)?;
+    Ok(PerCodecData::from_slice_uper(&octets))
+}
+
+fn encode_open_type(data: &mut PerCodecData, group: PerCodecData) -> Result<(), PerCodecError> {
+    encode::encode_octetstring(data, None, None, false, &group.into_bytes(), false)
+}
+
+impl UperCodec for ReportConfigEUTRA {
+    type Output = Self;
+
+    fn uper_decode(data: &mut PerCodecData) -> Result<Self::Output, PerCodecError> {
+        log::trace!("decode: ReportConfigEUTRA");
+
+        let (_, extended) = decode::decode_sequence_header(data, true, 0)?;
+        let mut report_config = ReportConfigEUTRA {
+            trigger_type: ReportConfigEUTRATriggerType::uper_decode(data)?,
+            trigger_quantity: ReportConfigEUTRATriggerQuantity::uper_decode(data)?,
+            report_quantity: ReportConfigEUTRAReportQuantity::uper_decode(data)?,
+            max_report_cells: ReportConfigEUTRAMaxReportCells::uper_decode(data)?,
+            report_interval: ReportInterval::uper_decode(data)?,
+            report_amount: ReportConfigEUTRAReportAmount::uper_decode(data)?,
+            include_location_info_r10: None,
+        };
+        if !extended {
+            return Ok(report_config);
+        }
+        let groups = decode_extension_bitmap(data)?;
+        for (index, present) in groups.iter().by_vals().enumerate() {
+            if !present {
+                continue;
+            }
+            // groups we don't decode still have to be read past
+            let mut group = decode_open_type(data)?;
+            if index == REPORT_CONFIG_EUTRA_R10_GROUP {
+                let (optionals, _) = decode::decode_sequence_header(&mut group, false, 2)?;
+                if optionals[0] {
+                    report_config.include_location_info_r10 =
+                        Some(ReportConfigEUTRAIncludeLocationInfo_r10::uper_decode(&mut group)?);
+                }
+            }
+        }
+        Ok(report_config)
+    }
+
+    fn uper_encode(&self, data: &mut PerCodecData) -> Result<(), PerCodecError> {
+        log::trace!("encode: ReportConfigEUTRA");
+
+        // encode_sequence_header refuses to encode extended sequences, but
+        // with no optional root fields the header is just the extension bit
+        encode::encode_bool(data, self.include_location_info_r10.is_some())?;
+        self.trigger_type.uper_encode(data)?;
+        self.trigger_quantity.uper_encode(data)?;
+        self.report_quantity.uper_encode(data)?;
+        self.max_report_cells.uper_encode(data)?;
+        self.report_interval.uper_encode(data)?;
+        self.report_amount.uper_encode(data)?;
+        let Some(include_location_info) = &self.include_location_info_r10 else {
+            return Ok(());
+        };
+        let mut groups = bitvec![u8, Msb0; 0; REPORT_CONFIG_EUTRA_R10_GROUP + 1];
+        groups.set(REPORT_CONFIG_EUTRA_R10_GROUP, true);
+        encode::encode_length_determinent(data, None, None, true, groups.len())?;
+        encode::encode_bitstring(data, Some(groups.len() as i128), Some(groups.len() as i128), false, &groups, false)?;
+        let mut group = PerCodecData::new_uper();
+        encode::encode_sequence_header(&mut group, false, bits![u8, Msb0; 1, 0], false)?;
+        include_location_info.uper_encode(&mut group)?;
+        encode_open_type(data, group)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode;
+    use crate::lte_rrc::{ReportConfigEUTRATriggerType_periodical, ReportConfigEUTRATriggerType_periodicalPurpose};
+
+    fn report_config(include_location_info_r10: Option<ReportConfigEUTRAIncludeLocationInfo_r10>) -> ReportConfigEUTRA {
+        ReportConfigEUTRA {
+            trigger_type: ReportConfigEUTRATriggerType::Periodical(ReportConfigEUTRATriggerType_periodical {
+                purpose: ReportConfigEUTRATriggerType_periodicalPurpose(0),
+            }),
+            trigger_quantity: ReportConfigEUTRATriggerQuantity(0),
+            report_quantity: ReportConfigEUTRAReportQuantity(1),
+            max_report_cells: ReportConfigEUTRAMaxReportCells(4),
+            report_interval: ReportInterval(5),
+            report_amount: ReportConfigEUTRAReportAmount(ReportConfigEUTRAReportAmount::INFINITY),
+            include_location_info_r10,
+        }
+    }
+
+    #[test]
+    fn test_include_location_info() {
+        // the root fields, then a bitmap of two extension groups with only
+        // the r10 group present, and that group's open type with only
+        // includeLocationInfo-r10 set
+        let report_config: ReportConfigEUTRA = decode(&[0xcb, 0x5e, 0x05, 0x01, 0x80]).unwrap();
+        assert_eq!(report_config.include_location_info_r10, Some(ReportConfigEUTRAIncludeLocationInfo_r10(0)));
+        assert_eq!(report_config.report_interval, ReportInterval(5));
+    }
+
+    #[test]
+    fn test_include_location_info_round_trip() {
+        let report_configs = [
+            report_config(Some(ReportConfigEUTRAIncludeLocationInfo_r10(0))),
+            report_config(None),
+        ];
+        let mut data = PerCodecData::new_uper();
+        for report_config in &report_configs {
+            report_config.uper_encode(&mut data).unwrap();
+        }
+        // the second one is only read correctly if the first one's
+        // extensions were read past
+        let mut data = PerCodecData::from_slice_uper(&data.into_bytes());
+        for report_config in &report_configs {
+            assert_eq!(&ReportConfigEUTRA::uper_decode(&mut data).unwrap(), report_config);
+        }
+    }
+
+    #[test]
+    fn test_skips_other_extension_groups() {
+        // the root fields, then the r9 group with si-RequestForHO-r9 set and
+        // the r10 group with only reportAddNeighMeas-r10 set
+        let mut data = PerCodecData::new_uper();
+        encode::encode_bool(&mut data, true).unwrap();
+        let root = report_config(None);
+        root.trigger_type.uper_encode(&mut data).unwrap();
+        root.trigger_quantity.uper_encode(&mut data).unwrap();
+        root.report_quantity.uper_encode(&mut data).unwrap();
+        root.max_report_cells.uper_encode(&mut data).unwrap();
+        root.report_interval.uper_encode(&mut data).unwrap();
+        root.report_amount.uper_encode(&mut data).unwrap();
+        encode::encode_length_determinent(&mut data, None, None, true, 2).unwrap();
+        encode::encode_bitstring(&mut data, Some(2), Some(2), false, bits![u8, Msb0; 1, 1], false).unwrap();
+        for optionals in [bits![u8, Msb0; 1, 0], bits![u8, Msb0; 0, 1]] {
+            let mut group = PerCodecData::new_uper();
+            encode::encode_sequence_header(&mut group, false, optionals, false).unwrap();
+            encode_open_type(&mut data, group).unwrap();
+        }
+
+        let decoded: ReportConfigEUTRA = decode(&data.into_bytes()).unwrap();
+        assert_eq!(decoded, root);
+    }
+}